@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::Manifest;
+
+/// `[aliases]` section: renames tags, correspondents and document types before anything else
+/// sees them, e.g. `tags = { Rechnung = "Invoice" }`. Mapping several names to the same one
+/// merges them into a single folder.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Aliases {
+    pub tags: HashMap<String, String>,
+    pub correspondents: HashMap<String, String>,
+    pub document_types: HashMap<String, String>,
+}
+
+impl Aliases {
+    /// Renames the aliased objects of `manifest` and their references in its documents.
+    pub fn apply(&self, manifest: &mut Manifest) {
+        let rename = |aliases: &HashMap<String, String>, name: &mut String| {
+            if let Some(alias) = aliases.get(name.as_str()) {
+                name.clone_from(alias);
+            }
+        };
+        for tag in manifest.tags.values_mut() {
+            rename(&self.tags, &mut tag.name);
+        }
+        for correspondent in manifest.correspondents.values_mut() {
+            rename(&self.correspondents, &mut correspondent.name);
+        }
+        for document_type in manifest.document_types.values_mut() {
+            rename(&self.document_types, &mut document_type.name);
+        }
+        for doc in &mut manifest.documents {
+            for tag in &mut doc.tags {
+                rename(&self.tags, &mut tag.name);
+            }
+            // a document tagged with two merged tags ends up in their folder once
+            let mut seen = Vec::new();
+            doc.tags.retain(|tag| {
+                let new = !seen.contains(&tag.name);
+                seen.push(tag.name.clone());
+                new
+            });
+            if let Some(correspondent) = &mut doc.correspondent {
+                rename(&self.correspondents, &mut correspondent.name);
+            }
+            if let Some(document_type) = &mut doc.document_type {
+                rename(&self.document_types, &mut document_type.name);
+            }
+        }
+    }
+}
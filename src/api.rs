@@ -0,0 +1,373 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{trace, warn};
+
+use crate::{
+    manifest::{self, ManifestBuilder},
+    version::parse_timestamp,
+    Error, Manifest,
+};
+
+/// Page size requested from list endpoints; paperless caps it at 100000.
+const PAGE_SIZE: usize = 1000;
+
+/// File in the output directory caching the documents of the last incremental API sync.
+pub const SYNC_STATE_FILE: &str = ".paperless-api-sync.json";
+
+/// Documents as of the last sync, so the next one only has to fetch what was modified since.
+#[derive(Default, Serialize, Deserialize)]
+struct SyncState {
+    /// Latest `modified` timestamp among the cached documents
+    synced_until: Option<DateTime<Utc>>,
+    documents: BTreeMap<i64, Value>,
+}
+
+/// Reads documents straight from a running paperless-ngx instance instead of an export.
+///
+/// API objects are translated into their exporter equivalents, so documents get exporter
+/// style names (`0000042.png`, `archive/0000042.pdf`) which [`ApiClient::read`] maps back to
+/// download URLs.
+pub struct ApiClient {
+    url: String,
+    display: PathBuf,
+    token: Option<String>,
+    agent: ureq::Agent,
+    /// `modified` timestamps of the fetched documents, by pk
+    modified: Mutex<HashMap<i64, SystemTime>>,
+    /// Where to cache documents between incremental syncs
+    state_file: Option<PathBuf>,
+}
+
+impl ApiClient {
+    /// A client for the paperless instance at `url`, authenticating with an API `token`.
+    pub fn new(url: &str, token: Option<String>) -> Self {
+        let url = url.trim_end_matches('/').to_owned();
+        Self {
+            display: PathBuf::from(&url),
+            url,
+            token,
+            agent: ureq::Agent::new_with_defaults(),
+            modified: Mutex::default(),
+            state_file: None,
+        }
+    }
+
+    /// Caches the fetched documents in `path`, so later syncs only fetch the documents
+    /// modified since and the ids of all others.
+    pub fn with_state_file(mut self, path: PathBuf) -> Self {
+        self.state_file = Some(path);
+        self
+    }
+
+    /// The instance's base URL.
+    pub fn url(&self) -> &Path {
+        &self.display
+    }
+
+    fn get(&self, url: &str) -> Result<ureq::http::Response<ureq::Body>, Error> {
+        trace!("GET {url}");
+        let mut request = self.agent.get(url).header("Accept", "application/json");
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Token {token}"));
+        }
+        request.call().map_err(|error| Error::Http {
+            url: url.to_owned(),
+            error: Box::new(error),
+        })
+    }
+
+    /// All results of the paginated list `endpoint`, e.g. `tags`, with `filter` (`&key=value`
+    /// pairs) appended to the query.
+    fn list(&self, endpoint: &str, filter: &str) -> Result<Vec<Value>, Error> {
+        let mut results = Vec::new();
+        let mut next = Some(format!(
+            "{}/api/{endpoint}/?page_size={PAGE_SIZE}{filter}",
+            self.url
+        ));
+        while let Some(url) = next {
+            let page: Value = self
+                .get(&url)?
+                .body_mut()
+                .with_config()
+                .limit(u64::MAX)
+                .read_json()
+                .map_err(|error| Error::Http {
+                    url: url.clone(),
+                    error: Box::new(error),
+                })?;
+            next = page["next"].as_str().map(str::to_owned);
+            if let Value::Array(page) = &page["results"] {
+                results.extend(page.iter().cloned());
+            }
+        }
+        Ok(results)
+    }
+
+    /// All objects of the list `endpoint`, or none if the instance refuses (403) or doesn't
+    /// know (404) it; any other error is returned.
+    fn list_optional(&self, endpoint: &str) -> Result<Vec<Value>, Error> {
+        match self.list(endpoint, "") {
+            Err(Error::Http { url, error })
+                if matches!(*error, ureq::Error::StatusCode(403 | 404)) =>
+            {
+                warn!("skipping {endpoint}: {}", Error::Http { url, error });
+                Ok(Vec::new())
+            }
+            result => result,
+        }
+    }
+
+    /// Fetches tags, correspondents, document types, storage paths, users, custom fields and
+    /// documents, leaving out malformed ones unless `strict`.
+    pub fn fetch_manifest(&self, strict: bool) -> Result<Manifest, Error> {
+        let mut builder = ManifestBuilder::new(strict, None);
+        let mut index = 0;
+        let mut add = |value| {
+            builder.add_value(index, value);
+            index += 1;
+        };
+
+        for (endpoint, model) in [
+            ("tags", "documents.tag"),
+            ("correspondents", "documents.correspondent"),
+            ("document_types", "documents.documenttype"),
+        ] {
+            for object in self.list(endpoint, "")? {
+                add(json!({
+                    "model": model,
+                    "pk": object["id"],
+                    "fields": { "name": object["name"] },
+                }));
+            }
+        }
+        for object in self.list("storage_paths", "")? {
+            add(json!({
+                "model": "documents.storagepath",
+                "pk": object["id"],
+                "fields": { "name": object["name"], "path": object["path"] },
+            }));
+        }
+
+        // listing users needs admin permissions, without them documents just lack an owner
+        let users = self.list_optional("users")?;
+        for user in &users {
+            add(json!({
+                "model": "auth.user",
+                "pk": user["id"],
+                "fields": { "username": user["username"] },
+            }));
+        }
+
+        // custom fields were added in paperless 2.0, older versions don't know the endpoint
+        let custom_fields = self.list_optional("custom_fields")?;
+        let mut value_keys = HashMap::new();
+        for field in &custom_fields {
+            if let Some(key) = field["data_type"]
+                .as_str()
+                .and_then(manifest::custom_field_value_key)
+            {
+                value_keys.insert(field["id"].as_i64().unwrap_or_default(), key);
+            }
+            add(json!({
+                "model": "documents.customfield",
+                "pk": field["id"],
+                "fields": {
+                    "name": field["name"],
+                    "data_type": field["data_type"],
+                    "extra_data": field["extra_data"],
+                },
+            }));
+        }
+
+        let mut modified = HashMap::new();
+        for document in self.fetch_documents()? {
+            let pk = document["id"].as_i64().unwrap_or_default();
+            if let Some(time) = document["modified"].as_str().and_then(parse_timestamp) {
+                modified.insert(pk, time.into());
+            }
+            if let Value::Array(notes) = &document["notes"] {
+                for note in notes {
+                    add(json!({
+                        "model": "documents.note",
+                        "pk": note["id"],
+                        "fields": {
+                            "document": pk,
+                            "note": note["note"],
+                            "created": note["created"],
+                        },
+                    }));
+                }
+            }
+            if let Value::Array(instances) = &document["custom_fields"] {
+                for instance in instances {
+                    let field = instance["field"].as_i64().unwrap_or_default();
+                    let Some(&key) = value_keys.get(&field) else {
+                        continue;
+                    };
+                    add(json!({
+                        "model": "documents.customfieldinstance",
+                        "pk": 0,
+                        "fields": { "document": pk, "field": field, key: instance["value"] },
+                    }));
+                }
+            }
+            let mut object = document_object(pk, &document);
+            if users.is_empty() {
+                object["fields"]["owner"] = Value::Null;
+            }
+            add(object);
+        }
+        *self.modified.lock().unwrap_or_else(|e| e.into_inner()) = modified;
+
+        builder.finish()
+    }
+
+    /// All documents, from the state file and the API if there is one.
+    fn fetch_documents(&self) -> Result<Vec<Value>, Error> {
+        let Some(path) = &self.state_file else {
+            return self.list("documents", "");
+        };
+        let mut state: SyncState = fs::read(path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+
+        let id = |document: &Value| document["id"].as_i64().unwrap_or_default();
+        let fetched = match state.synced_until {
+            Some(since) => {
+                let ids: HashSet<_> = self
+                    .list("documents", "&fields=id")?
+                    .iter()
+                    .map(id)
+                    .collect();
+                state.documents.retain(|pk, _| ids.contains(pk));
+                let mut fetched = self.list(
+                    "documents",
+                    &format!("&modified__gt={}", since.format("%Y-%m-%dT%H:%M:%S%.fZ")),
+                )?;
+                // e.g. restored from the trash without touching `modified`
+                let missing: Vec<_> = ids
+                    .iter()
+                    .filter(|pk| !state.documents.contains_key(pk))
+                    .map(i64::to_string)
+                    .collect();
+                for chunk in missing.chunks(100) {
+                    fetched
+                        .extend(self.list("documents", &format!("&id__in={}", chunk.join(",")))?);
+                }
+                fetched
+            }
+            None => self.list("documents", "")?,
+        };
+        for document in fetched {
+            state.documents.insert(id(&document), document);
+        }
+        state.synced_until = state
+            .documents
+            .values()
+            .filter_map(|d| d["modified"].as_str().and_then(parse_timestamp))
+            .max();
+
+        let json = serde_json::to_vec(&state).expect("serializable");
+        fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))
+            .and_then(|()| fs::write(path, json))
+            .map_err(|error| Error::Write {
+                path: path.clone(),
+                error,
+            })?;
+        Ok(state.documents.into_values().collect())
+    }
+
+    /// Modification time of the document behind the exporter style `name`.
+    pub fn modified(&self, name: &str) -> Option<SystemTime> {
+        let (pk, _) = parse_name(name)?;
+        let modified = self.modified.lock().unwrap_or_else(|e| e.into_inner());
+        modified.get(&pk).copied()
+    }
+
+    /// Calls `f` with the contents of the document file behind the exporter style `name`.
+    pub fn read<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut dyn Read) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let Some((pk, archive)) = parse_name(name) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("`{name}` is not a document file"),
+            ));
+        };
+        let url = format!(
+            "{}/api/documents/{pk}/download/{}",
+            self.url,
+            if archive { "" } else { "?original=true" }
+        );
+        let response = self.get(&url).map_err(io::Error::other)?;
+        f(&mut response.into_body().into_reader())
+    }
+}
+
+/// The exporter style `documents.document` object of an API document.
+fn document_object(pk: i64, document: &Value) -> Value {
+    let extension = |name: &Value| {
+        name.as_str()
+            .and_then(|n| Path::new(n).extension())
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default()
+    };
+    let archive_name = document["archived_file_name"].as_str().map(|_| {
+        format!(
+            "archive/{pk:07}{}",
+            extension(&document["archived_file_name"])
+        )
+    });
+
+    // timestamps are made RFC 3339 like those of exports, see `upgrade_object`
+    let mut object = json!({
+        "model": "documents.document",
+        "pk": pk,
+        "fields": {
+            "title": document["title"],
+            "content": document["content"],
+            "created": document["created"],
+            "added": document["added"],
+            "modified": document["modified"],
+            "correspondent": document["correspondent"],
+            "document_type": document["document_type"],
+            "storage_path": document["storage_path"],
+            "tags": document["tags"],
+            "archive_serial_number": document["archive_serial_number"],
+            "mime_type": document["mime_type"],
+            "owner": document["owner"],
+            // checksums are only available per document from the metadata endpoint
+            "checksum": "",
+        },
+        "__exported_file_name__": format!("{pk:07}{}", extension(&document["original_file_name"])),
+        "__exported_archive_name__": archive_name,
+    });
+    // up to paperless-ngx 2.15, the day paperless shows, next to the `created` timestamp
+    if let Some(date) = document["created_date"].as_str() {
+        object["fields"]["created_date"] = date.into();
+    }
+    object
+}
+
+/// Pk and whether it's the archive version, from an exporter style name.
+fn parse_name(name: &str) -> Option<(i64, bool)> {
+    let (archive, name) = match name.strip_prefix("archive/") {
+        Some(name) => (true, name),
+        None => (false, name),
+    };
+    let stem = Path::new(name).file_stem()?.to_str()?;
+    Some((stem.parse().ok()?, archive))
+}
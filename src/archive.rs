@@ -0,0 +1,384 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use chrono::{Datelike, Timelike};
+use flate2::{write::GzEncoder, Compression};
+use serde::Serialize;
+use zip::{write::SimpleFileOptions, DateTime, ZipWriter};
+
+use crate::{
+    backend::{Entry, OutputBackend},
+    organize::{FileNames, OrganizeOptions, View},
+    sync, Document,
+};
+
+/// Name of the metadata index at the root of every archive.
+pub const ARCHIVE_INDEX: &str = "index.json";
+
+/// Container format of the per-folder archives.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+pub enum ArchiveFormat {
+    #[serde(rename = "zip")]
+    #[value(name = "zip")]
+    Zip,
+    /// Gzip compressed tarball
+    #[serde(rename = "tar.gz")]
+    #[value(name = "tar.gz")]
+    TarGz,
+    /// Zstandard compressed tarball
+    #[serde(rename = "tar.zst")]
+    #[value(name = "tar.zst")]
+    TarZst,
+}
+
+impl ArchiveFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarZst => "tar.zst",
+        }
+    }
+
+    /// Compression levels the format supports, fastest first.
+    pub fn levels(self) -> RangeInclusive<i32> {
+        match self {
+            ArchiveFormat::Zip | ArchiveFormat::TarGz => 0..=9,
+            ArchiveFormat::TarZst => 1..=22,
+        }
+    }
+}
+
+/// Which archives to write, and how.
+#[derive(Clone, Copy, Debug)]
+pub struct ArchiveOptions {
+    pub format: ArchiveFormat,
+    /// The view whose top-level folders are each bundled into an archive next to them
+    pub view: View,
+    /// Compression level within [`ArchiveFormat::levels`], the format's default if `None`
+    pub level: Option<i32>,
+}
+
+/// A document as listed in [`ARCHIVE_INDEX`].
+#[derive(Serialize)]
+struct ArchivedDocument<'a> {
+    pk: i64,
+    title: &'a str,
+    /// Paths of the document inside the archive
+    files: Vec<String>,
+    created: chrono::DateTime<chrono::Utc>,
+    correspondent: Option<&'a str>,
+    document_type: Option<&'a str>,
+    tags: Vec<&'a str>,
+    checksum: &'a str,
+}
+
+/// The documents of one top-level folder of a view, by their path inside the folder.
+type Group<'a> = BTreeMap<String, &'a Document>;
+
+/// Writes one archive per top-level folder of the view of `archive` to `output_dir`, e.g.
+/// `by_year/2023.zip` next to `by_year/2023/`, holding the exported files of `documents` at the
+/// same paths as in the folder, plus an [`ARCHIVE_INDEX`] of their metadata.
+///
+/// Entries are sorted by path and stamped with the documents' creation dates, so unchanged
+/// folders produce byte-identical archives, which are left untouched. Returns the paths of
+/// all archives of the run.
+pub fn write_archives(
+    output_dir: &Path,
+    documents: &[&Document],
+    names: &FileNames,
+    options: &OrganizeOptions,
+    archive: &ArchiveOptions,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let dir = output_dir.join(archive.view.dir_name());
+    let extension = archive.format.extension();
+    let mut paths = Vec::new();
+    for (folder, group) in groups(documents, names, options, archive.view) {
+        let path = dir.join(format!("{folder}.{extension}"));
+        fs::create_dir_all(&dir)?;
+        let partial = dir.join(format!(".{folder}.{extension}.partial"));
+        write_archive(&partial, &group, options, archive)?;
+        if fs::metadata(&path).is_ok() && sync::same_contents(&partial, &path)? {
+            fs::remove_file(&partial)?;
+        } else {
+            fs::rename(&partial, &path)?;
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Paths of the archives [`write_archives`] writes for `documents`.
+pub fn archive_paths(
+    documents: &[&Document],
+    names: &FileNames,
+    options: &OrganizeOptions,
+    archive: &ArchiveOptions,
+) -> Vec<PathBuf> {
+    let dir = options.output_dir.join(archive.view.dir_name());
+    groups(documents, names, options, archive.view)
+        .into_keys()
+        .map(|folder| dir.join(format!("{folder}.{}", archive.format.extension())))
+        .collect()
+}
+
+/// The documents of `view` by top-level folder. Documents directly in the view's folder
+/// aren't in any.
+fn groups<'a>(
+    documents: &[&'a Document],
+    names: &FileNames,
+    options: &OrganizeOptions,
+    view: View,
+) -> BTreeMap<String, Group<'a>> {
+    let mut groups: BTreeMap<_, Group> = BTreeMap::new();
+    for &doc in documents {
+        let name = names.get(doc).unwrap_or_else(|| options.file_name(doc));
+        for entry in view.entries(doc, &name, names, options) {
+            let mut components = entry
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned());
+            let (Some(folder), path) = (components.next(), components.collect::<Vec<_>>()) else {
+                continue;
+            };
+            if !path.is_empty() {
+                groups
+                    .entry(folder)
+                    .or_default()
+                    .insert(path.join("/"), doc);
+            }
+        }
+    }
+    groups
+}
+
+fn write_archive(
+    path: &Path,
+    group: &Group,
+    options: &OrganizeOptions,
+    archive: &ArchiveOptions,
+) -> anyhow::Result<()> {
+    let mut index: BTreeMap<i64, ArchivedDocument> = BTreeMap::new();
+    for (file, doc) in group {
+        index
+            .entry(doc.pk)
+            .or_insert_with(|| ArchivedDocument {
+                pk: doc.pk,
+                title: &doc.title,
+                files: Vec::new(),
+                created: doc.created,
+                correspondent: doc.correspondent.as_ref().map(|c| c.name.as_str()),
+                document_type: doc.document_type.as_ref().map(|t| t.name.as_str()),
+                tags: doc.tags.iter().map(|t| t.name.as_str()).collect(),
+                checksum: &doc.checksum,
+            })
+            .files
+            .push(file.clone());
+    }
+    let index = serde_json::to_vec_pretty(&index.into_values().collect::<Vec<_>>())?;
+
+    let file = BufWriter::new(File::create(path)?);
+    let level = archive.level;
+    match archive.format {
+        ArchiveFormat::Zip => {
+            let mut zip = ZipWriter::new(file);
+            let entry = SimpleFileOptions::DEFAULT.compression_level(level.map(i64::from));
+            zip.start_file(ARCHIVE_INDEX, entry)?;
+            zip.write_all(&index)?;
+            for (name, doc) in group {
+                let len = len(doc, options)?;
+                let entry = entry
+                    .last_modified_time(zip_time(&doc.created))
+                    .large_file(len.is_none_or(|len| len > u32::MAX as u64));
+                zip.start_file(name.as_str(), entry)?;
+                options
+                    .export
+                    .read_document(doc, &doc.archive_name, |reader| io::copy(reader, &mut zip))?;
+            }
+            zip.finish()?.flush()?;
+        }
+        ArchiveFormat::TarGz => {
+            let level = level.map_or(Compression::default(), |l| Compression::new(l as u32));
+            let gz = write_tar(GzEncoder::new(file, level), &index, group, options)?;
+            gz.finish()?.flush()?;
+        }
+        ArchiveFormat::TarZst => {
+            let encoder =
+                zstd::Encoder::new(file, level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL))?;
+            write_tar(encoder, &index, group, options)?
+                .finish()?
+                .flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a tarball of `index` and the files of `group` to `writer` and returns it.
+fn write_tar<W: Write>(
+    writer: W,
+    index: &[u8],
+    group: &Group,
+    options: &OrganizeOptions,
+) -> io::Result<W> {
+    let mut tar = tar::Builder::new(writer);
+    let header = |len: u64, mtime: u64| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(len);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header
+    };
+    tar.append_data(&mut header(index.len() as u64, 0), ARCHIVE_INDEX, index)?;
+    for (name, doc) in group {
+        let mtime = doc.created.timestamp().try_into().unwrap_or(0);
+        let export = &options.export;
+        match len(doc, options)? {
+            Some(len) => export.read(&doc.archive_name, |reader| {
+                tar.append_data(&mut header(len, mtime), name, reader)
+            })?,
+            None => {
+                let mut contents = Vec::new();
+                export.read_document(doc, &doc.archive_name, |reader| {
+                    reader.read_to_end(&mut contents)
+                })?;
+                tar.append_data(&mut header(contents.len() as u64, mtime), name, &*contents)?;
+            }
+        }
+    }
+    tar.into_inner()
+}
+
+/// Size of the file of `doc` in the archive, `None` if unknown up front: the API doesn't tell,
+/// and decrypting changes it.
+fn len(doc: &Document, options: &OrganizeOptions) -> io::Result<Option<u64>> {
+    match doc.encrypted {
+        true => Ok(None),
+        false => Ok(options.export.file_info(&doc.archive_name)?.len),
+    }
+}
+
+/// A new zip archive the views are written into, e.g. to hand them over as a single file.
+/// Links stay symlinks, which unzip restores on Unix. It's written next to its final path
+/// and only moved there by [`OutputBackend::finish`], so it's always complete.
+pub struct ZipBackend {
+    path: PathBuf,
+    partial: PathBuf,
+    zip: Mutex<Option<ZipWriter<BufWriter<File>>>>,
+}
+
+impl ZipBackend {
+    /// Starts the archive at `path`, replacing it once finished.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(ZipBackend {
+            zip: Mutex::new(Some(ZipWriter::new(BufWriter::new(File::create(
+                &partial,
+            )?)))),
+            path: path.to_owned(),
+            partial,
+        })
+    }
+
+    /// Runs `write` with the archive, unless it's finished already.
+    fn with_zip<T>(
+        &self,
+        write: impl FnOnce(&mut ZipWriter<BufWriter<File>>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut zip = self.zip.lock().unwrap_or_else(|e| e.into_inner());
+        match zip.as_mut() {
+            Some(zip) => write(zip),
+            None => Err(io::Error::other(format!(
+                "{} is finished",
+                self.path.display()
+            ))),
+        }
+    }
+}
+
+impl OutputBackend for ZipBackend {
+    /// Nothing, as the archive is new.
+    fn list(&self) -> io::Result<Vec<Entry>> {
+        Ok(Vec::new())
+    }
+
+    fn write(
+        &self,
+        path: &Path,
+        contents: &mut dyn Read,
+        modified: Option<SystemTime>,
+    ) -> io::Result<u64> {
+        let time = modified.map_or(DateTime::DEFAULT, |t| zip_time(&t.into()));
+        let entry = SimpleFileOptions::DEFAULT
+            .last_modified_time(time)
+            .large_file(true);
+        self.with_zip(|zip| {
+            zip.start_file_from_path(path, entry)?;
+            io::copy(contents, zip)
+        })
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        // `add_symlink_from_path` would drop the `..` of relative targets
+        let [target, link] = [target, link].map(|path| {
+            let components: Vec<_> = path.components().map(|c| c.as_os_str()).collect();
+            components.join("/".as_ref()).to_string_lossy().into_owned()
+        });
+        self.with_zip(|zip| {
+            zip.add_symlink(link, target, SimpleFileOptions::DEFAULT)
+                .map_err(io::Error::from)
+        })
+    }
+
+    fn read_link(&self, link: &Path) -> io::Result<PathBuf> {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} isn't in {}", link.display(), self.path.display()),
+        ))
+    }
+
+    /// Nothing, as [`list`](Self::list) reports nothing to remove.
+    fn remove(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        let zip = self.zip.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(zip) = zip {
+            zip.finish()?.into_inner()?.sync_all()?;
+            fs::rename(&self.partial, &self.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ZipBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+/// `time` as a zip timestamp, or the earliest one zip supports if it's out of range.
+fn zip_time(time: &chrono::DateTime<chrono::Utc>) -> DateTime {
+    let part = |value: u32| value.try_into().unwrap_or(u8::MAX);
+    DateTime::from_date_and_time(
+        time.year().try_into().unwrap_or(0),
+        part(time.month()),
+        part(time.day()),
+        part(time.hour()),
+        part(time.minute()),
+        part(time.second()),
+    )
+    .unwrap_or(DateTime::DEFAULT)
+}
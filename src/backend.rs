@@ -0,0 +1,288 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::bail;
+use tracing::info;
+use walkdir::WalkDir;
+
+use crate::{
+    archive::ZipBackend,
+    export::{ExportSource, FileInfo},
+    platform,
+    remote::Remote,
+    sftp::SftpClient,
+    Document,
+};
+
+/// What is at a path of an [`OutputBackend`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// A file, folder or symlink below the root of an [`OutputBackend`].
+pub struct Entry {
+    /// Relative to the root
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// A destination the organized tree is written to, e.g. the output directory or a folder on a
+/// NAS. Paths are relative to its root, and writes create missing parent folders.
+///
+/// New destinations only need to implement this trait to be usable as
+/// [`OrganizeOptions::backend`].
+pub trait OutputBackend: fmt::Display + Send + Sync {
+    /// Everything below the root, which is created if it doesn't exist yet.
+    fn list(&self) -> io::Result<Vec<Entry>>;
+
+    /// Writes `contents` to the file `path`, replacing what is there, and sets its
+    /// modification time to `modified`. Returns the number of bytes written.
+    fn write(
+        &self,
+        path: &Path,
+        contents: &mut dyn Read,
+        modified: Option<SystemTime>,
+    ) -> io::Result<u64>;
+
+    /// Like [`write`](Self::write) with the exported file `name` of `doc`, decrypted if
+    /// paperless stored it encrypted.
+    fn copy_document(
+        &self,
+        export: &ExportSource,
+        doc: &Document,
+        name: &str,
+        path: &Path,
+        modified: Option<SystemTime>,
+    ) -> io::Result<u64> {
+        export.read_document(doc, name, |reader| self.write(path, reader, modified))
+    }
+
+    /// Whether [`symlink`](Self::symlink) works; the views get copies otherwise.
+    fn symlinks(&self) -> bool {
+        true
+    }
+
+    /// Creates a symlink at `link` pointing to `target`, replacing what is there.
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()>;
+
+    /// What the symlink at `link` points to.
+    fn read_link(&self, link: &Path) -> io::Result<PathBuf>;
+
+    /// Removes the file, symlink or folder `path`, with everything in it.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    /// Called once everything is written, e.g. to complete an archive.
+    fn finish(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A directory on the local file system. Absolute paths are used as they are, e.g. for a
+/// restricted directory outside of the output directory.
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalBackend { root: root.into() }
+    }
+}
+
+impl OutputBackend for LocalBackend {
+    fn list(&self) -> io::Result<Vec<Entry>> {
+        fs::create_dir_all(&self.root)?;
+        let mut entries = Vec::new();
+        for entry in WalkDir::new(&self.root).min_depth(1) {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            entries.push(Entry {
+                path: entry
+                    .path()
+                    .strip_prefix(&self.root)
+                    .unwrap_or(entry.path())
+                    .to_owned(),
+                kind: match entry.file_type() {
+                    kind if kind.is_dir() => EntryKind::Dir,
+                    kind if kind.is_symlink() => EntryKind::Symlink,
+                    _ => EntryKind::File,
+                },
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+        Ok(entries)
+    }
+
+    fn write(
+        &self,
+        path: &Path,
+        contents: &mut dyn Read,
+        modified: Option<SystemTime>,
+    ) -> io::Result<u64> {
+        let path = self.root.join(path);
+        create_parent_dir(&path)?;
+        // don't write through a symlink into the file it points to
+        let _ = fs::remove_file(&path);
+        let bytes = io::copy(contents, &mut BufWriter::new(File::create(&path)?))?;
+        match modified {
+            Some(time) => platform::set_file_time(&path, time).map(|()| bytes),
+            None => Ok(bytes),
+        }
+    }
+
+    /// Clones the file where the file system supports it, see [`ExportSource::copy_to`].
+    fn copy_document(
+        &self,
+        export: &ExportSource,
+        doc: &Document,
+        name: &str,
+        path: &Path,
+        modified: Option<SystemTime>,
+    ) -> io::Result<u64> {
+        let path = self.root.join(path);
+        create_parent_dir(&path)?;
+        // don't write through a link into the file it points to
+        let _ = fs::remove_file(&path);
+        let bytes = export.copy_document(doc, name, &path)?;
+        match modified {
+            Some(time) => platform::set_file_time(&path, time).map(|()| bytes),
+            None => Ok(bytes),
+        }
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        let link = self.root.join(link);
+        create_parent_dir(&link)?;
+        match fs::remove_file(&link) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        platform::symlink_file(target, &link)
+    }
+
+    fn read_link(&self, link: &Path) -> io::Result<PathBuf> {
+        fs::read_link(self.root.join(link))
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let path = self.root.join(path);
+        match path.symlink_metadata()?.is_dir() {
+            true => fs::remove_dir_all(path),
+            false => fs::remove_file(path),
+        }
+    }
+}
+
+impl fmt::Display for LocalBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.root.display())
+    }
+}
+
+/// Opens the backend at `url`:
+///
+/// - `sftp://[user@]host[:port]/path`, see [`SftpClient`]
+/// - `s3://bucket/prefix` and `dav://` or `davs://host/path`, see [`Remote`]; S3 buckets
+///   outside of AWS are reached at `s3_endpoint`
+/// - `zip:///path/to/archive.zip`, a new archive of the views, see [`ZipBackend`]
+/// - `file:///path`, a local directory
+pub fn open(url: &str, s3_endpoint: Option<&str>) -> anyhow::Result<Box<dyn OutputBackend>> {
+    let Some((scheme, location)) = url.split_once("://") else {
+        bail!("invalid output URL `{url}`, expected e.g. sftp://host/path");
+    };
+    Ok(match scheme {
+        "sftp" => Box::new(SftpClient::connect(url)?),
+        "s3" | "dav" | "webdav" | "davs" | "webdavs" => Box::new(Remote::new(url, s3_endpoint)?),
+        "zip" => Box::new(ZipBackend::create(Path::new(location))?),
+        "file" => Box::new(LocalBackend::new(location)),
+        _ => bail!(
+            "unsupported output URL `{url}`, expected sftp://, s3://, dav://, davs://, zip:// \
+             or file://"
+        ),
+    })
+}
+
+fn create_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Whether `entry` is a copy of the exported file described by `info`: the same size (unless
+/// it was decrypted) and not older than the export, or with the modification time `stamp`.
+pub(crate) fn up_to_date(
+    doc: &Document,
+    info: &FileInfo,
+    stamp: Option<SystemTime>,
+    entry: Option<&Entry>,
+) -> bool {
+    let Some(entry) = entry.filter(|entry| entry.kind == EntryKind::File) else {
+        return false;
+    };
+    // servers only keep whole seconds
+    let seconds = |time: SystemTime| time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let newer = match (entry.modified.map(seconds), info.modified.map(seconds)) {
+        (Some(modified), _) if Some(modified) == stamp.map(seconds) => true,
+        (Some(modified), Some(source)) => modified >= source,
+        _ => true,
+    };
+    let same_size = doc.encrypted || info.len.is_none_or(|len| len == entry.size);
+    same_size && newer
+}
+
+/// Writes every file in `dir` to the same path relative to the root of `backend`.
+pub(crate) fn upload_dir(dir: &Path, backend: &dyn OutputBackend) -> anyhow::Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path().strip_prefix(dir)?.to_owned();
+        backend.write(&path, &mut fs::File::open(entry.path())?, None)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Removes everything in the view folders `dirs` of `backend` that isn't one of the
+/// `outputs`, returning how many files and folders were removed. Folders go with their
+/// contents.
+pub(crate) fn prune(
+    backend: &dyn OutputBackend,
+    existing: &HashMap<PathBuf, Entry>,
+    outputs: &HashSet<PathBuf>,
+    dirs: &[String],
+) -> io::Result<u64> {
+    let needed: HashSet<&Path> = outputs.iter().flat_map(|path| path.ancestors()).collect();
+    let stale: BTreeSet<&Path> = existing
+        .keys()
+        .filter(|path| dirs.iter().any(|dir| path.starts_with(dir)))
+        .filter(|path| !needed.contains(path.as_path()))
+        .map(PathBuf::as_path)
+        .collect();
+    let mut removed: Vec<&Path> = Vec::new();
+    // sorted, so folders come before their contents
+    for path in stale {
+        if removed.last().is_some_and(|dir| path.starts_with(dir)) {
+            continue;
+        }
+        info!("removing {} from {backend}", path.display());
+        backend.remove(path)?;
+        removed.push(path);
+    }
+    Ok(removed.len() as u64)
+}
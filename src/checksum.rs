@@ -0,0 +1,41 @@
+use std::io::{self, Read};
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+/// Hex encoded digest of everything read from `reader`, using the algorithm whose digest length
+/// matches `expected` (MD5 for paperless' classic checksums, SHA-256 otherwise).
+pub fn digest_like(reader: &mut dyn Read, expected: &str) -> io::Result<String> {
+    if expected.len() == 32 {
+        digest::<Md5>(reader)
+    } else {
+        digest::<Sha256>(reader)
+    }
+}
+
+/// Hex encoded SHA-256 of everything read from `reader`.
+pub fn sha256(reader: &mut dyn Read) -> io::Result<String> {
+    digest::<Sha256>(reader)
+}
+
+/// The first four hex digits of the SHA-256 of `text`, to tell shortened names apart.
+pub fn short_hash(text: &str) -> String {
+    hex(&Sha256::digest(text.as_bytes())[..2])
+}
+
+fn digest<D: Digest>(reader: &mut dyn Read) -> io::Result<String> {
+    let mut hasher = D::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,696 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::bail;
+use chrono::NaiveDate;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use parse_paperless_manifest::{
+    api::ApiClient,
+    archive::ArchiveFormat,
+    config::Config,
+    diff::DiffFormat,
+    duplicates::DuplicatesFormat,
+    export::ExportSource,
+    filter::IncludeMode,
+    graph::GraphFormat,
+    grouping::{AmountBuckets, Grouping},
+    index::IndexFormat,
+    link::LinkMode,
+    notify::NotifyOn,
+    organize::{AsnLayout, CollisionStrategy, DateDepth, DateField, TaxYearStart, View},
+    platform,
+    query::Query,
+    sanitize::Normalization,
+    sidecar::SidecarFormat,
+    stats::StatsFormat,
+    sums::SumsLayout,
+    template::FilenameTemplate,
+};
+
+/// Organizes a paperless-ngx document export into browsable folder views.
+#[derive(Parser)]
+#[command(
+    version,
+    about,
+    after_help = "Exit codes: 0 success, 1 fatal error, 2 completed but some documents failed, \
+                  3 invalid manifest"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Args)]
+pub struct LogArgs {
+    /// Log more details: `-v` for every document, `-vv` for everything
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Append the log to this file instead of printing it
+    #[arg(long, value_name = "FILE", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value = "text", global = true)]
+    pub log_format: LogFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Copy the exported documents and generate the views
+    Organize(OrganizeArgs),
+    /// Check that the generated copies and links still resolve and match the export
+    Verify(LayoutArgs),
+    /// Check the exported files against the checksums recorded in the manifest
+    VerifyExport(ExportArgs),
+    /// Check the copies against the `SHA256SUMS` files written by `organize --checksums`
+    VerifyChecksums(ExportArgs),
+    /// Check the manifest for missing fields, malformed dates, duplicate pks and references
+    /// to objects that aren't exported, without writing anything
+    Lint(ExportArgs),
+    /// Print document counts and sizes per year, tag, correspondent and document type
+    Stats {
+        #[command(flatten)]
+        export: ExportArgs,
+
+        #[arg(long, value_enum, default_value = "table")]
+        format: StatsFormat,
+
+        /// Time zone whose years documents are counted in, e.g. `Europe/Berlin` or `UTC`
+        /// [default: local]
+        #[arg(long, value_name = "TZ")]
+        timezone: Option<String>,
+    },
+    /// Report documents added, removed, retagged or re-filed between two exports, e.g. two
+    /// backups of the same instance
+    Diff {
+        /// The earlier export directory or zipped export
+        old: PathBuf,
+
+        /// The later export directory or zipped export
+        new: PathBuf,
+
+        #[arg(long, value_enum, default_value = "table")]
+        format: DiffFormat,
+    },
+    /// List documents whose original files are identical, e.g. to clean them up in paperless
+    Duplicates {
+        #[command(flatten)]
+        export: ExportArgs,
+
+        /// Hash the exported originals instead of relying on the checksums in the manifest
+        #[arg(long)]
+        hash_files: bool,
+
+        #[arg(long, value_enum, default_value = "table")]
+        format: DuplicatesFormat,
+    },
+    /// Write a new export with only the documents matching a query, e.g. to hand them to an
+    /// accountant; `document_importer` can import it
+    Extract {
+        #[command(flatten)]
+        export: ExportArgs,
+
+        /// Which documents to extract, e.g. `tag:taxes AND created >= 2023-01-01`
+        query: Query,
+
+        /// Time zone whose days `created` is compared in, e.g. `Europe/Berlin` or `UTC`
+        /// [default: local]
+        #[arg(long, value_name = "TZ")]
+        timezone: Option<String>,
+    },
+    /// Full-text search the documents indexed by `organize --index sqlite`
+    Search {
+        #[command(flatten)]
+        export: ExportArgs,
+
+        /// Words, `"phrases"`, `prefix*`, `AND`/`OR`/`NOT` and column filters like
+        /// `title: invoice`
+        query: String,
+
+        /// Maximum number of results
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Revert the changes of the last `organize --undoable` run
+    Undo(ExportArgs),
+    /// Remove the generated views without regenerating them
+    Clean {
+        #[command(flatten)]
+        export: ExportArgs,
+
+        /// Comma-separated list of views to remove (defaults to all)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        views: Option<Vec<View>>,
+    },
+}
+
+#[derive(Args, Clone)]
+pub struct ExportArgs {
+    /// Directory produced by paperless' `document_exporter`, or the archive written by its
+    /// `--zip` option
+    #[arg(long)]
+    pub export_dir: Option<PathBuf>,
+
+    /// Directory the organized views are written to (defaults to the export directory, or the
+    /// directory containing a zipped export)
+    #[arg(long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Path to the manifest, or `-` to read it from stdin (defaults to
+    /// `<export-dir>/manifest.json`)
+    #[arg(long)]
+    pub manifest: Option<PathBuf>,
+
+    /// Read documents from the REST API of the paperless-ngx instance at this URL instead of
+    /// an export; requires `--output-dir`
+    #[arg(long, conflicts_with_all = ["export_dir", "manifest"])]
+    pub url: Option<String>,
+
+    /// API token for `--url`
+    #[arg(long, env = "PAPERLESS_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// TOML config file (defaults to `paperless-organize.toml` in the working directory, if
+    /// present); command line options take precedence over its values
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Abort on the first malformed manifest object instead of leaving it out with a warning
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Refuse exports of paperless releases newer than the ones this tool was tested against,
+    /// instead of warning about them
+    #[arg(long)]
+    pub strict_version: bool,
+}
+
+/// Export locations after merging the command line with the config file.
+pub struct Export {
+    pub config: Config,
+    pub source: ExportSource,
+    pub output_dir: PathBuf,
+    /// Manifest outside of the export; `manifest.json` in the export if `None`
+    pub manifest: Option<PathBuf>,
+    /// Whether malformed manifest objects are fatal
+    pub strict: bool,
+    /// Whether exports of untested paperless releases are refused
+    pub strict_version: bool,
+}
+
+impl ExportArgs {
+    pub fn resolve(&self) -> anyhow::Result<Export> {
+        let config = Config::discover(self.config.as_deref())?;
+        let strict = self.strict || config.strict.unwrap_or(false);
+        let strict_version = self.strict_version || config.strict_version.unwrap_or(false);
+        if let Some(url) = self.url.clone().or(config.url.clone()) {
+            let Some(output_dir) = self.output_dir.clone().or(config.output_dir.clone()) else {
+                bail!("no output directory given, pass --output-dir when reading from the API");
+            };
+            let token = self.token.clone().or(config.token.clone());
+            return Ok(Export {
+                config,
+                source: ExportSource::Api(ApiClient::new(&url, token)),
+                output_dir: platform::extended_path(&output_dir)?,
+                manifest: None,
+                strict,
+                strict_version,
+            });
+        }
+        let Some(export_dir) = self.export_dir.clone().or(config.export_dir.clone()) else {
+            bail!("no export directory given, pass --export-dir or set `export_dir` in the config");
+        };
+        let export_dir = platform::extended_path(&export_dir)?;
+        let source = ExportSource::open(&export_dir)?;
+        let output_dir = match self.output_dir.clone().or(config.output_dir.clone()) {
+            Some(output_dir) => output_dir,
+            None if source.is_archive() => match export_dir.parent() {
+                Some(parent) => parent.to_path_buf(),
+                None => PathBuf::from("."),
+            },
+            None => export_dir,
+        };
+        let output_dir = platform::extended_path(&output_dir)?;
+        let manifest = self.manifest.clone().or(config.manifest.clone());
+        Ok(Export {
+            config,
+            source,
+            output_dir,
+            manifest,
+            strict,
+            strict_version,
+        })
+    }
+}
+
+/// Options that determine which documents end up where.
+#[derive(Args, Clone)]
+pub struct LayoutArgs {
+    #[command(flatten)]
+    pub export: ExportArgs,
+
+    /// Skip documents carrying this tag (repeatable). Any exclusion given here or in the
+    /// config replaces the default ones: the tags `fine`, `legal` and `private` and tags
+    /// ending in `2`
+    #[arg(long, value_name = "TAG")]
+    pub exclude_tag: Vec<String>,
+
+    /// Skip documents with a tag matching this regex (repeatable)
+    #[arg(long, value_name = "REGEX")]
+    pub exclude_tag_pattern: Vec<String>,
+
+    /// Skip documents with a tag matching this glob, e.g. `private*` (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub exclude_tag_glob: Vec<String>,
+
+    /// Don't skip documents by the default exclusions (`fine`, `legal`, `private` and tags
+    /// ending in `2`) when no others are given
+    #[arg(long)]
+    pub no_default_excludes: bool,
+
+    /// Copy excluded documents into this directory, readable only by the owner on Unix,
+    /// instead of skipping them; other files in it are removed
+    #[arg(long, value_name = "DIR")]
+    pub restricted_dir: Option<PathBuf>,
+
+    /// Encrypt excluded documents to this age recipient (`age1...`, repeatable) and copy them
+    /// into the restricted directory, `encrypted/` in the output directory by default
+    #[arg(long, value_name = "RECIPIENT")]
+    pub encrypt_to: Vec<String>,
+
+    /// After organizing, mirror the output to this location, uploading only what changed and
+    /// deleting what is gone locally: `files/` and the index to an S3 bucket
+    /// (`s3://backups/paperless`, credentials from `AWS_ACCESS_KEY_ID` and
+    /// `AWS_SECRET_ACCESS_KEY`), or all views to a WebDAV folder such as Nextcloud's
+    /// (`davs://host/remote.php/dav/files/alice/paperless`, credentials from
+    /// `WEBDAV_USERNAME` and `WEBDAV_PASSWORD`)
+    #[arg(long, value_name = "URL")]
+    pub remote: Option<String>,
+
+    /// Endpoint of an S3-compatible service to use instead of AWS, e.g.
+    /// `http://localhost:9000`
+    #[arg(long, value_name = "URL", env = "AWS_ENDPOINT_URL")]
+    pub s3_endpoint: Option<String>,
+
+    /// Only include documents created on or after this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    pub created_after: Option<NaiveDate>,
+
+    /// Only include documents created before this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    pub created_before: Option<NaiveDate>,
+
+    /// Only include documents carrying this tag (repeatable)
+    #[arg(long, value_name = "TAG")]
+    pub include_tag: Vec<String>,
+
+    /// Only include documents from this correspondent (repeatable)
+    #[arg(long, value_name = "NAME")]
+    pub include_correspondent: Vec<String>,
+
+    /// Only include documents this user can see in paperless: those they own, those shared
+    /// with them and those without an owner (repeatable)
+    #[arg(long, value_name = "USERNAME")]
+    pub owner: Vec<String>,
+
+    /// Also include documents that were moved to the trash in paperless
+    #[arg(long)]
+    pub include_deleted: bool,
+
+    /// Whether all or any of the include filters have to match [default: all]
+    #[arg(long, value_enum)]
+    pub include_mode: Option<IncludeMode>,
+
+    /// Only include documents matching this query, e.g.
+    /// `tag:invoice AND correspondent:'ACME' AND created >= 2022-01-01 AND NOT tag:private`
+    #[arg(long)]
+    pub query: Option<Query>,
+
+    /// Folder name in `by_correspondent/` for documents without a correspondent
+    /// [default: unassigned]
+    #[arg(long, value_name = "NAME")]
+    pub unassigned_correspondent: Option<String>,
+
+    /// Folder name in `by_document_type/` for documents without a document type
+    /// [default: unassigned]
+    #[arg(long, value_name = "NAME")]
+    pub unassigned_document_type: Option<String>,
+
+    /// Folder name in `by_tag/` for documents without any tags, an empty name leaves them
+    /// out [default: untagged]
+    #[arg(long, value_name = "NAME")]
+    pub unassigned_tag: Option<String>,
+
+    /// How the secondary views refer to the copies in `files/` [default: symlink]
+    #[arg(long, value_enum)]
+    pub link_mode: Option<LinkMode>,
+
+    /// Create symlinks with paths relative to the link, e.g. `../../files/Invoice.pdf`, so
+    /// the output directory keeps working when moved or mounted elsewhere
+    #[arg(long)]
+    pub relative_links: bool,
+
+    /// Comma-separated list of views to generate (defaults to all but by_owner, by_added,
+    /// by_title, by_type, by_tax_year and related)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub views: Option<Vec<View>>,
+
+    /// Nesting of the date folders in the `by_year` view [default: year]
+    #[arg(long, value_enum)]
+    pub date_depth: Option<DateDepth>,
+
+    /// Time zone whose dates the date views sort documents by, e.g. `Europe/Berlin` or `UTC`
+    /// [default: local]
+    #[arg(long, value_name = "TZ")]
+    pub timezone: Option<String>,
+
+    /// Which date the date views sort documents by [default: created]
+    #[arg(long, value_enum)]
+    pub date_field: Option<DateField>,
+
+    /// The day tax years start on in the `by_tax_year` view, e.g. `04-06` for the UK or `07-01`
+    /// for Australia [default: 01-01]
+    #[arg(long, value_name = "MM-DD")]
+    pub tax_year_start: Option<TaxYearStart>,
+
+    /// Edges of the ranges the `amount:NAME` grouping files documents under, e.g. `0,100,1000`
+    /// for `below-0/`, `0-100/`, `100-1000/` and `1000+/` [default: 0,100,1000,10000]
+    #[arg(long, value_name = "AMOUNTS")]
+    pub amount_buckets: Option<AmountBuckets>,
+
+    /// Split tag names on this separator into nested folders in the `by_tag` view, e.g. `/`
+    /// turns `finance/taxes/2023` into `by_tag/finance/taxes/2023/`
+    #[arg(long, value_name = "SEP")]
+    pub tag_separator: Option<String>,
+
+    /// Folder levels of a view as `VIEW=KEY/KEY...` with the keys tag, correspondent,
+    /// document_type, owner, decade, year, month, week, day, tax_year, date:FORMAT,
+    /// first_letter, correspondent_letter, custom_field:NAME and amount:NAME, e.g.
+    /// `by_correspondent=correspondent/year` (repeatable)
+    #[arg(long, value_name = "VIEW=LAYOUT", value_parser = parse_layout)]
+    pub layout: Vec<(View, Grouping)>,
+
+    /// Whether the `by_asn` view uses a folder per ASN or prefixes file names with it
+    /// [default: folder]
+    #[arg(long, value_enum)]
+    pub asn_layout: Option<AsnLayout>,
+
+    /// Number of digits ASNs are zero-padded to in the `by_asn` view [default: 5]
+    #[arg(long, value_name = "N")]
+    pub asn_width: Option<usize>,
+
+    /// Output file name template, e.g. `{{created:%Y-%m-%d}} - {{correspondent}} - {{title}}.{{ext}}`
+    #[arg(long, value_name = "TEMPLATE")]
+    pub filename_template: Option<FilenameTemplate>,
+
+    /// Name output files after the document's creation date and title
+    /// (`2023-05-01 Invoice 42.pdf`) instead of the exporter's names
+    #[arg(long, conflicts_with = "filename_template")]
+    pub title_names: bool,
+
+    /// Character substituted for characters that are illegal in file names [default: _]
+    #[arg(long, value_name = "CHAR")]
+    pub replacement_char: Option<char>,
+
+    /// Shorten file and folder names to at most this many characters, keeping the file
+    /// extension, e.g. for long correspondent names on Windows
+    #[arg(long, value_name = "CHARS")]
+    pub max_name_length: Option<usize>,
+
+    /// Bring file and folder names into this Unicode normalization form, so names match
+    /// across platforms (macOS HFS+ stores NFD)
+    #[arg(long, value_enum)]
+    pub normalize: Option<Normalization>,
+
+    /// Transliterate file and folder names to ASCII, e.g. `Überweisung` to `Uberweisung`
+    #[arg(long)]
+    pub ascii_names: bool,
+
+    /// Shorten paths below the output directory to at most this many characters: file names
+    /// first, then, if need be, tag and other folder names
+    #[arg(long, value_name = "CHARS")]
+    pub max_path_length: Option<usize>,
+
+    /// How to name documents whose file name is already taken by another one
+    /// [default: append_pk]
+    #[arg(long, value_enum)]
+    pub on_collision: Option<CollisionStrategy>,
+
+    /// Comma-separated list of index files to write to the output directory, listing every
+    /// organized document with its metadata and output paths
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "FORMATS")]
+    pub index: Option<Vec<IndexFormat>>,
+
+    /// Comma-separated list of formats to write the graph of the links between documents in,
+    /// made with document link custom fields, to the output directory
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "FORMATS")]
+    pub link_graph: Option<Vec<GraphFormat>>,
+
+    /// Write a metadata file (title, tags, correspondent, created date, ASN and notes) next to
+    /// every copied document
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub sidecar: Option<SidecarFormat>,
+
+    /// Write the SHA-256 of every copied document to a `SHA256SUMS` file in the output
+    /// directory, or with `per_directory` to one in each folder, for `verify-checksums` or
+    /// `sha256sum -c`
+    #[arg(
+        long,
+        value_enum,
+        value_name = "LAYOUT",
+        num_args = 0..=1,
+        default_missing_value = "single"
+    )]
+    pub checksums: Option<SumsLayout>,
+
+    /// Also bundle each year's documents into `by_year/<year>.<ext>` (see `--archive-by`),
+    /// with an `index.json` of their metadata; unchanged archives are left untouched
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub archive: Option<ArchiveFormat>,
+
+    /// The view whose top-level folders `--archive` bundles, e.g. `by_correspondent` for one
+    /// archive per correspondent [default: by_year]
+    #[arg(long, value_enum, value_name = "VIEW")]
+    pub archive_by: Option<View>,
+
+    /// Compression level of `--archive`: 0-9 for zip and tar.gz, 1-22 for tar.zst
+    #[arg(long, value_name = "LEVEL", allow_negative_numbers = true)]
+    pub archive_level: Option<i32>,
+
+    /// Write the OCR text of every document to `<name>.txt` next to its copy, for grep and
+    /// desktop search tools
+    #[arg(long)]
+    pub content_text: bool,
+
+    /// Write the notes of documents that have any to `<name>.notes.txt` next to their copy
+    #[arg(long)]
+    pub notes: bool,
+
+    /// Set the modification time (and on Windows the creation time) of copied files to the
+    /// document's creation date
+    #[arg(long)]
+    pub timestamps_from_created: bool,
+
+    /// Write `skipped.json` and `errors.json` to the output directory, listing the excluded
+    /// documents with the tags excluding them and the documents that failed
+    #[arg(long)]
+    pub report: bool,
+
+    /// Also copy the original files into `files-originals/`
+    #[arg(long)]
+    pub include_originals: bool,
+
+    /// With `--include-originals`, also link the originals into the views next to the archive
+    /// versions
+    #[arg(long)]
+    pub link_originals: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct OrganizeArgs {
+    #[command(flatten)]
+    pub layout: LayoutArgs,
+
+    /// Only copy new or changed documents and remove outputs of vanished ones instead of
+    /// wiping the views. With `--url`, documents are also cached between runs so only those
+    /// modified since the last sync are fetched
+    #[arg(long)]
+    pub incremental: bool,
+
+    /// Keep the files this run replaces or deletes, so `undo` can restore the previous state
+    /// until the next run
+    #[arg(long)]
+    pub undoable: bool,
+
+    /// Build the views next to the existing ones and swap them in once complete, so
+    /// consumers of the output never see a half-built tree
+    #[arg(long, conflicts_with_all = ["incremental", "watch", "interval"])]
+    pub atomic: bool,
+
+    /// Write the views straight to this location instead of the output directory: a folder
+    /// on a NAS at `sftp://admin@nas:2222/volume1/paperless`, an S3 bucket
+    /// (`s3://bucket/prefix`), a WebDAV folder (`davs://host/path`), a new zip archive
+    /// (`zip:///tmp/views.zip`) or a local directory (`file:///srv/paperless`). Only what
+    /// changed is written, as with `--incremental`; links become copies where symlinks
+    /// aren't supported
+    #[arg(long, value_name = "URL", conflicts_with_all = ["atomic", "undoable", "dry_run", "plan_out"])]
+    pub output_url: Option<String>,
+
+    /// Run this command for every document copied in the run, e.g. `lp {path}`, once
+    /// everything is written. `{path}`, `{title}`, `{tags}`, `{pk}`, `{correspondent}`,
+    /// `{document_type}` and `{created}` are replaced by the document's values, which are
+    /// also in the environment as `PAPERLESS_PATH` and so on. It's run without a shell; use
+    /// `sh -c '... "$PAPERLESS_TITLE"'` for one. With `--output-url`, `{path}` is the copy's
+    /// location there. Failures are reported at the end
+    #[arg(long, value_name = "COMMAND")]
+    pub exec: Option<String>,
+
+    /// Number of `--exec` commands run at once (defaults to 1)
+    #[arg(long, value_name = "N", requires = "exec")]
+    pub exec_jobs: Option<usize>,
+
+    /// Send a summary of each run here: a webhook receiving it as JSON (`https://...`), an
+    /// ntfy topic (`ntfys://ntfy.sh/topic`, access token from `NTFY_TOKEN`) or a Gotify
+    /// server (`gotifys://host?token=...` or the token in `GOTIFY_TOKEN`)
+    #[arg(long, value_name = "URL")]
+    pub notify: Option<String>,
+
+    /// When to send the summary (defaults to always)
+    #[arg(long, value_name = "WHEN")]
+    pub notify_on: Option<NotifyOn>,
+
+    /// After each run, write its metrics (documents, bytes copied, failures, duration) to
+    /// this file for node_exporter's textfile collector, e.g.
+    /// `/var/lib/node_exporter/textfile/paperless.prom`
+    #[arg(long, value_name = "FILE")]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Keep running and do an incremental pass whenever the manifest (or zipped export)
+    /// changes, e.g. after a scheduled `document_exporter` run
+    #[arg(long, conflicts_with = "url")]
+    pub watch: bool,
+
+    /// Keep running and do an incremental pass at this interval, e.g. `6h` or `1h30m`; Ctrl-C
+    /// or SIGTERM stop it once the current pass is done
+    #[arg(long, value_parser = parse_interval, conflicts_with = "watch")]
+    pub interval: Option<Duration>,
+
+    /// With `--incremental`, `--watch` or `--interval`, compare file contents rather than just
+    /// size and modification time
+    #[arg(long)]
+    pub compare_contents: bool,
+
+    /// Check the exported files against the manifest checksums before copying anything
+    #[arg(long)]
+    pub verify_checksums: bool,
+
+    /// Only log what would be copied, linked, written and deleted, without changing anything
+    #[arg(long, conflicts_with_all = ["watch", "interval"])]
+    pub dry_run: bool,
+
+    /// Write every intended operation with its source, destination and reason to this JSON
+    /// file, e.g. to review a `--dry-run`
+    #[arg(long, value_name = "FILE")]
+    pub plan_out: Option<PathBuf>,
+
+    /// Don't show a progress bar
+    #[arg(long, short)]
+    pub quiet: bool,
+
+    /// Number of documents processed in parallel (defaults to the number of CPU cores)
+    #[arg(
+        long,
+        short,
+        value_name = "N",
+        default_value_t = 0,
+        hide_default_value = true
+    )]
+    pub jobs: usize,
+}
+
+/// Parses durations like `90s`, `15m`, `6h`, `1d` or `1h30m`.
+fn parse_interval(text: &str) -> Result<Duration, String> {
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("unknown unit `{c}`, expected s, m, h or d")),
+        };
+        if number.is_empty() {
+            return Err(format!("expected a number before `{c}`"));
+        }
+        let too_long = || format!("the interval `{text}` is too long");
+        let value: u64 = number.parse().map_err(|_| too_long())?;
+        seconds = value
+            .checked_mul(unit)
+            .and_then(|value| value.checked_add(seconds))
+            .ok_or_else(too_long)?;
+        number.clear();
+    }
+    if !number.is_empty() || seconds == 0 {
+        return Err("expected a positive duration like `6h` or `1h30m`".to_owned());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parses `by_correspondent=correspondent/year`.
+fn parse_layout(text: &str) -> Result<(View, Grouping), String> {
+    let (view, grouping) = text
+        .split_once('=')
+        .ok_or("expected VIEW=LAYOUT, e.g. `by_year=year/correspondent`")?;
+    let view = View::from_str(view.trim(), false)?;
+    if view == View::Files {
+        return Err("the files view has no folders".to_owned());
+    }
+    Ok((
+        view,
+        grouping.parse().map_err(|e: anyhow::Error| e.to_string())?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_add_up_their_units() {
+        assert_eq!(parse_interval("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_interval("2d1s"), Ok(Duration::from_secs(172_801)));
+        assert_eq!(parse_interval("0h5m"), Ok(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn invalid_intervals_are_rejected() {
+        for interval in ["", "5", "0s", "h", "1hm", "1w", "-1h", "1h 30m"] {
+            assert!(parse_interval(interval).is_err(), "{interval}");
+        }
+        for interval in [
+            "18446744073709551616s",
+            "213503982334602d",
+            "18446744073709551615s1s",
+        ] {
+            assert_eq!(
+                parse_interval(interval),
+                Err(format!("the interval `{interval}` is too long"))
+            );
+        }
+    }
+}
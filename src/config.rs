@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::{
+    alias::Aliases,
+    archive::ArchiveFormat,
+    filter::IncludeMode,
+    graph::GraphFormat,
+    index::IndexFormat,
+    link::LinkMode,
+    notify::NotifyOn,
+    organize::{AsnLayout, CollisionStrategy, DateDepth, DateField, View},
+    sanitize::Normalization,
+    sidecar::SidecarFormat,
+    sums::SumsLayout,
+};
+
+/// Config file picked up from the working directory when no `--config` is given.
+pub const DEFAULT_CONFIG_FILE: &str = "paperless-organize.toml";
+
+/// Settings read from a TOML config file. Every value can be overridden on the command line.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub export_dir: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    /// Where to write the views instead of `output_dir`, e.g. `sftp://nas/volume1/paperless`,
+    /// `s3://bucket/prefix`, `davs://host/path`, `zip:///path/views.zip` or `file:///path`
+    pub output_url: Option<String>,
+    pub manifest: Option<PathBuf>,
+    /// Base URL of a paperless-ngx instance to read from instead of an export
+    pub url: Option<String>,
+    pub token: Option<String>,
+    /// Fail on malformed manifest objects instead of leaving them out
+    pub strict: Option<bool>,
+    /// Refuse exports of paperless releases newer than the tested ones
+    pub strict_version: Option<bool>,
+    pub restricted_dir: Option<PathBuf>,
+    /// Where to mirror the organized copies to, e.g. `s3://bucket/prefix` or
+    /// `davs://host/path`
+    pub remote: Option<String>,
+    pub s3_endpoint: Option<String>,
+    /// Command run for every document copied, e.g. `lp {path}`
+    pub exec: Option<String>,
+    pub exec_jobs: Option<usize>,
+    /// Where to send a summary of each run, e.g. `ntfys://ntfy.sh/topic`
+    pub notify: Option<String>,
+    pub notify_on: Option<NotifyOn>,
+    /// Where to write Prometheus metrics after each run, e.g. `paperless.prom`
+    pub metrics_file: Option<PathBuf>,
+    pub views: Option<Vec<View>>,
+    pub link_mode: Option<LinkMode>,
+    pub relative_links: Option<bool>,
+    pub date_depth: Option<DateDepth>,
+    /// e.g. `Europe/Berlin`; the system's time zone if unset
+    pub timezone: Option<String>,
+    pub date_field: Option<DateField>,
+    /// `MM-DD`, e.g. `04-06`
+    pub tax_year_start: Option<String>,
+    /// e.g. `[0, 100, 1000]`
+    pub amount_buckets: Option<Vec<f64>>,
+    pub tag_separator: Option<String>,
+    /// Folder levels of views, e.g. `by_correspondent = "correspondent/year"`
+    pub layouts: HashMap<View, String>,
+    pub custom_views: Vec<CustomViewConfig>,
+    /// Custom fields to generate a `by_<field>/<value>/` view for, e.g. `["Project", "Vehicle"]`
+    pub custom_field_views: Vec<String>,
+    pub asn_layout: Option<AsnLayout>,
+    pub asn_width: Option<usize>,
+    pub filename_template: Option<String>,
+    pub title_names: Option<bool>,
+    pub replacement_char: Option<char>,
+    pub max_name_length: Option<usize>,
+    pub normalize: Option<Normalization>,
+    pub ascii_names: Option<bool>,
+    pub max_path_length: Option<usize>,
+    pub on_collision: Option<CollisionStrategy>,
+    pub index: Option<Vec<IndexFormat>>,
+    pub link_graph: Option<Vec<GraphFormat>>,
+    pub sidecar: Option<SidecarFormat>,
+    pub checksums: Option<SumsLayout>,
+    pub archive: Option<ArchiveFormat>,
+    pub archive_by: Option<View>,
+    pub archive_level: Option<i32>,
+    pub content_text: Option<bool>,
+    pub notes: Option<bool>,
+    pub timestamps_from_created: Option<bool>,
+    pub report: Option<bool>,
+    pub include_originals: Option<bool>,
+    pub link_originals: Option<bool>,
+    pub exclude: ExcludeConfig,
+    pub encryption: EncryptionConfig,
+    pub include: IncludeConfig,
+    pub unassigned: UnassignedConfig,
+    pub aliases: Aliases,
+}
+
+/// `[[custom_views]]` entry: a view named `name` grouping documents by `group`, e.g.
+/// `document_type/year`, `date:%Y-%m/first_letter` or `custom_field:Project`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomViewConfig {
+    pub name: String,
+    pub group: String,
+}
+
+/// `[exclude]` section: documents carrying a matching tag are skipped. Setting any of the
+/// lists, even to `[]`, replaces the default exclusions.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExcludeConfig {
+    pub tags: Option<Vec<String>>,
+    /// Regexes, e.g. `^private`
+    pub tag_patterns: Option<Vec<String>>,
+    /// Globs, e.g. `private*`
+    pub tag_globs: Option<Vec<String>>,
+}
+
+/// `[encryption]` section: excluded documents are encrypted to these age recipients, into
+/// `restricted_dir` or `encrypted/` in the output directory, instead of being skipped.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// X25519 public keys, `age1...`
+    pub recipients: Vec<String>,
+}
+
+/// `[include]` section: restricts a run to the matching documents.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IncludeConfig {
+    pub tags: Vec<String>,
+    pub correspondents: Vec<String>,
+    /// Usernames whose visible documents are included
+    pub owners: Vec<String>,
+    /// Whether documents in paperless' trash are included
+    pub deleted: Option<bool>,
+    pub mode: Option<IncludeMode>,
+    pub query: Option<String>,
+}
+
+/// `[unassigned]` section: folder names for documents lacking a correspondent, type or tags.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnassignedConfig {
+    pub correspondent: Option<String>,
+    pub document_type: Option<String>,
+    pub tag: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read config {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("failed to parse config {}", path.display()))
+    }
+
+    /// Loads `path` if given, otherwise [`DEFAULT_CONFIG_FILE`] if it exists.
+    pub fn discover(path: Option<&Path>) -> anyhow::Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                Self::load(Path::new(DEFAULT_CONFIG_FILE))
+            }
+            None => Ok(Self::default()),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+};
+
+use serde::Serialize;
+
+use crate::{Document, Manifest};
+
+/// Output format of the `diff` subcommand.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum DiffFormat {
+    /// Aligned, human readable tables
+    #[default]
+    Table,
+    Json,
+}
+
+/// A document that is only in one of the exports.
+#[derive(Serialize)]
+pub struct Entry {
+    pub pk: i64,
+    pub title: String,
+}
+
+/// A document whose tags changed.
+#[derive(Serialize)]
+pub struct Retagged {
+    pub pk: i64,
+    pub title: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// A document that moved to another correspondent, document type or storage path.
+#[derive(Serialize)]
+pub struct Refiled {
+    pub pk: i64,
+    pub title: String,
+    /// `correspondent`, `document_type` or `storage_path`
+    pub field: &'static str,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// What changed between two exports of the same paperless instance. Documents are matched by
+/// pk; those in the trash count as absent.
+#[derive(Default, Serialize)]
+pub struct Diff {
+    pub added: Vec<Entry>,
+    pub removed: Vec<Entry>,
+    pub retagged: Vec<Retagged>,
+    pub refiled: Vec<Refiled>,
+}
+
+impl Diff {
+    pub fn between(old: &Manifest, new: &Manifest) -> Self {
+        let (old, new) = (documents(old), documents(new));
+        let entry = |doc: &Document| Entry {
+            pk: doc.pk,
+            title: doc.title.clone(),
+        };
+
+        let mut diff = Diff {
+            removed: old
+                .values()
+                .filter(|doc| !new.contains_key(&doc.pk))
+                .map(|doc| entry(doc))
+                .collect(),
+            ..Diff::default()
+        };
+        for (pk, doc) in &new {
+            let Some(before) = old.get(pk) else {
+                diff.added.push(entry(doc));
+                continue;
+            };
+
+            let (tags_before, tags_after) = (tags(before), tags(doc));
+            if tags_before != tags_after {
+                diff.retagged.push(Retagged {
+                    pk: *pk,
+                    title: doc.title.clone(),
+                    added: tags_after.difference(&tags_before).cloned().collect(),
+                    removed: tags_before.difference(&tags_after).cloned().collect(),
+                });
+            }
+
+            let fields = [
+                (
+                    "correspondent",
+                    before.correspondent.as_ref().map(|c| &c.name),
+                    doc.correspondent.as_ref().map(|c| &c.name),
+                ),
+                (
+                    "document_type",
+                    before.document_type.as_ref().map(|t| &t.name),
+                    doc.document_type.as_ref().map(|t| &t.name),
+                ),
+                (
+                    "storage_path",
+                    before.storage_path.as_ref().map(|s| &s.name),
+                    doc.storage_path.as_ref().map(|s| &s.name),
+                ),
+            ];
+            for (field, old, new) in fields {
+                if old != new {
+                    diff.refiled.push(Refiled {
+                        pk: *pk,
+                        title: doc.title.clone(),
+                        field,
+                        old: old.cloned(),
+                        new: new.cloned(),
+                    });
+                }
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.retagged.is_empty()
+            && self.refiled.is_empty()
+    }
+
+    /// Renders the changes as one table per kind of change.
+    pub fn table(&self) -> String {
+        let mut out = String::new();
+        if self.is_empty() {
+            out.push_str("no documents were added, removed, retagged or re-filed\n");
+            return out;
+        }
+        let line = |out: &mut String, pk: i64, title: &str, change: &str| {
+            let line = format!("  {pk:>6}  {title:<40} {change}");
+            let _ = writeln!(out, "{}", line.trim_end());
+        };
+
+        for (heading, entries) in [("added", &self.added), ("removed", &self.removed)] {
+            if !entries.is_empty() {
+                let _ = writeln!(out, "\n{heading} ({})", entries.len());
+                for entry in entries {
+                    line(&mut out, entry.pk, &entry.title, "");
+                }
+            }
+        }
+        if !self.retagged.is_empty() {
+            let _ = writeln!(out, "\nretagged ({})", self.retagged.len());
+            for doc in &self.retagged {
+                let changes: Vec<_> = (doc.added.iter().map(|t| format!("+{t}")))
+                    .chain(doc.removed.iter().map(|t| format!("-{t}")))
+                    .collect();
+                line(&mut out, doc.pk, &doc.title, &changes.join(", "));
+            }
+        }
+        if !self.refiled.is_empty() {
+            let _ = writeln!(out, "\nre-filed ({})", self.refiled.len());
+            for doc in &self.refiled {
+                let name = |name: &Option<String>| name.clone().unwrap_or_else(|| "-".to_owned());
+                let change = format!("{}: {} -> {}", doc.field, name(&doc.old), name(&doc.new));
+                line(&mut out, doc.pk, &doc.title, &change);
+            }
+        }
+        out.remove(0);
+        out
+    }
+}
+
+/// The documents of `manifest` that aren't in the trash, by pk.
+fn documents(manifest: &Manifest) -> BTreeMap<i64, &Document> {
+    manifest
+        .documents
+        .iter()
+        .filter(|doc| doc.deleted_at.is_none())
+        .map(|doc| (doc.pk, doc))
+        .collect()
+}
+
+fn tags(doc: &Document) -> BTreeSet<String> {
+    doc.tags.iter().map(|t| t.name.clone()).collect()
+}
@@ -0,0 +1,123 @@
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{checksum, export::ExportSource, Document, Manifest};
+
+/// Output format of the `duplicates` subcommand.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum DuplicatesFormat {
+    /// Aligned, human readable tables
+    #[default]
+    Table,
+    Json,
+}
+
+/// One of several documents with the same original file.
+#[derive(Serialize)]
+pub struct Duplicate {
+    pub pk: i64,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub correspondent: Option<String>,
+    pub document_type: Option<String>,
+}
+
+/// Documents whose original files have the same checksum, oldest pk first.
+#[derive(Serialize)]
+pub struct DuplicateGroup {
+    pub checksum: String,
+    pub documents: Vec<Duplicate>,
+}
+
+/// Groups the documents of `manifest` that aren't in the trash by the checksum of their
+/// original file, keeping the groups of more than one.
+///
+/// Paperless refuses to consume a file it already has, so duplicates usually differ in a byte
+/// or two, or were added while the check was bypassed. With `export`, the SHA-256 of each
+/// exported original is computed instead of trusting the manifest; documents whose file can't
+/// be read fall back to the recorded checksum.
+pub fn find_duplicates(manifest: &Manifest, export: Option<&ExportSource>) -> Vec<DuplicateGroup> {
+    let checksums: Vec<(String, &Document)> = manifest
+        .documents
+        .par_iter()
+        .filter(|doc| doc.deleted_at.is_none())
+        .map(|doc| {
+            let computed = export.and_then(|export| {
+                export
+                    .read_document(doc, &doc.file_name, checksum::sha256)
+                    .inspect_err(|error| {
+                        warn!(
+                            "failed to read {}: {error}",
+                            export.display_path(&doc.file_name).display()
+                        )
+                    })
+                    .ok()
+            });
+            (computed.unwrap_or_else(|| doc.checksum.to_lowercase()), doc)
+        })
+        .collect();
+
+    let mut groups: BTreeMap<String, Vec<&Document>> = BTreeMap::new();
+    for (checksum, doc) in checksums {
+        if !checksum.is_empty() {
+            groups.entry(checksum).or_default().push(doc);
+        }
+    }
+    let mut duplicates: Vec<_> = groups
+        .into_iter()
+        .filter(|(_, docs)| docs.len() > 1)
+        .map(|(checksum, mut docs)| {
+            docs.sort_by_key(|doc| doc.pk);
+            DuplicateGroup {
+                checksum,
+                documents: docs.into_iter().map(duplicate).collect(),
+            }
+        })
+        .collect();
+    duplicates.sort_by_key(|group| group.documents[0].pk);
+    duplicates
+}
+
+fn duplicate(doc: &Document) -> Duplicate {
+    Duplicate {
+        pk: doc.pk,
+        title: doc.title.clone(),
+        tags: doc.tags.iter().map(|t| t.name.clone()).collect(),
+        correspondent: doc.correspondent.as_ref().map(|c| c.name.clone()),
+        document_type: doc.document_type.as_ref().map(|t| t.name.clone()),
+    }
+}
+
+/// Renders `groups` as one table per group of duplicates.
+pub fn table(groups: &[DuplicateGroup]) -> String {
+    let mut out = String::new();
+    if groups.is_empty() {
+        out.push_str("no duplicate documents found\n");
+        return out;
+    }
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let _ = writeln!(
+            out,
+            "{} documents with checksum {}",
+            group.documents.len(),
+            group.checksum
+        );
+        for doc in &group.documents {
+            let filed: Vec<_> = [&doc.correspondent, &doc.document_type]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .chain(doc.tags.iter().map(|t| format!("#{t}")))
+                .collect();
+            let line = format!("  {:>6}  {:<40} {}", doc.pk, doc.title, filed.join(", "));
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+    }
+    out
+}
@@ -0,0 +1,59 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use age::x25519;
+use anyhow::{anyhow, bail};
+
+use crate::{export::ExportSource, Document};
+
+/// Extension appended to the names of encrypted copies, as the `age` tool does.
+pub const ENCRYPTED_EXTENSION: &str = "age";
+
+/// Default directory for the encrypted copies of excluded documents, relative to the output
+/// directory, if no `restricted_dir` is set.
+pub const ENCRYPTED_DIR: &str = "encrypted";
+
+/// The age recipients excluded documents are encrypted to. Any of their identities can
+/// decrypt the copies, e.g. with `age -d -i key.txt`.
+#[derive(Clone)]
+pub struct Encryption {
+    recipients: Vec<x25519::Recipient>,
+}
+
+impl Encryption {
+    /// Parses the `age1...` public keys in `recipients`.
+    pub fn new(recipients: &[String]) -> anyhow::Result<Self> {
+        if recipients.is_empty() {
+            bail!("no age recipients to encrypt to");
+        }
+        let recipients = recipients
+            .iter()
+            .map(|r| {
+                r.trim()
+                    .parse()
+                    .map_err(|error| anyhow!("invalid age recipient `{r}`: {error}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Encryption { recipients })
+    }
+
+    /// Encrypts the exported file `name` of `doc` to `dest`, returning the number of bytes
+    /// read.
+    pub fn encrypt_to(
+        &self,
+        export: &ExportSource,
+        doc: &Document,
+        name: &str,
+        dest: &Path,
+    ) -> io::Result<u64> {
+        let encryptor = age::Encryptor::with_recipients(self.recipients.iter().map(|r| r as _))
+            .map_err(io::Error::other)?;
+        let mut writer = encryptor.wrap_output(BufWriter::new(File::create(dest)?))?;
+        let bytes = export.read_document(doc, name, |reader| io::copy(reader, &mut writer))?;
+        writer.finish()?.flush()?;
+        Ok(bytes)
+    }
+}
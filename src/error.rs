@@ -0,0 +1,94 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+/// Errors from reading a manifest or organizing a single document.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("manifest is not a JSON array of objects: {0}")]
+    Json(serde_json::Error),
+
+    #[error(
+        "malformed {model} object #{index}{}: {error}",
+        pk.map(|pk| format!(" (pk {pk})")).unwrap_or_default()
+    )]
+    MalformedObject {
+        index: usize,
+        model: String,
+        pk: Option<i64>,
+        error: serde_json::Error,
+    },
+
+    #[error("request to {url} failed: {error}")]
+    Http {
+        url: String,
+        error: Box<ureq::Error>,
+    },
+
+    #[error("failed to read {}: {error}", path.display())]
+    Read { path: PathBuf, error: io::Error },
+
+    #[error("failed to write {}: {error}", path.display())]
+    Write { path: PathBuf, error: io::Error },
+
+    #[error("{}: {error}", path.display())]
+    InFile { path: PathBuf, error: Box<Error> },
+
+    #[error("document {pk}: `{field}` references unknown object {value}")]
+    UnknownReference {
+        pk: i64,
+        field: &'static str,
+        value: i64,
+    },
+
+    #[error("document {pk}: failed to copy {} to {}: {error}", from.display(), to.display())]
+    Copy {
+        pk: i64,
+        from: PathBuf,
+        to: PathBuf,
+        error: io::Error,
+    },
+
+    #[error("document {pk}: failed to create {} ({view}): {error}", path.display())]
+    Link {
+        pk: i64,
+        path: PathBuf,
+        view: String,
+        error: io::Error,
+    },
+
+    #[error(
+        "documents {other} and {pk} would both be written to {}",
+        name.display()
+    )]
+    Collision { pk: i64, other: i64, name: PathBuf },
+
+    #[error("document {pk}: {error}")]
+    Io { pk: i64, error: io::Error },
+
+    #[error("document {pk}: `{command}` failed: {error}")]
+    Hook {
+        pk: i64,
+        command: String,
+        error: String,
+    },
+}
+
+impl Error {
+    /// Whether the manifest itself is at fault: malformed, or referring to objects it lacks.
+    pub fn is_invalid_manifest(&self) -> bool {
+        match self {
+            Error::Json(_) | Error::MalformedObject { .. } | Error::UnknownReference { .. } => true,
+            Error::InFile { error, .. } => error.is_invalid_manifest(),
+            _ => false,
+        }
+    }
+}
+
+// the underlying errors are part of the messages above, so they aren't exposed as `source()`
+// to keep error chains from repeating them
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(error)
+    }
+}
@@ -0,0 +1,437 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use chrono::NaiveDate;
+use flate2::bufread::MultiGzDecoder;
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+use crate::{
+    api::ApiClient,
+    gpg,
+    manifest::ManifestBuilder,
+    version::{ExporterVersion, VERSION_FILES},
+    Document, Error, Manifest,
+};
+
+/// Name of the main manifest inside an export.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Folders `document_exporter` writes into an export: archive versions, thumbnails and, with
+/// `--use-folder-prefix`, originals and split manifests.
+pub const EXPORT_DIRS: [&str; 4] = ["archive", "thumbnails", "originals", "json"];
+
+/// The manifest path standing for standard input, e.g. to pipe in a manifest filtered by `jq`.
+pub const STDIN_MANIFEST: &str = "-";
+
+/// Suffix of the per-document manifests written by `document_exporter --split-manifest`.
+const SPLIT_MANIFEST_SUFFIX: &str = "-manifest.json";
+/// Folder `document_exporter --use-folder-prefix` puts the per-document manifests in; without
+/// it they are at the top level.
+const SPLIT_MANIFEST_DIR: &str = "json";
+/// Files `document_exporter` writes next to the documents, describing the export itself
+const METADATA_FILES: &[&str] = &[MANIFEST_FILE, "metadata.json", "version.json"];
+/// Extensions of compressed manifests, e.g. `manifest.json.gz`; they are detected by their
+/// contents, so the extensions only matter for finding them.
+const COMPRESSED_EXTENSIONS: [&str; 2] = [".gz", ".zst"];
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Where the exported documents are read from: an export directory, the zip archive written
+/// by `document_exporter --zip`, whose entries are extracted on demand, or the REST API of a
+/// running paperless instance.
+pub enum ExportSource {
+    Directory(PathBuf),
+    Zip {
+        path: PathBuf,
+        archive: Mutex<ZipArchive<File>>,
+    },
+    Api(ApiClient),
+}
+
+/// Size and modification time of an exported file.
+pub struct FileInfo {
+    /// `None` if unknown without downloading the file
+    pub len: Option<u64>,
+    pub modified: Option<SystemTime>,
+}
+
+impl ExportSource {
+    /// Opens `path` as a zip archive if it is a file, as an export directory otherwise.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        if !path.is_file() {
+            return Ok(ExportSource::Directory(path.to_path_buf()));
+        }
+        let read_error = |error| Error::Read {
+            path: path.to_path_buf(),
+            error,
+        };
+        let file = File::open(path).map_err(read_error)?;
+        let archive = ZipArchive::new(file).map_err(|e| read_error(e.into()))?;
+        Ok(ExportSource::Zip {
+            path: path.to_path_buf(),
+            archive: Mutex::new(archive),
+        })
+    }
+
+    /// The export directory or zip file.
+    pub fn path(&self) -> &Path {
+        match self {
+            ExportSource::Directory(path) | ExportSource::Zip { path, .. } => path,
+            ExportSource::Api(client) => client.url(),
+        }
+    }
+
+    /// Whether the export is packed into a zip archive.
+    pub fn is_archive(&self) -> bool {
+        matches!(self, ExportSource::Zip { .. })
+    }
+
+    /// Whether the exported files exist on disk, so views can link to them directly.
+    pub fn is_directory(&self) -> bool {
+        matches!(self, ExportSource::Directory(_))
+    }
+
+    /// Location of the exported file `name` on disk, unless it's packed into an archive.
+    pub fn file_path(&self, name: &str) -> Option<PathBuf> {
+        match self {
+            ExportSource::Directory(dir) => Some(dir.join(name)),
+            ExportSource::Zip { .. } | ExportSource::Api(_) => None,
+        }
+    }
+
+    /// Path used to refer to `name` in messages.
+    pub fn display_path(&self, name: &str) -> PathBuf {
+        self.path().join(name)
+    }
+
+    pub fn file_info(&self, name: &str) -> io::Result<FileInfo> {
+        match self {
+            ExportSource::Directory(dir) => {
+                let meta = fs::metadata(dir.join(name))?;
+                Ok(FileInfo {
+                    len: Some(meta.len()),
+                    modified: meta.modified().ok(),
+                })
+            }
+            ExportSource::Zip { archive, .. } => {
+                let mut archive = archive.lock().unwrap_or_else(|e| e.into_inner());
+                let entry = archive.by_name(name).map_err(io::Error::from)?;
+                let modified = entry.last_modified().and_then(|t| {
+                    NaiveDate::from_ymd_opt(t.year().into(), t.month().into(), t.day().into())?
+                        .and_hms_opt(t.hour().into(), t.minute().into(), t.second().into())
+                        .map(|t| t.and_utc().into())
+                });
+                Ok(FileInfo {
+                    len: Some(entry.size()),
+                    modified,
+                })
+            }
+            ExportSource::Api(client) => Ok(FileInfo {
+                len: None,
+                modified: client.modified(name),
+            }),
+        }
+    }
+
+    /// Calls `f` with a reader over the contents of the exported file `name`.
+    pub fn read<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut dyn Read) -> io::Result<T>,
+    ) -> io::Result<T> {
+        match self {
+            ExportSource::Directory(dir) => f(&mut BufReader::new(File::open(dir.join(name))?)),
+            ExportSource::Zip { archive, .. } => {
+                let mut archive = archive.lock().unwrap_or_else(|e| e.into_inner());
+                let mut entry = archive.by_name(name).map_err(io::Error::from)?;
+                f(&mut entry)
+            }
+            ExportSource::Api(client) => client.read(name, f),
+        }
+    }
+
+    /// Copies the exported file `name` to `dest`, returning the size of the copy.
+    ///
+    /// From a directory on a file system with copy-on-write support (Btrfs, XFS, APFS, ReFS),
+    /// the file is cloned, which takes no extra space until either side is modified.
+    pub fn copy_to(&self, name: &str, dest: &Path) -> io::Result<u64> {
+        match self {
+            ExportSource::Directory(dir) => {
+                // cloning refuses to overwrite
+                match fs::remove_file(dest) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+                match reflink_copy::reflink_or_copy(dir.join(name), dest)? {
+                    Some(bytes) => Ok(bytes),
+                    None => Ok(fs::metadata(dest)?.len()),
+                }
+            }
+            ExportSource::Zip { .. } | ExportSource::Api(_) => {
+                self.read(name, |reader| io::copy(reader, &mut File::create(dest)?))
+            }
+        }
+    }
+
+    /// Like [`read`](Self::read) for the file `name` of `doc`, decrypted if paperless stored
+    /// the document GPG-encrypted.
+    pub fn read_document<T>(
+        &self,
+        doc: &Document,
+        name: &str,
+        f: impl FnOnce(&mut dyn Read) -> io::Result<T>,
+    ) -> io::Result<T> {
+        match doc.encrypted {
+            true => gpg::read_decrypted(self, name, f),
+            false => self.read(name, f),
+        }
+    }
+
+    /// Like [`copy_to`](Self::copy_to) for the file `name` of `doc`, decrypted if paperless
+    /// stored the document GPG-encrypted.
+    pub fn copy_document(&self, doc: &Document, name: &str, dest: &Path) -> io::Result<u64> {
+        match doc.encrypted {
+            true => gpg::read_decrypted(self, name, |reader| {
+                io::copy(reader, &mut File::create(dest)?)
+            }),
+            false => self.copy_to(name, dest),
+        }
+    }
+
+    /// Names of all files in the export, relative to it and with `/` as separator, sorted. Hidden
+    /// files and directories are left out; from the API, there are none.
+    pub fn file_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = match self {
+            ExportSource::Directory(dir) => WalkDir::new(dir)
+                .into_iter()
+                .filter_entry(|e| {
+                    e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.')
+                })
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_file())
+                .filter_map(|e| {
+                    let relative = e.path().strip_prefix(dir).ok()?;
+                    let components: Option<Vec<_>> = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_str())
+                        .collect();
+                    Some(components?.join("/"))
+                })
+                .collect(),
+            ExportSource::Zip { archive, .. } => archive
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .file_names()
+                .filter_map(Result::ok)
+                .filter(|name| !name.ends_with('/'))
+                .filter(|name| !name.split('/').any(|part| part.starts_with('.')))
+                .map(|name| name.into_owned())
+                .collect(),
+            ExportSource::Api(_) => Vec::new(),
+        };
+        names.sort();
+        names
+    }
+
+    /// Whether the exported file `name` is a manifest or other metadata rather than a document
+    /// file.
+    pub fn is_metadata(name: &str) -> bool {
+        let name = uncompressed_name(name);
+        METADATA_FILES.contains(&name) || name.ends_with(SPLIT_MANIFEST_SUFFIX)
+    }
+
+    /// The name of the main manifest in the export: `manifest.json`, or a compressed
+    /// `manifest.json.gz` or `manifest.json.zst` if there is no uncompressed one.
+    pub fn manifest_name(&self) -> String {
+        if self.file_info(MANIFEST_FILE).is_ok() {
+            return MANIFEST_FILE.to_owned();
+        }
+        COMPRESSED_EXTENSIONS
+            .into_iter()
+            .map(|extension| format!("{MANIFEST_FILE}{extension}"))
+            .find(|name| self.file_info(name).is_ok())
+            .unwrap_or_else(|| MANIFEST_FILE.to_owned())
+    }
+
+    /// Names of the per-document manifests of a split export, compressed or not.
+    /// Only the top level and [`SPLIT_MANIFEST_DIR`] are looked at, the exporter doesn't put
+    /// them anywhere else.
+    fn split_manifests(&self) -> Vec<String> {
+        let names = match self {
+            ExportSource::Directory(dir) => {
+                let mut names = Vec::new();
+                for sub_dir in [None, Some(SPLIT_MANIFEST_DIR)] {
+                    let path = sub_dir.map_or_else(|| dir.clone(), |sub_dir| dir.join(sub_dir));
+                    let Ok(entries) = fs::read_dir(path) else {
+                        continue;
+                    };
+                    for entry in entries.filter_map(Result::ok) {
+                        let Ok(name) = entry.file_name().into_string() else {
+                            continue;
+                        };
+                        if name.starts_with('.') || !entry.path().is_file() {
+                            continue;
+                        }
+                        names.push(match sub_dir {
+                            Some(sub_dir) => format!("{sub_dir}/{name}"),
+                            None => name,
+                        });
+                    }
+                }
+                names.sort();
+                names
+            }
+            _ => self
+                .file_names()
+                .into_iter()
+                .filter(|name| match name.split_once('/') {
+                    Some((dir, rest)) => dir == SPLIT_MANIFEST_DIR && !rest.contains('/'),
+                    None => true,
+                })
+                .collect(),
+        };
+        names
+            .into_iter()
+            .filter(|name| uncompressed_name(name).ends_with(SPLIT_MANIFEST_SUFFIX))
+            .collect()
+    }
+
+    /// Loads the main manifest (`manifest`, which is read from stdin if it's
+    /// [`STDIN_MANIFEST`], or the export's `manifest.json` if `None`) and, for
+    /// split exports, merges in the per-document manifests. From the API, everything is fetched
+    /// and `manifest` is ignored. Unless `strict`, malformed objects are left out rather than
+    /// failing, see [`Manifest::malformed`].
+    pub fn load_manifest(&self, manifest: Option<&Path>, strict: bool) -> Result<Manifest, Error> {
+        if let ExportSource::Api(client) = self {
+            return client.fetch_manifest(strict);
+        }
+        let mut builder = ManifestBuilder::new(strict, self.exporter_version());
+        self.read_manifests(manifest, &mut |_, reader| builder.read(reader))?;
+        builder.finish()
+    }
+
+    /// The objects of the main manifest and the per-document manifests as they are, for
+    /// writing a manifest `document_importer` accepts.
+    pub fn load_objects(&self, manifest: Option<&Path>) -> Result<Vec<serde_json::Value>, Error> {
+        let files = self.load_object_files(manifest)?;
+        Ok(files.into_iter().flat_map(|(_, objects)| objects).collect())
+    }
+
+    /// Like [`ExportSource::load_objects`], but keeping the objects of each manifest file
+    /// apart, with the file's path.
+    pub fn load_object_files(
+        &self,
+        manifest: Option<&Path>,
+    ) -> Result<Vec<(PathBuf, Vec<serde_json::Value>)>, Error> {
+        let mut files = Vec::new();
+        self.read_manifests(manifest, &mut |path, reader| {
+            files.push((path.to_path_buf(), serde_json::from_reader(reader)?));
+            Ok(())
+        })?;
+        Ok(files)
+    }
+
+    /// The paperless release that wrote the export, from its `metadata.json` or `version.json`;
+    /// `None` if neither says, or when reading from the API.
+    pub fn exporter_version(&self) -> Option<ExporterVersion> {
+        if let ExportSource::Api(_) = self {
+            return None;
+        }
+        VERSION_FILES.into_iter().find_map(|name| {
+            let json: serde_json::Value = self
+                .read(name, |reader| Ok(serde_json::from_reader(reader)?))
+                .ok()?;
+            ExporterVersion::from_json(&json).ok()
+        })
+    }
+
+    /// Calls `parse` on the main manifest and then each per-document manifest, with the path
+    /// of each.
+    fn read_manifests(
+        &self,
+        manifest: Option<&Path>,
+        parse: &mut dyn FnMut(&Path, &mut dyn Read) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        match manifest {
+            Some(path) if path == Path::new(STDIN_MANIFEST) => {
+                let path = Path::new("<stdin>");
+                let mut stdin = io::stdin().lock();
+                let mut reader = decompressed(&mut stdin).map_err(|error| Error::Read {
+                    path: path.to_path_buf(),
+                    error,
+                })?;
+                parse(path, &mut reader).map_err(|error| Error::InFile {
+                    path: path.to_path_buf(),
+                    error: Box::new(error),
+                })?;
+            }
+            Some(path) => {
+                let mut file = File::open(path).map_err(|error| Error::Read {
+                    path: path.to_path_buf(),
+                    error,
+                })?;
+                let mut reader = decompressed(&mut file).map_err(|error| Error::Read {
+                    path: path.to_path_buf(),
+                    error,
+                })?;
+                parse(path, &mut reader).map_err(|error| Error::InFile {
+                    path: path.to_path_buf(),
+                    error: Box::new(error),
+                })?;
+            }
+            None => self.read_manifest(&self.manifest_name(), parse)?,
+        }
+        for name in self.split_manifests() {
+            self.read_manifest(&name, parse)?;
+        }
+        Ok(())
+    }
+
+    fn read_manifest(
+        &self,
+        name: &str,
+        parse: &mut dyn FnMut(&Path, &mut dyn Read) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let path = self.display_path(name);
+        let mut result = Ok(());
+        self.read(name, |reader| {
+            result = parse(&path, &mut decompressed(reader)?);
+            Ok(())
+        })
+        .map_err(|error| Error::Read {
+            path: path.clone(),
+            error,
+        })?;
+        result.map_err(|error| Error::InFile {
+            path,
+            error: Box::new(error),
+        })
+    }
+}
+
+/// `name` without the extension of a compressed file, if it has one.
+fn uncompressed_name(name: &str) -> &str {
+    COMPRESSED_EXTENSIONS
+        .into_iter()
+        .find_map(|extension| name.strip_suffix(extension))
+        .unwrap_or(name)
+}
+
+/// `reader`, decompressed if it starts like a gzip or zstd stream, buffered either way.
+fn decompressed<'a>(reader: &'a mut dyn Read) -> io::Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let magic = reader.fill_buf()?;
+    Ok(if magic.starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+    } else {
+        Box::new(reader)
+    })
+}
@@ -0,0 +1,175 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use serde_json::Value;
+
+use crate::{export::ExportSource, Document, Error};
+
+/// Files `document_importer` reads next to the manifest; copied as they are if present.
+const COPIED_METADATA: &[&str] = &["metadata.json", "version.json"];
+
+/// What [`extract`] wrote.
+#[derive(Default)]
+pub struct ExtractSummary {
+    pub documents: u64,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Writes a new export to `dir` holding only `documents`: their files and a `manifest.json`
+/// with the objects of `export`'s manifest (`manifest`, if it is kept elsewhere), minus the
+/// other documents and everything attached to them, such as notes, custom field values and
+/// permissions. Tags, correspondents, users and the like are kept so the result imports
+/// cleanly with `document_importer`.
+pub fn extract(
+    export: &ExportSource,
+    manifest: Option<&Path>,
+    documents: &[&Document],
+    dir: &Path,
+) -> Result<ExtractSummary, Error> {
+    let selected: HashSet<i64> = documents.iter().map(|doc| doc.pk).collect();
+    let mut objects = export.load_objects(manifest)?;
+    let codenames = permission_codenames(&objects);
+    objects.retain(|object| keep(object, &selected, &codenames));
+
+    let write_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| Error::Write { path, error }
+    };
+    let mut summary = ExtractSummary::default();
+    for doc in documents {
+        let mut names = vec![&doc.file_name];
+        if doc.has_archive() {
+            names.push(&doc.archive_name);
+        }
+        names.extend(&doc.thumbnail_name);
+        for name in names {
+            let dest = dir.join(name);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).map_err(write_error(parent))?;
+            }
+            summary.bytes += export.copy_to(name, &dest).map_err(|error| Error::Copy {
+                pk: doc.pk,
+                from: export.display_path(name),
+                to: dest.clone(),
+                error,
+            })?;
+            summary.files += 1;
+        }
+        summary.documents += 1;
+    }
+    for name in COPIED_METADATA {
+        if export.file_info(name).is_ok() {
+            let dest = dir.join(name);
+            export.copy_to(name, &dest).map_err(write_error(&dest))?;
+        }
+    }
+
+    let path = dir.join(crate::export::MANIFEST_FILE);
+    let file = File::create(&path).map_err(write_error(&path))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &objects)
+        .map_err(|error| write_error(&path)(io::Error::from(error)))?;
+    Ok(summary)
+}
+
+/// Codenames of the `auth.permission` objects by pk, to tell which object permissions are
+/// about documents when they reference permissions by pk.
+fn permission_codenames(objects: &[Value]) -> HashMap<i64, String> {
+    objects
+        .iter()
+        .filter(|object| object["model"] == "auth.permission")
+        .filter_map(|object| {
+            Some((
+                object["pk"].as_i64()?,
+                object["fields"]["codename"].as_str()?.to_owned(),
+            ))
+        })
+        .collect()
+}
+
+/// Whether `object` belongs into a manifest with only the `selected` documents.
+fn keep(object: &Value, selected: &HashSet<i64>, codenames: &HashMap<i64, String>) -> bool {
+    let fields = &object["fields"];
+    let is_selected = |pk: Option<i64>| pk.is_none_or(|pk| selected.contains(&pk));
+    match object["model"].as_str().unwrap_or_default() {
+        "documents.document" => is_selected(object["pk"].as_i64()),
+        "guardian.userobjectpermission" | "guardian.groupobjectpermission" => {
+            let codename = match &fields["permission"] {
+                Value::Number(pk) => pk
+                    .as_i64()
+                    .and_then(|pk| codenames.get(&pk))
+                    .map(String::as_str),
+                key => key[0].as_str(),
+            };
+            !codename.is_some_and(|c| c.ends_with("_document"))
+                || is_selected(fields["object_pk"].as_str().and_then(|pk| pk.parse().ok()))
+        }
+        "auditlog.logentry" => {
+            fields["content_type"] != serde_json::json!(["documents", "document"])
+                || is_selected(fields["object_pk"].as_str().and_then(|pk| pk.parse().ok()))
+        }
+        // notes, custom field values, share links, workflow runs, ...
+        _ => is_selected(fields["document"].as_i64()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn kept(object: Value) -> bool {
+        let codenames =
+            HashMap::from([(1, "view_document".to_owned()), (2, "view_tag".to_owned())]);
+        keep(&object, &HashSet::from([1]), &codenames)
+    }
+
+    #[test]
+    fn only_the_selected_documents_and_their_objects_are_kept() {
+        assert!(kept(
+            json!({"model": "documents.document", "pk": 1, "fields": {}})
+        ));
+        assert!(!kept(
+            json!({"model": "documents.document", "pk": 2, "fields": {}})
+        ));
+        assert!(kept(
+            json!({"model": "documents.note", "pk": 5, "fields": {"document": 1}})
+        ));
+        assert!(!kept(
+            json!({"model": "documents.note", "pk": 6, "fields": {"document": 2}})
+        ));
+        assert!(kept(
+            json!({"model": "documents.tag", "pk": 2, "fields": {"name": "a"}})
+        ));
+    }
+
+    #[test]
+    fn permissions_and_log_entries_of_other_documents_are_dropped() {
+        let permission = |permission: Value, object_pk: &str| {
+            json!({"model": "guardian.userobjectpermission", "pk": 1, "fields": {
+                "permission": permission, "object_pk": object_pk, "user": 1}})
+        };
+        let by_key = json!(["view_document", "documents", "document"]);
+        assert!(kept(permission(by_key.clone(), "1")));
+        assert!(!kept(permission(by_key, "2")));
+        assert!(kept(permission(json!(1), "1")));
+        assert!(!kept(permission(json!(1), "2")));
+        assert!(kept(permission(json!(2), "2")));
+        assert!(kept(permission(
+            json!(["view_tag", "documents", "tag"]),
+            "2"
+        )));
+
+        let log_entry = |content_type: Value| {
+            json!({"model": "auditlog.logentry", "pk": 1, "fields": {
+                "content_type": content_type, "object_pk": "2"}})
+        };
+        assert!(!kept(log_entry(json!(["documents", "document"]))));
+        assert!(kept(log_entry(json!(["documents", "tag"]))));
+    }
+}
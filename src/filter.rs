@@ -0,0 +1,198 @@
+use anyhow::Context;
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::{
+    query::Query,
+    timezone::{self, TimeZone},
+    Document,
+};
+
+/// Tags excluded unless other rules are given, as this tool always did.
+pub const DEFAULT_EXCLUDED_TAGS: [&str; 3] = ["fine", "legal", "private"];
+
+/// Tag globs excluded unless other rules are given: tags ending in `2`.
+pub const DEFAULT_EXCLUDED_GLOBS: [&str; 1] = ["*2"];
+
+/// Tag based exclusion rules; a document is skipped if any of its tags matches.
+#[derive(Debug)]
+pub struct ExcludeRules {
+    tags: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Default for ExcludeRules {
+    /// [`DEFAULT_EXCLUDED_TAGS`] and [`DEFAULT_EXCLUDED_GLOBS`]
+    fn default() -> Self {
+        let tags = DEFAULT_EXCLUDED_TAGS.map(str::to_owned).to_vec();
+        let globs = DEFAULT_EXCLUDED_GLOBS.map(str::to_owned);
+        Self::new(tags, &[], &globs).expect("valid default globs")
+    }
+}
+
+impl ExcludeRules {
+    /// Rules excluding `tags`, tags matching one of the regexes `patterns` and tags matching
+    /// one of the shell-style `globs`, e.g. `private*` or `*-202?`.
+    pub fn new(tags: Vec<String>, patterns: &[String], globs: &[String]) -> anyhow::Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|p| Regex::new(p).with_context(|| format!("invalid tag pattern `{p}`")))
+            .chain(globs.iter().map(|g| {
+                glob_regex(g)
+                    .and_then(|regex| Ok(Regex::new(&regex)?))
+                    .with_context(|| format!("invalid tag glob `{g}`"))
+            }))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { tags, patterns })
+    }
+
+    /// Rules excluding nothing.
+    pub fn none() -> Self {
+        Self {
+            tags: Vec::new(),
+            patterns: Vec::new(),
+        }
+    }
+
+    pub fn excludes_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag) || self.patterns.iter().any(|p| p.is_match(tag))
+    }
+}
+
+/// The anchored regex matching what `glob` does: `*` any text, `?` one character and `[...]`
+/// one of a set, with `[!...]` negating it.
+fn glob_regex(glob: &str) -> anyhow::Result<String> {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                regex.push('[');
+                if chars.next_if(|&c| c == '!' || c == '^').is_some() {
+                    regex.push('^');
+                }
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' | '[' | '&' | '~' => {
+                            regex.push('\\');
+                            regex.push(c);
+                        }
+                        c => regex.push(c),
+                    }
+                }
+                if !closed {
+                    anyhow::bail!("unterminated `[`");
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Ok(regex)
+}
+
+/// Narrows a run down to a slice of the archive; unlike exclusions, documents outside the
+/// selection are silently left out.
+#[derive(Default, Debug)]
+pub struct DocumentFilter {
+    /// Only documents created on or after this date
+    pub created_after: Option<NaiveDate>,
+    /// Only documents created before this date
+    pub created_before: Option<NaiveDate>,
+    /// Each tag is a criterion: the document carries that tag
+    pub include_tags: Vec<String>,
+    /// A single criterion: the document's correspondent is one of these
+    pub include_correspondents: Vec<String>,
+    /// Whether all or any of the include criteria have to hold
+    pub include_mode: IncludeMode,
+    /// Only documents at least one of these users can see in paperless
+    pub owners: Vec<String>,
+    /// Also documents in paperless' trash
+    pub include_deleted: bool,
+    pub query: Option<Query>,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IncludeMode {
+    /// Every include criterion has to match (AND)
+    #[default]
+    All,
+    /// At least one include criterion has to match (OR)
+    Any,
+}
+
+impl DocumentFilter {
+    /// Whether `doc` is selected, taking its creation day in `timezone`.
+    pub fn matches(&self, doc: &Document, timezone: &TimeZone) -> bool {
+        let created = timezone::created(doc, timezone).date_naive();
+        (self.include_deleted || doc.deleted_at.is_none())
+            && self.created_after.is_none_or(|after| created >= after)
+            && self.created_before.is_none_or(|before| created < before)
+            && (self.owners.is_empty() || self.owners.iter().any(|o| doc.is_visible_to(o)))
+            && self.matches_includes(doc)
+            && self.query.as_ref().is_none_or(|q| q.matches(doc, timezone))
+    }
+
+    fn matches_includes(&self, doc: &Document) -> bool {
+        let mut criteria = self
+            .include_tags
+            .iter()
+            .map(|tag| doc.tags.iter().any(|t| &t.name == tag))
+            .collect::<Vec<_>>();
+        if !self.include_correspondents.is_empty() {
+            criteria.push(
+                doc.correspondent
+                    .as_ref()
+                    .is_some_and(|c| self.include_correspondents.contains(&c.name)),
+            );
+        }
+        match self.include_mode {
+            _ if criteria.is_empty() => true,
+            IncludeMode::All => criteria.iter().all(|&c| c),
+            IncludeMode::Any => criteria.iter().any(|&c| c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_rules_exclude_the_baseline_tags() {
+        let rules = ExcludeRules::default();
+        for tag in ["fine", "legal", "private", "tax2"] {
+            assert!(rules.excludes_tag(tag), "{tag}");
+        }
+        assert!(!rules.excludes_tag("invoice"));
+        assert!(!ExcludeRules::none().excludes_tag("private"));
+    }
+
+    #[test]
+    fn globs_match_whole_tags() {
+        let rules =
+            ExcludeRules::new(Vec::new(), &[], &["private*".into(), "q[!0-2].v?".into()]).unwrap();
+        assert!(rules.excludes_tag("private"));
+        assert!(rules.excludes_tag("private/bank"));
+        assert!(!rules.excludes_tag("not private"));
+        assert!(rules.excludes_tag("q3.v1"));
+        assert!(!rules.excludes_tag("q1.v1"));
+        assert!(!rules.excludes_tag("q3xv1"));
+        for glob in ["tax[", "q[!0-2.v?", "[!"] {
+            let error = ExcludeRules::new(Vec::new(), &[], &[glob.into()]).unwrap_err();
+            assert_eq!(
+                format!("{error:#}"),
+                format!("invalid tag glob `{glob}`: unterminated `[`")
+            );
+        }
+    }
+}
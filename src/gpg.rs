@@ -0,0 +1,87 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use crate::export::ExportSource;
+
+/// Environment variable with the passphrase paperless encrypted documents with, named like
+/// paperless' own setting. Without it, `gpg` asks its agent.
+pub const PASSPHRASE_VAR: &str = "PAPERLESS_PASSPHRASE";
+
+/// Calls `f` with a reader over the decrypted contents of the exported file `name`, which
+/// paperless stored GPG-encrypted (`storage_type: gpg`). Decryption is left to `gpg`, which
+/// must be on the `PATH`.
+pub fn read_decrypted<T>(
+    export: &ExportSource,
+    name: &str,
+    f: impl FnOnce(&mut dyn Read) -> io::Result<T>,
+) -> io::Result<T> {
+    // gpg reads the passphrase from stdin, so the encrypted file has to be on disk
+    let extracted;
+    let input = match export.file_path(name) {
+        Some(path) => path,
+        None => {
+            extracted = Extracted::new(export, name)?;
+            extracted.0.clone()
+        }
+    };
+
+    let passphrase = env::var(PASSPHRASE_VAR).ok();
+    let mut command = Command::new("gpg");
+    command.args(["--batch", "--quiet", "--no-tty"]);
+    if passphrase.is_some() {
+        command.args(["--pinentry-mode", "loopback", "--passphrase-fd", "0"]);
+    }
+    let mut child = command
+        .arg("--decrypt")
+        .arg(&input)
+        .stdin(match passphrase {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| io::Error::new(error.kind(), format!("failed to run gpg: {error}")))?;
+    if let (Some(passphrase), Some(mut stdin)) = (passphrase, child.stdin.take()) {
+        writeln!(stdin, "{passphrase}")?;
+    }
+
+    let result = match &mut child.stdout {
+        Some(stdout) => f(stdout),
+        None => Err(io::Error::other("gpg has no output")),
+    };
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "gpg failed to decrypt {}: {}",
+            export.display_path(name).display(),
+            stderr.trim()
+        )));
+    }
+    result
+}
+
+/// An encrypted file extracted from a zipped export or downloaded from the API for `gpg`,
+/// removed again when dropped.
+struct Extracted(PathBuf);
+
+impl Extracted {
+    fn new(export: &ExportSource, name: &str) -> io::Result<Self> {
+        let file_name = name.replace('/', "_");
+        let path = env::temp_dir().join(format!(".{}-{file_name}", std::process::id()));
+        let extracted = Extracted(path);
+        export.copy_to(name, &extracted.0)?;
+        Ok(extracted)
+    }
+}
+
+impl Drop for Extracted {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::{organize::FileNames, CustomFieldValue, Document};
+
+/// Format of the graph of the links between documents written to the output directory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphFormat {
+    /// `links.dot`, for Graphviz, e.g. `dot -Tsvg links.dot`
+    Dot,
+    /// `links.json`, with the documents as `nodes` and the links as `edges`
+    Json,
+}
+
+impl GraphFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "links.dot",
+            GraphFormat::Json => "links.json",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Serialize)]
+struct Node {
+    pk: i64,
+    title: String,
+    /// The output file name of the document
+    file: Option<String>,
+}
+
+/// A document linking another one with the document link custom field `field`.
+#[derive(Serialize)]
+struct Edge {
+    from: i64,
+    to: i64,
+    field: String,
+}
+
+impl Graph {
+    /// The links between `documents`; links to documents that aren't among them are left out.
+    fn new(documents: &[&Document], names: &FileNames) -> Self {
+        let pks: HashSet<i64> = documents.iter().map(|d| d.pk).collect();
+        let nodes = documents
+            .iter()
+            .map(|doc| Node {
+                pk: doc.pk,
+                title: doc.title.clone(),
+                file: names
+                    .get(doc)
+                    .map(|name| name.to_string_lossy().into_owned()),
+            })
+            .collect();
+        let edges = documents
+            .iter()
+            .flat_map(|doc| {
+                doc.custom_fields.iter().flat_map(|(field, value)| {
+                    let linked = match value {
+                        CustomFieldValue::DocumentLinks(pks) => pks.as_slice(),
+                        _ => &[],
+                    };
+                    linked.iter().map(|&to| Edge {
+                        from: doc.pk,
+                        to,
+                        field: field.clone(),
+                    })
+                })
+            })
+            .filter(|edge| edge.from != edge.to && pks.contains(&edge.to))
+            .collect();
+        Graph { nodes, edges }
+    }
+
+    fn write_dot(&self, w: &mut impl Write) -> io::Result<()> {
+        // DOT strings are quoted like JSON ones
+        let quote = |text: &str| serde_json::Value::from(text).to_string();
+        writeln!(w, "digraph documents {{")?;
+        writeln!(w, "    node [shape=box];")?;
+        for node in &self.nodes {
+            writeln!(w, "    {} [label={}];", node.pk, quote(&node.title))?;
+        }
+        for edge in &self.edges {
+            writeln!(
+                w,
+                "    {} -> {} [label={}];",
+                edge.from,
+                edge.to,
+                quote(&edge.field)
+            )?;
+        }
+        writeln!(w, "}}")
+    }
+}
+
+/// Writes the graph of the document links between `documents`, named `names`, to
+/// `output_dir` in every format of `formats`.
+pub fn write_graph(
+    output_dir: &Path,
+    documents: &[&Document],
+    names: &FileNames,
+    formats: &[GraphFormat],
+) -> anyhow::Result<()> {
+    let graph = Graph::new(documents, names);
+    for &format in formats {
+        let path = output_dir.join(format.file_name());
+        let result = File::create(&path).and_then(|file| {
+            let mut w = BufWriter::new(file);
+            match format {
+                GraphFormat::Dot => graph.write_dot(&mut w)?,
+                GraphFormat::Json => {
+                    serde_json::to_writer_pretty(&mut w, &graph)?;
+                    writeln!(w)?;
+                }
+            }
+            w.flush()
+        });
+        result.with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,261 @@
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context};
+use chrono::format::{Item, StrftimeItems};
+
+use crate::{
+    organize::{decade, OrganizeOptions},
+    CustomFieldValue, Document,
+};
+
+/// A level of folders in a view, derived from one piece of a document's metadata.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GroupKey {
+    /// One folder per tag, nested with `tag_separator`; `unassigned_tag` for untagged documents
+    Tag,
+    Correspondent,
+    DocumentType,
+    /// The owner's username; documents without an owner are left out of the view
+    Owner,
+    /// `2020s`
+    Decade,
+    /// `2023`
+    Year,
+    /// `2023-05`
+    Month,
+    /// `2023-W18`, the ISO week and the year it belongs to
+    Week,
+    /// `2023-05-01`
+    Day,
+    /// The tax year starting on `tax_year_start`, e.g. `2023-24`
+    TaxYear,
+    /// The creation date in a custom strftime format, e.g. `date:%Y-%m`
+    Date(String),
+    /// Upper-cased first letter or digit of the title, `#` for anything else
+    FirstLetter,
+    /// Like `FirstLetter`, of the correspondent's name (or `unassigned_correspondent`), to split
+    /// up many correspondents, e.g. `correspondent_letter/correspondent`
+    CorrespondentLetter,
+    /// The value of a custom field, e.g. `custom_field:Project`; documents without it are left
+    /// out of the view
+    CustomField(String),
+    /// The range of `amount_buckets` the amount of a monetary or numeric custom field is in,
+    /// e.g. `amount:Invoice amount` files 250 under `100-1000`; documents without it are left
+    /// out of the view
+    Amount(String),
+}
+
+/// The folder levels of a view, outermost first, e.g. `correspondent/year`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Grouping(Vec<GroupKey>);
+
+impl FromStr for Grouping {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> anyhow::Result<Self> {
+        let keys = spec
+            .split('/')
+            .map(|key| {
+                let key = key.trim();
+                if let Some(format) = key.strip_prefix("date:") {
+                    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                        bail!("invalid date format `{format}`");
+                    }
+                    return Ok(GroupKey::Date(format.to_owned()));
+                }
+                if let Some(name) = key.strip_prefix("custom_field:") {
+                    return Ok(GroupKey::CustomField(name.to_owned()));
+                }
+                if let Some(name) = key.strip_prefix("amount:") {
+                    return Ok(GroupKey::Amount(name.to_owned()));
+                }
+                Ok(match key {
+                    "tag" => GroupKey::Tag,
+                    "correspondent" => GroupKey::Correspondent,
+                    "document_type" => GroupKey::DocumentType,
+                    "owner" => GroupKey::Owner,
+                    "decade" => GroupKey::Decade,
+                    "year" => GroupKey::Year,
+                    "month" => GroupKey::Month,
+                    "week" => GroupKey::Week,
+                    "day" => GroupKey::Day,
+                    "tax_year" => GroupKey::TaxYear,
+                    "first_letter" => GroupKey::FirstLetter,
+                    "correspondent_letter" => GroupKey::CorrespondentLetter,
+                    key => bail!(
+                        "unknown grouping `{key}`, expected tag, correspondent, document_type, \
+                         owner, decade, year, month, week, day, tax_year, date:FORMAT, first_letter, \
+                         correspondent_letter, custom_field:NAME or amount:NAME"
+                    ),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self(keys))
+    }
+}
+
+impl Grouping {
+    /// Folders by the value of the custom field `name`, e.g. for the view generated for it.
+    pub fn custom_field(name: &str) -> Self {
+        Self(vec![GroupKey::CustomField(name.to_owned())])
+    }
+
+    /// Every folder `doc` is filed under, relative to the view; a document with several tags
+    /// gets one per tag, one lacking a custom field or owner the view groups by none.
+    pub(crate) fn folders(&self, doc: &Document, options: &OrganizeOptions) -> Vec<PathBuf> {
+        self.0.iter().fold(vec![PathBuf::new()], |parents, key| {
+            let folders = key.folders(doc, options);
+            parents
+                .iter()
+                .flat_map(|parent| folders.iter().map(move |folder| parent.join(folder)))
+                .collect()
+        })
+    }
+}
+
+impl GroupKey {
+    fn folders(&self, doc: &Document, options: &OrganizeOptions) -> Vec<PathBuf> {
+        let sanitized = |name: &str| vec![PathBuf::from(options.sanitizer.component(name))];
+        let time = options.date(doc);
+        let date = |format| vec![PathBuf::from(time.format(format).to_string())];
+        match self {
+            GroupKey::Tag => options.tag_folders(doc),
+            GroupKey::Correspondent => sanitized(
+                doc.correspondent
+                    .as_ref()
+                    .map_or(&options.unassigned_correspondent, |c| &c.name),
+            ),
+            GroupKey::DocumentType => sanitized(
+                doc.document_type
+                    .as_ref()
+                    .map_or(&options.unassigned_document_type, |t| &t.name),
+            ),
+            GroupKey::Owner => doc
+                .owner
+                .iter()
+                .map(|owner| PathBuf::from(options.sanitizer.component(&owner.username)))
+                .collect(),
+            GroupKey::Decade => vec![PathBuf::from(decade(time))],
+            GroupKey::Year => date("%Y"),
+            GroupKey::Month => date("%Y-%m"),
+            GroupKey::Week => date("%G-W%V"),
+            GroupKey::Day => date("%Y-%m-%d"),
+            GroupKey::TaxYear => vec![PathBuf::from(options.tax_year_start.folder(time.date()))],
+            GroupKey::Date(format) => date(format),
+            GroupKey::FirstLetter => vec![PathBuf::from(first_letter(&doc.title))],
+            GroupKey::CorrespondentLetter => {
+                let name = doc
+                    .correspondent
+                    .as_ref()
+                    .map_or(&options.unassigned_correspondent, |c| &c.name);
+                vec![PathBuf::from(first_letter(name))]
+            }
+            GroupKey::CustomField(name) => doc
+                .custom_fields
+                .get(name)
+                .map(|value| {
+                    vec![PathBuf::from(
+                        options.sanitizer.component(&value.to_string()),
+                    )]
+                })
+                .unwrap_or_default(),
+            GroupKey::Amount(name) => doc
+                .custom_fields
+                .get(name)
+                .and_then(CustomFieldValue::amount)
+                .map(|amount| vec![PathBuf::from(options.amount_buckets.folder(amount))])
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The edges of the ranges `amount:NAME` folders are, e.g. `0, 100, 1000` for `below-0/`,
+/// `0-100/`, `100-1000/` and `1000+/`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AmountBuckets(Vec<f64>);
+
+impl Default for AmountBuckets {
+    fn default() -> Self {
+        Self(vec![0.0, 100.0, 1000.0, 10000.0])
+    }
+}
+
+impl FromStr for AmountBuckets {
+    type Err = anyhow::Error;
+
+    /// Parses a comma-separated list like `0,100,1000`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let edges = s
+            .split(',')
+            .map(|edge| {
+                edge.trim()
+                    .parse()
+                    .with_context(|| format!("invalid amount `{}`", edge.trim()))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Self::new(edges)
+    }
+}
+
+impl AmountBuckets {
+    /// Checks that `edges` are ascending.
+    pub fn new(edges: Vec<f64>) -> anyhow::Result<Self> {
+        if edges.is_empty() || edges.iter().any(|edge| !edge.is_finite()) {
+            bail!("expected amounts like `0,100,1000`");
+        }
+        if edges.windows(2).any(|pair| pair[0] >= pair[1]) {
+            bail!("the amounts of the buckets have to be ascending");
+        }
+        Ok(Self(edges))
+    }
+
+    /// The folder of the range `amount` is in, the lower edge included.
+    fn folder(&self, amount: f64) -> String {
+        let above = self.0.iter().take_while(|&&edge| edge <= amount).count();
+        match (above.checked_sub(1), self.0.get(above)) {
+            (None, Some(first)) => format!("below-{first}"),
+            (Some(lower), Some(upper)) => format!("{}-{upper}", self.0[lower]),
+            (Some(lower), None) => format!("{}+", self.0[lower]),
+            (None, None) => unreachable!("there is at least one edge"),
+        }
+    }
+}
+
+/// The upper-cased first letter or digit of `text`, `#` if it starts with anything else.
+pub(crate) fn first_letter(text: &str) -> String {
+    match text.chars().find(|c| !c.is_whitespace()) {
+        Some(c) if c.is_alphanumeric() => c.to_uppercase().collect(),
+        _ => "#".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_are_filed_by_their_range() {
+        let buckets: AmountBuckets = "0, 100, 1000".parse().unwrap();
+        assert_eq!(buckets.folder(-0.5), "below-0");
+        assert_eq!(buckets.folder(0.0), "0-100");
+        assert_eq!(buckets.folder(99.99), "0-100");
+        assert_eq!(buckets.folder(100.0), "100-1000");
+        assert_eq!(buckets.folder(1000.0), "1000+");
+        assert_eq!(buckets.folder(1e9), "1000+");
+        assert_eq!(AmountBuckets::default().folder(250.0), "100-1000");
+        assert_eq!(
+            "12.5".parse::<AmountBuckets>().unwrap().folder(3.0),
+            "below-12.5"
+        );
+    }
+
+    #[test]
+    fn buckets_need_ascending_finite_edges() {
+        for edges in ["", "0,100,100", "100,0", "0,inf", "NaN", "0,,100", "0;100"] {
+            assert!(edges.parse::<AmountBuckets>().is_err(), "{edges}");
+        }
+        assert!(AmountBuckets::new(Vec::new()).is_err());
+        assert!(AmountBuckets::new(vec![0.0, f64::INFINITY]).is_err());
+        assert!(AmountBuckets::new(vec![-10.0, 0.0, 0.5]).is_ok());
+    }
+}
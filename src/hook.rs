@@ -0,0 +1,217 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::bail;
+use rayon::{prelude::*, ThreadPoolBuilder};
+use regex::{Captures, Regex};
+use tracing::debug;
+
+use crate::{
+    timezone::{self, TimeZone},
+    Document, Error,
+};
+
+/// Placeholders [`Hook`] replaces in the arguments of its command.
+pub const PLACEHOLDERS: [&str; 7] = [
+    "path",
+    "title",
+    "tags",
+    "pk",
+    "correspondent",
+    "document_type",
+    "created",
+];
+
+/// A command run for every document a run copies, e.g. to push it to another system, print it
+/// or run further OCR on it: `lp {path}` or `sh -c 'ocrmypdf "$PAPERLESS_PATH" out.pdf'`.
+///
+/// The command is split into words like a shell would, but run without one, and
+/// placeholders such as `{path}` or `{title}` in them are replaced by the document's values
+/// (see [`PLACEHOLDERS`]; tags are separated by commas). The same values are in the
+/// environment as `PAPERLESS_PATH`, `PAPERLESS_TITLE` and so on, which is the safe way to
+/// hand them to a shell script.
+pub struct Hook {
+    command: String,
+    args: Vec<String>,
+    placeholder: Regex,
+    /// How many commands run at once
+    jobs: usize,
+}
+
+/// What [`Hook::run_all`] did.
+#[derive(Default)]
+pub struct HookSummary {
+    pub ran: u64,
+    /// Documents whose command failed
+    pub failures: Vec<Error>,
+}
+
+impl Hook {
+    /// The hook running `command` for up to `jobs` documents at once, at least one.
+    pub fn new(command: &str, jobs: usize) -> anyhow::Result<Self> {
+        let args = split_words(command)?;
+        if args.is_empty() {
+            bail!("the command to run for each document is empty");
+        }
+        // `${NAME}` is left to shells the command runs
+        let placeholder = Regex::new(r"\$?\{(\w+)\}").unwrap();
+        let names = args.iter().flat_map(|arg| placeholder.captures_iter(arg));
+        for captures in names.filter(|captures| !captures[0].starts_with('$')) {
+            if !PLACEHOLDERS.contains(&&captures[1]) {
+                bail!(
+                    "unknown placeholder `{}` in `{command}`, expected one of {}",
+                    &captures[0],
+                    PLACEHOLDERS.map(|p| format!("{{{p}}}")).join(", ")
+                );
+            }
+        }
+        Ok(Hook {
+            command: command.to_owned(),
+            args,
+            placeholder,
+            jobs: jobs.max(1),
+        })
+    }
+
+    /// Runs the command for each document and its copy, continuing past failures.
+    pub fn run_all(
+        &self,
+        documents: &[(&Document, PathBuf)],
+        timezone: &TimeZone,
+    ) -> anyhow::Result<HookSummary> {
+        let pool = ThreadPoolBuilder::new().num_threads(self.jobs).build()?;
+        let results: Vec<_> = pool.install(|| {
+            documents
+                .par_iter()
+                .map(|(doc, path)| self.run(doc, path, timezone))
+                .collect()
+        });
+        Ok(HookSummary {
+            ran: results.len() as u64,
+            failures: results.into_iter().filter_map(Result::err).collect(),
+        })
+    }
+
+    /// Runs the command for `doc`, copied to `path`, with its creation date in `timezone`.
+    pub fn run(&self, doc: &Document, path: &Path, timezone: &TimeZone) -> Result<(), Error> {
+        let values = values(doc, path, timezone);
+        let args: Vec<_> =
+            self.args
+                .iter()
+                .map(|arg| {
+                    let replaced = self.placeholder.replace_all(arg, |captures: &Captures| {
+                        match values.iter().find(|(name, _)| *name == &captures[1]) {
+                            Some((_, value)) if !captures[0].starts_with('$') => value.clone(),
+                            _ => captures[0].to_owned(),
+                        }
+                    });
+                    replaced.into_owned()
+                })
+                .collect();
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..]).stdin(Stdio::null());
+        for (name, value) in &values {
+            command.env(format!("PAPERLESS_{}", name.to_uppercase()), value);
+        }
+        debug!("running {args:?} for document {}", doc.pk);
+
+        let failed = |error: String| Error::Hook {
+            pk: doc.pk,
+            command: self.command.clone(),
+            error,
+        };
+        let output = command.output().map_err(|e| failed(e.to_string()))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !stdout.trim().is_empty() {
+            debug!("{}: {}", args[0], stdout.trim_end());
+        }
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(failed(match stderr.trim() {
+                "" => output.status.to_string(),
+                stderr => format!("{}: {stderr}", output.status),
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// The values of the placeholders for `doc`, copied to `path`.
+fn values(doc: &Document, path: &Path, timezone: &TimeZone) -> [(&'static str, String); 7] {
+    let name = |value: Option<&String>| value.cloned().unwrap_or_default();
+    [
+        ("path", path.to_string_lossy().into_owned()),
+        ("title", doc.title.clone()),
+        (
+            "tags",
+            doc.tags
+                .iter()
+                .map(|tag| tag.name.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        ("pk", doc.pk.to_string()),
+        (
+            "correspondent",
+            name(doc.correspondent.as_ref().map(|c| &c.name)),
+        ),
+        (
+            "document_type",
+            name(doc.document_type.as_ref().map(|t| &t.name)),
+        ),
+        (
+            "created",
+            timezone::created(doc, timezone)
+                .format("%Y-%m-%d")
+                .to_string(),
+        ),
+    ]
+}
+
+/// Splits `command` into words at unquoted whitespace, like a POSIX shell: single quotes keep
+/// everything, double quotes everything but `\"` and `\\`, and a backslash outside of quotes
+/// escapes the next character.
+fn split_words(command: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => bail!("unterminated ' in `{command}`"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => word.extend(['\\', c]),
+                            None => bail!("unterminated \" in `{command}`"),
+                        },
+                        Some(c) => word.push(c),
+                        None => bail!("unterminated \" in `{command}`"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => bail!("`{command}` ends in a lone backslash"),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs,
+    path::Path,
+};
+
+use chrono::{Datelike, NaiveDate};
+
+use crate::{
+    index::IndexEntry,
+    link::relative_path,
+    platform::plain_path,
+    timezone::{self, TimeZone},
+    Manifest,
+};
+
+/// Folder next to `index.html` holding the per tag, correspondent and year pages.
+pub const PAGES_DIR: &str = "html";
+
+const STYLE: &str =
+    "body{font-family:sans-serif;margin:2em}table{border-collapse:collapse;width:100%}\
+th,td{text-align:left;padding:.3em .6em;border-bottom:1px solid #ddd}input{width:100%;padding:.4em;\
+margin:1em 0}nav a{margin-right:1em;white-space:nowrap}";
+
+const SCRIPT: &str = "const q=document.getElementById('q');q.addEventListener('input',()=>{\
+const t=q.value.toLowerCase();for(const r of document.querySelectorAll('tbody tr'))\
+r.hidden=!r.dataset.search.includes(t)})";
+
+/// Writes a static, self-contained HTML browser for `entries`: `index.html` in `output_dir`
+/// with a searchable list of all documents, and one page per tag, correspondent and year. Days
+/// and years are those documents of `manifest` were created in `timezone`.
+pub fn write_html(
+    output_dir: &Path,
+    manifest: &Manifest,
+    entries: &[IndexEntry],
+    timezone: &TimeZone,
+) -> std::io::Result<()> {
+    let days: HashMap<i64, NaiveDate> = manifest
+        .documents
+        .iter()
+        .map(|doc| (doc.pk, timezone::created(doc, timezone).date_naive()))
+        .collect();
+    let day = |entry: &IndexEntry| {
+        days.get(&entry.pk)
+            .copied()
+            .unwrap_or(entry.created.date_naive())
+    };
+    let pages_dir = output_dir.join(PAGES_DIR);
+    let _ = fs::remove_dir_all(&pages_dir);
+    fs::create_dir_all(&pages_dir)?;
+
+    let mut groups: [(&str, BTreeMap<String, Vec<&IndexEntry>>); 3] = [
+        ("tag", BTreeMap::new()),
+        ("correspondent", BTreeMap::new()),
+        ("year", BTreeMap::new()),
+    ];
+    for entry in entries {
+        for tag in &entry.tags {
+            groups[0].1.entry(tag.clone()).or_default().push(entry);
+        }
+        if let Some(correspondent) = &entry.correspondent {
+            groups[1]
+                .1
+                .entry(correspondent.clone())
+                .or_default()
+                .push(entry);
+        }
+        groups[2]
+            .1
+            .entry(day(entry).year().to_string())
+            .or_default()
+            .push(entry);
+    }
+
+    let mut nav = String::new();
+    for (kind, members) in &groups {
+        let _ = write!(nav, "<h2>{kind}s</h2><nav>");
+        for (i, (name, entries)) in members.iter().enumerate() {
+            // tag and correspondent names aren't necessarily valid file names
+            let id = if *kind == "year" {
+                name.clone()
+            } else {
+                i.to_string()
+            };
+            let file = format!("{kind}-{id}.html");
+            let _ = write!(
+                nav,
+                "<a href=\"{PAGES_DIR}/{file}\">{} ({})</a> ",
+                escape(name),
+                entries.len()
+            );
+            let page = page(
+                &format!("{kind}: {name}"),
+                "<p><a href=\"../index.html\">all documents</a></p>",
+                entries,
+                &day,
+                output_dir,
+                &pages_dir,
+            );
+            fs::write(pages_dir.join(file), page)?;
+        }
+        nav.push_str("</nav>");
+    }
+
+    let all: Vec<_> = entries.iter().collect();
+    fs::write(
+        output_dir.join("index.html"),
+        page("documents", &nav, &all, &day, output_dir, output_dir),
+    )
+}
+
+/// A page in `page_dir` listing `entries` created on `day`, whose paths are relative to
+/// `output_dir`.
+fn page(
+    title: &str,
+    header: &str,
+    entries: &[&IndexEntry],
+    day: &dyn Fn(&IndexEntry) -> NaiveDate,
+    output_dir: &Path,
+    page_dir: &Path,
+) -> String {
+    let mut rows = String::new();
+    for entry in entries {
+        let title = match entry.paths.get("files").and_then(|p| p.first()) {
+            Some(path) => format!(
+                "<a href=\"{}\">{}</a>",
+                escape(&href(output_dir, page_dir, path)),
+                escape(&entry.title)
+            ),
+            None => escape(&entry.title),
+        };
+        let correspondent = entry.correspondent.as_deref().unwrap_or_default();
+        let document_type = entry.document_type.as_deref().unwrap_or_default();
+        let tags = entry.tags.join(", ");
+        let search = format!(
+            "{} {correspondent} {document_type} {tags} {}",
+            entry.title,
+            day(entry)
+        )
+        .to_lowercase();
+        let _ = write!(
+            rows,
+            "<tr data-search=\"{}\"><td>{}</td><td>{title}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&search),
+            day(entry),
+            escape(correspondent),
+            escape(document_type),
+            escape(&tags),
+        );
+    }
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <style>{STYLE}</style></head><body><h1>{title}</h1>{header}\
+         <input id=\"q\" placeholder=\"search\" autofocus><table><thead><tr><th>created</th>\
+         <th>title</th><th>correspondent</th><th>document type</th><th>tags</th></tr></thead>\
+         <tbody>{rows}</tbody></table><script>{SCRIPT}</script></body></html>\n",
+        title = escape(title)
+    )
+}
+
+/// Link from a page in `page_dir` to the output `path`, which is relative to `output_dir`
+/// unless absolute. The link is relative to the page, unless `path` is on another drive.
+pub(crate) fn href(output_dir: &Path, page_dir: &Path, path: &str) -> String {
+    let target = plain_path(&output_dir.join(path));
+    let page_dir = plain_path(page_dir);
+    if target.components().next() != page_dir.components().next() {
+        return file_url(&target);
+    }
+    // relative_path expects the path of the page itself
+    let relative = relative_path(&page_dir.join("index.html"), &target);
+    encode(&relative.to_string_lossy(), false)
+}
+
+/// The `file:` URL of the absolute `path`, e.g. `file:///C:/Users/...` or
+/// `file://server/share/...` on Windows.
+pub(crate) fn file_url(path: &Path) -> String {
+    let path = plain_path(path).to_string_lossy().replace('\\', "/");
+    match path.starts_with('/') {
+        // UNC paths already start with the `//` of the host
+        true if path.starts_with("//") => format!("file:{}", encode(&path, true)),
+        true => format!("file://{}", encode(&path, true)),
+        false => format!("file:///{}", encode(&path, true)),
+    }
+}
+
+/// `path` with `/` separators and everything but unreserved characters percent-encoded; the
+/// `:` of drive letters is kept if `keep_colons`, in relative links it would start a scheme.
+fn encode(path: &str, keep_colons: bool) -> String {
+    path.replace('\\', "/")
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            b':' if keep_colons => ":".to_owned(),
+            b => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn href_is_relative_to_the_page() {
+        let output_dir = Path::new("/srv/out");
+        let pages_dir = output_dir.join(PAGES_DIR);
+        assert_eq!(
+            href(output_dir, &pages_dir, "files/Invoice? 1:2.pdf"),
+            "../files/Invoice%3F%201%3A2.pdf"
+        );
+        assert_eq!(href(output_dir, output_dir, "files/a.pdf"), "files/a.pdf");
+    }
+
+    #[test]
+    fn file_url_drops_the_verbatim_prefix() {
+        assert_eq!(
+            file_url(Path::new(r"\\?\C:\out\files\a b.pdf")),
+            "file:///C:/out/files/a%20b.pdf"
+        );
+        assert_eq!(
+            file_url(Path::new(r"\\?\UNC\nas\docs\a.pdf")),
+            "file://nas/docs/a.pdf"
+        );
+        assert_eq!(file_url(Path::new("/srv/a.pdf")), "file:///srv/a.pdf");
+    }
+}
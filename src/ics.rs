@@ -0,0 +1,108 @@
+use std::{collections::HashMap, fmt::Write as _, fs, path::Path};
+
+use chrono::Days;
+
+use crate::{
+    html::file_url,
+    index::IndexEntry,
+    organize::View,
+    timezone::{self, TimeZone},
+    Document, Manifest,
+};
+
+/// Writes an iCalendar file to `path` with an all-day event for every document of `entries` on
+/// the day it was created in `timezone`, linking to its copy in `output_dir`.
+pub fn write_calendar(
+    path: &Path,
+    output_dir: &Path,
+    manifest: &Manifest,
+    entries: &[IndexEntry],
+    timezone: &TimeZone,
+) -> std::io::Result<()> {
+    let documents: HashMap<i64, &Document> = manifest.documents.iter().map(|d| (d.pk, d)).collect();
+    // calendar apps only open absolute links
+    let output_dir = fs::canonicalize(output_dir).unwrap_or_else(|_| output_dir.to_owned());
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//parse-paperless-manifest//documents//EN".to_owned(),
+        "X-WR-CALNAME:paperless documents".to_owned(),
+    ];
+    for entry in entries {
+        let Some(doc) = documents.get(&entry.pk) else {
+            continue;
+        };
+        let day = timezone::created(doc, timezone).date_naive();
+        // the stamp only has to change with the document, so unchanged calendars stay so
+        let stamp = doc.modified.unwrap_or(doc.created);
+        let mut description = Vec::new();
+        if let Some(correspondent) = &entry.correspondent {
+            description.push(format!("Correspondent: {correspondent}"));
+        }
+        if let Some(document_type) = &entry.document_type {
+            description.push(format!("Document type: {document_type}"));
+        }
+        let copy = entry
+            .paths
+            .get(View::Files.dir_name())
+            .and_then(|paths| paths.first());
+        if let Some(copy) = copy {
+            description.push(format!("File: {copy}"));
+        }
+
+        lines.push("BEGIN:VEVENT".to_owned());
+        lines.push(format!("UID:paperless-document-{}", doc.pk));
+        lines.push(format!("DTSTAMP:{}", stamp.format("%Y%m%dT%H%M%SZ")));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", day.format("%Y%m%d")));
+        if let Some(next) = day.checked_add_days(Days::new(1)) {
+            lines.push(format!("DTEND;VALUE=DATE:{}", next.format("%Y%m%d")));
+        }
+        lines.push(format!("SUMMARY:{}", escape(&entry.title)));
+        lines.push(format!("DESCRIPTION:{}", escape(&description.join("\n"))));
+        if !entry.tags.is_empty() {
+            let tags: Vec<_> = entry.tags.iter().map(|tag| escape(tag)).collect();
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        // not with output backends, whose copies aren't local
+        if let Some(copy) = copy
+            .map(|copy| output_dir.join(copy))
+            .filter(|c| c.exists())
+        {
+            lines.push(format!("URL:{}", file_url(&copy)));
+        }
+        lines.push("END:VEVENT".to_owned());
+    }
+    lines.push("END:VCALENDAR".to_owned());
+
+    let mut calendar = String::new();
+    for line in lines {
+        let _ = write!(calendar, "{}\r\n", fold(&line));
+    }
+    fs::write(path, calendar)
+}
+
+/// Escapes `text` for a text value: backslashes, separators and line breaks.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Splits `line` into lines of at most 75 bytes, continued lines starting with a space, as
+/// RFC 5545 requires.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    html, ics, markdown,
+    organize::{DocumentOutputs, View, ORIGINALS_DIR},
+    timezone::TimeZone,
+    Document, Manifest,
+};
+
+/// Format of the document index written to the output directory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexFormat {
+    /// `index.csv`, one row per document with a column per view
+    Csv,
+    /// `index.json`, an array of documents
+    Json,
+    /// `index.db`, an SQLite database with tables for documents, tags, correspondents,
+    /// document types and output paths, and a full-text index used by `search`
+    Sqlite,
+    /// `index.html` and an `html/` folder: static pages to browse the documents by tag,
+    /// correspondent and year
+    Html,
+    /// A `vault/` folder with a Markdown note per document, its metadata as front matter and
+    /// its OCR text as the body, to search the archive in Obsidian or Logseq; what you write
+    /// below the marker line ending a note is kept
+    Markdown,
+    /// `documents.ics`, a calendar with an all-day event per document on the day it was
+    /// created
+    Ics,
+}
+
+impl IndexFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            IndexFormat::Csv => "index.csv",
+            IndexFormat::Json => "index.json",
+            IndexFormat::Sqlite => "index.db",
+            IndexFormat::Html => "index.html",
+            IndexFormat::Markdown => markdown::VAULT_DIR,
+            IndexFormat::Ics => "documents.ics",
+        }
+    }
+}
+
+/// An organized document and where it ended up.
+#[derive(Serialize)]
+pub struct IndexEntry {
+    pub pk: i64,
+    pub title: String,
+    pub created: DateTime<Utc>,
+    pub correspondent: Option<String>,
+    pub document_type: Option<String>,
+    pub tags: Vec<String>,
+    pub checksum: String,
+    pub archive_checksum: Option<String>,
+    /// Output paths by view folder (`files`, `by_tag`, ..., `files-originals`), relative to the
+    /// output directory where possible
+    pub paths: BTreeMap<String, Vec<String>>,
+}
+
+impl IndexEntry {
+    pub fn new(
+        doc: &Document,
+        outputs: &DocumentOutputs,
+        original: Option<&DocumentOutputs>,
+        output_dir: &Path,
+    ) -> Self {
+        let relative = |path: &Path| {
+            path.strip_prefix(output_dir)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .into_owned()
+        };
+        let mut paths: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        paths
+            .entry(View::Files.dir_name().to_owned())
+            .or_default()
+            .push(relative(&outputs.target));
+        for (view, link) in &outputs.links {
+            paths.entry(view.clone()).or_default().push(relative(link));
+        }
+        if let Some(original) = original {
+            paths
+                .entry(ORIGINALS_DIR.to_owned())
+                .or_default()
+                .push(relative(&original.target));
+        }
+
+        Self {
+            pk: doc.pk,
+            title: doc.title.clone(),
+            created: doc.created,
+            correspondent: doc.correspondent.as_ref().map(|c| c.name.clone()),
+            document_type: doc.document_type.as_ref().map(|t| t.name.clone()),
+            tags: doc.tags.iter().map(|t| t.name.clone()).collect(),
+            checksum: doc.checksum.clone(),
+            archive_checksum: doc.archive_checksum.clone(),
+            paths,
+        }
+    }
+}
+
+/// Writes `entries` to `output_dir` in every format of `formats`, with the days documents were
+/// created on in `timezone`. The Markdown vault is left to the caller, see [`markdown::notes`].
+pub fn write_index(
+    output_dir: &Path,
+    manifest: &Manifest,
+    entries: &[IndexEntry],
+    formats: &[IndexFormat],
+    timezone: &TimeZone,
+) -> anyhow::Result<()> {
+    for &format in formats {
+        let path = output_dir.join(format.file_name());
+        let result = match format {
+            IndexFormat::Csv => write_file(&path, |w| write_csv(w, entries)),
+            IndexFormat::Json => write_file(&path, |w| {
+                serde_json::to_writer_pretty(&mut *w, entries)?;
+                writeln!(w)
+            }),
+            IndexFormat::Sqlite => write_sqlite(&path, manifest, entries),
+            IndexFormat::Html => {
+                html::write_html(output_dir, manifest, entries, timezone).map_err(Into::into)
+            }
+            IndexFormat::Markdown => continue,
+            IndexFormat::Ics => ics::write_calendar(&path, output_dir, manifest, entries, timezone)
+                .map_err(Into::into),
+        };
+        result.with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE tags (pk INTEGER PRIMARY KEY, name TEXT NOT NULL);
+    CREATE TABLE correspondents (pk INTEGER PRIMARY KEY, name TEXT NOT NULL);
+    CREATE TABLE document_types (pk INTEGER PRIMARY KEY, name TEXT NOT NULL);
+    CREATE TABLE documents (
+        pk INTEGER PRIMARY KEY,
+        title TEXT NOT NULL,
+        created TEXT NOT NULL,
+        correspondent INTEGER REFERENCES correspondents (pk),
+        document_type INTEGER REFERENCES document_types (pk),
+        asn INTEGER,
+        checksum TEXT NOT NULL,
+        archive_checksum TEXT
+    );
+    CREATE TABLE document_tags (
+        document INTEGER NOT NULL REFERENCES documents (pk),
+        tag INTEGER NOT NULL REFERENCES tags (pk),
+        PRIMARY KEY (document, tag)
+    );
+    CREATE TABLE outputs (
+        document INTEGER NOT NULL REFERENCES documents (pk),
+        view TEXT NOT NULL,
+        path TEXT NOT NULL
+    );
+    CREATE VIRTUAL TABLE documents_fts USING fts5 (title, content, correspondent, tags);
+";
+
+/// Writes a fresh SQLite database with the organized documents and all tags, correspondents
+/// and document types of `manifest`.
+fn write_sqlite(path: &Path, manifest: &Manifest, entries: &[IndexEntry]) -> anyhow::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut db = rusqlite::Connection::open(path)?;
+    let tx = db.transaction()?;
+    tx.execute_batch(SQLITE_SCHEMA)?;
+
+    let named = [
+        (
+            "tags",
+            manifest
+                .tags
+                .values()
+                .map(|t| (t.pk, &t.name))
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "correspondents",
+            manifest
+                .correspondents
+                .values()
+                .map(|c| (c.pk, &c.name))
+                .collect(),
+        ),
+        (
+            "document_types",
+            manifest
+                .document_types
+                .values()
+                .map(|t| (t.pk, &t.name))
+                .collect(),
+        ),
+    ];
+    for (table, rows) in named {
+        let mut insert = tx.prepare(&format!("INSERT INTO {table} (pk, name) VALUES (?1, ?2)"))?;
+        for (pk, name) in rows {
+            insert.execute((pk, name))?;
+        }
+    }
+
+    let documents: HashMap<_, _> = manifest.documents.iter().map(|d| (d.pk, d)).collect();
+    {
+        let mut document = tx.prepare(
+            "INSERT INTO documents
+             (pk, title, created, correspondent, document_type, asn, checksum, archive_checksum)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?;
+        let mut tag = tx.prepare("INSERT INTO document_tags (document, tag) VALUES (?1, ?2)")?;
+        let mut output =
+            tx.prepare("INSERT INTO outputs (document, view, path) VALUES (?1, ?2, ?3)")?;
+        let mut text = tx.prepare(
+            "INSERT INTO documents_fts (rowid, title, content, correspondent, tags)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for entry in entries {
+            let Some(doc) = documents.get(&entry.pk) else {
+                continue;
+            };
+            document.execute((
+                doc.pk,
+                &doc.title,
+                doc.created.to_rfc3339(),
+                doc.correspondent.as_ref().map(|c| c.pk),
+                doc.document_type.as_ref().map(|t| t.pk),
+                doc.asn,
+                &doc.checksum,
+                &doc.archive_checksum,
+            ))?;
+            for t in &doc.tags {
+                tag.execute((doc.pk, t.pk))?;
+            }
+            text.execute((
+                doc.pk,
+                &doc.title,
+                &doc.content,
+                &entry.correspondent,
+                entry.tags.join(" "),
+            ))?;
+            for (view, paths) in &entry.paths {
+                for path in paths {
+                    output.execute((doc.pk, view, path))?;
+                }
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn write_csv(writer: impl Write, entries: &[IndexEntry]) -> io::Result<()> {
+    let mut views: Vec<_> = entries.iter().flat_map(|e| e.paths.keys()).collect();
+    views.sort();
+    views.dedup();
+
+    let mut csv = csv::Writer::from_writer(writer);
+    let mut header = vec![
+        "pk",
+        "title",
+        "created",
+        "correspondent",
+        "document_type",
+        "tags",
+        "checksum",
+        "archive_checksum",
+    ];
+    header.extend(views.iter().map(|v| v.as_str()));
+    csv.write_record(&header)?;
+
+    for entry in entries {
+        let mut record = vec![
+            entry.pk.to_string(),
+            entry.title.clone(),
+            entry.created.to_rfc3339(),
+            entry.correspondent.clone().unwrap_or_default(),
+            entry.document_type.clone().unwrap_or_default(),
+            entry.tags.join(";"),
+            entry.checksum.clone(),
+            entry.archive_checksum.clone().unwrap_or_default(),
+        ];
+        record.extend(
+            views
+                .iter()
+                .map(|v| entry.paths.get(*v).map(|p| p.join(";")).unwrap_or_default()),
+        );
+        csv.write_record(&record)?;
+    }
+    csv.flush()
+}
@@ -0,0 +1,57 @@
+pub mod alias;
+pub mod api;
+pub mod archive;
+pub mod backend;
+pub mod checksum;
+pub mod config;
+pub mod diff;
+pub mod duplicates;
+pub mod encrypt;
+pub mod error;
+pub mod export;
+pub mod extract;
+pub mod filter;
+pub mod gpg;
+pub mod graph;
+pub mod grouping;
+pub mod hook;
+pub mod html;
+pub mod ics;
+pub mod index;
+pub mod link;
+pub mod lint;
+pub mod manifest;
+pub mod markdown;
+pub mod metrics;
+pub mod notify;
+pub mod organize;
+pub mod plan;
+pub mod platform;
+pub mod query;
+pub mod remote;
+pub mod report;
+pub mod resume;
+pub mod s3;
+pub mod sanitize;
+pub mod search;
+pub mod sftp;
+pub mod shortcut;
+pub mod sidecar;
+pub mod stats;
+pub mod storage_path;
+pub mod sums;
+pub mod sync;
+pub mod template;
+pub mod timezone;
+pub mod undo;
+pub mod verify;
+pub mod version;
+pub mod watch;
+pub mod webdav;
+
+pub use backend::{Entry, EntryKind, LocalBackend, OutputBackend};
+pub use error::Error;
+pub use manifest::{
+    parse_manifest, parse_manifests, Correspondent, CustomFieldValue, Document, DocumentType,
+    Manifest, Note, StoragePath, Tag, User,
+};
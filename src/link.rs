@@ -0,0 +1,81 @@
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{platform::symlink_file, shortcut::shortcut};
+
+/// How secondary views (`by_tag`, `by_year`, ...) refer to the copy in `files/`.
+#[derive(
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Debug,
+    clap::ValueEnum,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkMode {
+    /// Symbolic links (need Developer Mode or elevated privileges on Windows)
+    #[default]
+    Symlink,
+    /// Hard links (no extra space, no privileges, same volume only)
+    Hardlink,
+    /// Independent copies
+    Copy,
+    /// Windows shortcuts (`Invoice.pdf.lnk`), which need no privileges but are only followed
+    /// by Explorer
+    Shortcut,
+}
+
+impl LinkMode {
+    /// Makes `link` refer to the file `original` according to this mode.
+    pub fn link(self, original: &Path, link: &Path) -> io::Result<()> {
+        match self {
+            LinkMode::Symlink => symlink_file(original, link),
+            LinkMode::Hardlink => fs::hard_link(original, link),
+            LinkMode::Copy => fs::copy(original, link).map(|_| ()),
+            LinkMode::Shortcut => fs::write(link, shortcut(original, link)?),
+        }
+    }
+}
+
+/// Tries whether links of `mode` can be created in `dir` by linking a scratch file; symlinks
+/// are created dangling.
+pub fn probe(mode: LinkMode, dir: &Path) -> io::Result<()> {
+    let original = dir.join(".paperless-organize-probe");
+    let link = dir.join(".paperless-organize-probe.link");
+    let result = fs::create_dir_all(dir)
+        .and_then(|()| match mode {
+            LinkMode::Symlink => Ok(()),
+            _ => fs::write(&original, b""),
+        })
+        .and_then(|()| {
+            let _ = fs::remove_file(&link);
+            mode.link(&original, &link)
+        });
+    let _ = fs::remove_file(&link);
+    let _ = fs::remove_file(&original);
+    result
+}
+
+/// The path leading from a symlink at `link` to `target`, e.g. `../../files/Invoice.pdf` for
+/// `by_tag/invoice/Invoice.pdf` and `files/Invoice.pdf`. Both paths have to be relative to the
+/// same directory; the path is worked out lexically.
+pub fn relative_path(link: &Path, target: &Path) -> PathBuf {
+    let from: Vec<_> = link
+        .parent()
+        .unwrap_or(Path::new(""))
+        .components()
+        .collect();
+    let to: Vec<_> = target.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    from[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .chain(to[common..].iter().copied())
+        .collect()
+}
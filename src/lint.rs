@@ -0,0 +1,477 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use chrono::DateTime;
+use serde_json::Value;
+
+use crate::{
+    manifest::custom_field_value_key,
+    version::{upgrade_object, ExporterVersion},
+};
+
+/// How bad a [`Diagnostic`] is.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    /// The manifest is broken: organizing fails or loses data, and so would an import
+    Error,
+    /// Something this tool doesn't understand and ignores, e.g. from a newer paperless version
+    Warning,
+}
+
+/// A problem [`lint`] found with a manifest object.
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The manifest file the object is in
+    pub file: PathBuf,
+    /// The position of the object in `file`
+    pub index: usize,
+    pub model: Option<String>,
+    pub pk: Option<i64>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: object #{}", self.file.display(), self.index)?;
+        match (&self.model, self.pk) {
+            (Some(model), Some(pk)) => write!(f, " ({model}, pk {pk})")?,
+            (Some(model), None) => write!(f, " ({model})")?,
+            (None, _) => {}
+        }
+        write!(f, ": {severity}: {}", self.message)
+    }
+}
+
+/// What the value of a field has to be.
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    Integer,
+    /// An RFC 3339 date and time, e.g. `2024-03-01T12:00:00Z`
+    Date,
+    /// The pk of an object of the model
+    Ref(&'static str),
+    /// A list of pks of objects of the model
+    Refs(&'static str),
+    /// A pk or a natural key, a list of strings
+    Key,
+}
+
+struct FieldRule {
+    name: &'static str,
+    kind: Kind,
+    /// Whether it has to be present and not `null`
+    required: bool,
+}
+
+const fn required(name: &'static str, kind: Kind) -> FieldRule {
+    FieldRule {
+        name,
+        kind,
+        required: true,
+    }
+}
+
+const fn optional(name: &'static str, kind: Kind) -> FieldRule {
+    FieldRule {
+        name,
+        kind,
+        required: false,
+    }
+}
+
+const DOCUMENT: &str = "documents.document";
+
+/// The fields checked for each model this tool reads; objects of other models are left alone.
+const SCHEMA: [(&str, &[FieldRule]); 11] = [
+    ("documents.tag", &[required("name", Kind::Text)]),
+    ("documents.correspondent", &[required("name", Kind::Text)]),
+    ("documents.documenttype", &[required("name", Kind::Text)]),
+    (
+        "documents.storagepath",
+        &[required("name", Kind::Text), required("path", Kind::Text)],
+    ),
+    (
+        DOCUMENT,
+        &[
+            required("title", Kind::Text),
+            required("created", Kind::Date),
+            optional("added", Kind::Date),
+            optional("modified", Kind::Date),
+            optional("correspondent", Kind::Ref("documents.correspondent")),
+            optional("document_type", Kind::Ref("documents.documenttype")),
+            optional("storage_path", Kind::Ref("documents.storagepath")),
+            required("tags", Kind::Refs("documents.tag")),
+            required("checksum", Kind::Text),
+            optional("archive_checksum", Kind::Text),
+            optional("archive_serial_number", Kind::Integer),
+            optional("mime_type", Kind::Text),
+            optional("content", Kind::Text),
+            optional("owner", Kind::Ref("auth.user")),
+            optional("deleted_at", Kind::Date),
+            optional("storage_type", Kind::Text),
+        ],
+    ),
+    (
+        "documents.note",
+        &[
+            required("document", Kind::Ref(DOCUMENT)),
+            required("note", Kind::Text),
+            required("created", Kind::Date),
+            optional("user", Kind::Ref("auth.user")),
+            optional("deleted_at", Kind::Date),
+        ],
+    ),
+    (
+        "documents.customfield",
+        &[
+            required("name", Kind::Text),
+            required("data_type", Kind::Text),
+        ],
+    ),
+    (
+        "documents.customfieldinstance",
+        &[
+            required("document", Kind::Ref(DOCUMENT)),
+            required("field", Kind::Ref("documents.customfield")),
+        ],
+    ),
+    ("auth.user", &[required("username", Kind::Text)]),
+    ("auth.permission", &[required("codename", Kind::Text)]),
+    (
+        "guardian.userobjectpermission",
+        &[
+            required("permission", Kind::Key),
+            required("user", Kind::Key),
+            required("object_pk", Kind::Text),
+        ],
+    ),
+];
+
+/// The fields of documents outside of `fields`, naming the exported files.
+const DOCUMENT_FILES: [FieldRule; 3] = [
+    required("__exported_file_name__", Kind::Text),
+    optional("__exported_archive_name__", Kind::Text),
+    optional("__exported_thumbnail_name__", Kind::Text),
+];
+
+/// Checks the objects of the manifest `files` (the main manifest and, for split exports, the
+/// per-document ones) against the schema of paperless' exports: required fields and their
+/// types, RFC 3339 dates, pks used twice and references to objects that aren't exported.
+///
+/// Objects are checked as they are parsed, after upgrading them from the layout of the
+/// exporter's `version` to that of current releases.
+pub fn lint(files: &[(PathBuf, Vec<Value>)], version: Option<ExporterVersion>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let files: Vec<(&PathBuf, Vec<Value>)> = files
+        .iter()
+        .map(|(file, objects)| {
+            let mut objects = objects.clone();
+            for object in &mut objects {
+                upgrade_object(version, object);
+            }
+            (file, objects)
+        })
+        .collect();
+
+    // where each pk is defined, per model
+    let mut pks: HashMap<&str, HashMap<i64, (&Path, usize)>> = HashMap::new();
+    for (file, objects) in &files {
+        for (index, object) in objects.iter().enumerate() {
+            let Some(model) = object["model"].as_str() else {
+                continue;
+            };
+            let (Some(pk), true) = (object["pk"].as_i64(), rules(model).is_some()) else {
+                continue;
+            };
+            if let Some((other_file, other)) =
+                pks.entry(model).or_default().insert(pk, (file, index))
+            {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    file: file.to_path_buf(),
+                    index,
+                    model: Some(model.to_owned()),
+                    pk: Some(pk),
+                    message: format!(
+                        "duplicate pk, also used by object #{other}{}",
+                        match other_file == file.as_path() {
+                            true => String::new(),
+                            false => format!(" in {}", other_file.display()),
+                        }
+                    ),
+                });
+            }
+        }
+    }
+    let exists = |model: &str, pk: i64| pks.get(model).is_some_and(|pks| pks.contains_key(&pk));
+
+    for (file, objects) in &files {
+        for (index, object) in objects.iter().enumerate() {
+            let model = object["model"].as_str();
+            let pk = object["pk"].as_i64();
+            let mut report = |severity, message| {
+                diagnostics.push(Diagnostic {
+                    severity,
+                    file: file.to_path_buf(),
+                    index,
+                    model: model.map(str::to_owned),
+                    pk,
+                    message,
+                })
+            };
+            let Some(object) = object.as_object() else {
+                report(Severity::Error, "not a JSON object".to_owned());
+                continue;
+            };
+            let Some(model) = model else {
+                report(Severity::Error, "no `model`".to_owned());
+                continue;
+            };
+            let Some(rules) = rules(model) else {
+                continue;
+            };
+            if pk.is_none() {
+                report(
+                    Severity::Error,
+                    match object.get("pk") {
+                        None => "no `pk`".to_owned(),
+                        Some(pk) => format!("`pk` is not an integer: {pk}"),
+                    },
+                );
+            }
+            let Some(fields) = object.get("fields").and_then(Value::as_object) else {
+                report(Severity::Error, "no `fields` object".to_owned());
+                continue;
+            };
+
+            let mut checks: Vec<(String, &FieldRule, Option<&Value>)> = rules
+                .iter()
+                .map(|rule| (format!("fields.{}", rule.name), rule, fields.get(rule.name)))
+                .collect();
+            if model == DOCUMENT {
+                checks.extend(
+                    DOCUMENT_FILES
+                        .iter()
+                        .map(|rule| (rule.name.to_owned(), rule, object.get(rule.name))),
+                );
+            }
+            for (name, rule, value) in checks {
+                let value = match value {
+                    None | Some(Value::Null) if rule.required => {
+                        report(Severity::Error, format!("`{name}` is missing"));
+                        continue;
+                    }
+                    None | Some(Value::Null) => continue,
+                    Some(value) => value,
+                };
+                for message in check(&name, rule.kind, value, &exists) {
+                    report(Severity::Error, message);
+                }
+            }
+
+            if model == "documents.customfield" {
+                if let Some(data_type) = fields.get("data_type").and_then(Value::as_str) {
+                    if custom_field_value_key(data_type).is_none() {
+                        report(
+                            Severity::Warning,
+                            format!("unknown custom field data type `{data_type}`, its values are ignored"),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    diagnostics.sort_by_key(|d| (files.iter().position(|(file, _)| **file == d.file), d.index));
+    diagnostics
+}
+
+fn rules(model: &str) -> Option<&'static [FieldRule]> {
+    SCHEMA
+        .iter()
+        .find(|(name, _)| *name == model)
+        .map(|(_, rules)| *rules)
+}
+
+/// What's wrong with `value` of the field `name`, supposed to be of `kind`.
+fn check(
+    name: &str,
+    kind: Kind,
+    value: &Value,
+    exists: &impl Fn(&str, i64) -> bool,
+) -> Vec<String> {
+    let unknown = |model: &str, pk: i64| match exists(model, pk) {
+        true => Ok(()),
+        false => Err(format!(
+            "`{name}` refers to {model} {pk}, which isn't exported"
+        )),
+    };
+    let result = match kind {
+        Kind::Text if value.is_string() => Ok(()),
+        Kind::Text => Err(format!("`{name}` is not a string: {value}")),
+        Kind::Integer if value.is_i64() => Ok(()),
+        Kind::Integer => Err(format!("`{name}` is not an integer: {value}")),
+        Kind::Date => match value.as_str() {
+            Some(date) if DateTime::parse_from_rfc3339(date).is_ok() => Ok(()),
+            _ => Err(format!("`{name}` is not an RFC 3339 date: {value}")),
+        },
+        Kind::Ref(model) => match value.as_i64() {
+            Some(pk) => unknown(model, pk),
+            None => Err(format!("`{name}` is not a pk: {value}")),
+        },
+        Kind::Refs(model) => {
+            let Some(values) = value.as_array() else {
+                return vec![format!("`{name}` is not a list of pks: {value}")];
+            };
+            let mut seen = HashSet::new();
+            return values
+                .iter()
+                .filter_map(|value| {
+                    let Some(pk) = value.as_i64() else {
+                        return Some(format!("`{name}` contains {value}, which is not a pk"));
+                    };
+                    match seen.insert(pk) {
+                        true => unknown(model, pk).err(),
+                        false => Some(format!("`{name}` contains {pk} twice")),
+                    }
+                })
+                .collect();
+        }
+        Kind::Key => match value {
+            Value::Number(n) if n.is_i64() => Ok(()),
+            Value::Array(key) if key.iter().all(Value::is_string) => Ok(()),
+            _ => Err(format!(
+                "`{name}` is neither a pk nor a natural key: {value}"
+            )),
+        },
+    };
+    result.err().into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn lint_manifest(objects: Value) -> Vec<String> {
+        let Value::Array(objects) = objects else {
+            panic!("a manifest is a list");
+        };
+        lint(&[(PathBuf::from("manifest.json"), objects)], None)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    fn document(pk: i64, fields: Value) -> Value {
+        let mut document = json!({
+            "model": DOCUMENT,
+            "pk": pk,
+            "fields": {
+                "title": "Invoice", "created": "2024-03-01T12:00:00Z", "tags": [1],
+                "checksum": "abc",
+            },
+            "__exported_file_name__": format!("{pk:07}.pdf"),
+        });
+        for (name, value) in fields.as_object().unwrap() {
+            document["fields"][name] = value.clone();
+        }
+        document
+    }
+
+    #[test]
+    fn a_clean_manifest_has_no_diagnostics() {
+        let diagnostics = lint_manifest(json!([
+            {"model": "documents.tag", "pk": 1, "fields": {"name": "invoice"}},
+            {"model": "documents.correspondent", "pk": 1, "fields": {"name": "ACME"}},
+            {"model": "auth.user", "pk": 1, "fields": {"username": "admin"}},
+            {"model": "documents.customfield", "pk": 1,
+             "fields": {"name": "Due", "data_type": "date"}},
+            document(1, json!({"correspondent": 1, "owner": 1})),
+            {"model": "documents.customfieldinstance", "pk": 1,
+             "fields": {"document": 1, "field": 1, "value_date": "2024-03-31"}},
+            {"model": "guardian.userobjectpermission", "pk": 1,
+             "fields": {"permission": ["view_document", "documents", "document"], "user": 1,
+                        "object_pk": "1"}},
+            {"model": "documents.savedview", "fields": {}},
+        ]));
+        assert_eq!(diagnostics, Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_check_reports_its_problem() {
+        let diagnostics = lint_manifest(json!([
+            "tag",
+            {"pk": 1, "fields": {}},
+            {"model": "documents.tag", "fields": {"name": "a"}},
+            {"model": "documents.tag", "pk": "2", "fields": {"name": "b"}},
+            {"model": "documents.tag", "pk": 3},
+            {"model": "documents.tag", "pk": 4, "fields": {"name": 4}},
+            {"model": "documents.tag", "pk": 4, "fields": {"name": "d"}},
+            {"model": "documents.customfield", "pk": 1,
+             "fields": {"name": "Due", "data_type": "hologram"}},
+            document(1, json!({
+                "title": null, "created": "yesterday", "archive_serial_number": "17",
+                "correspondent": 9, "owner": "admin", "tags": [4, 4, "x", 5],
+            })),
+            {"model": DOCUMENT, "pk": 2, "fields": {
+                "title": "Receipt", "created": "2024-03-01T12:00:00Z", "tags": 4,
+                "checksum": "def"}},
+            {"model": "guardian.userobjectpermission", "pk": 1,
+             "fields": {"permission": [1], "user": 1, "object_pk": 1}},
+        ]));
+        let expected = [
+            "#0: error: not a JSON object",
+            "#1: error: no `model`",
+            "#2 (documents.tag): error: no `pk`",
+            "#3 (documents.tag): error: `pk` is not an integer: \"2\"",
+            "#4 (documents.tag, pk 3): error: no `fields` object",
+            "#5 (documents.tag, pk 4): error: `fields.name` is not a string: 4",
+            "#6 (documents.tag, pk 4): error: duplicate pk, also used by object #5",
+            "#7 (documents.customfield, pk 1): warning: unknown custom field data type `hologram`, \
+             its values are ignored",
+            "#8 (documents.document, pk 1): error: `fields.title` is missing",
+            "#8 (documents.document, pk 1): error: `fields.created` is not an RFC 3339 date: \
+             \"yesterday\"",
+            "#8 (documents.document, pk 1): error: `fields.correspondent` refers to \
+             documents.correspondent 9, which isn't exported",
+            "#8 (documents.document, pk 1): error: `fields.tags` contains 4 twice",
+            "#8 (documents.document, pk 1): error: `fields.tags` contains \"x\", which is not a pk",
+            "#8 (documents.document, pk 1): error: `fields.tags` refers to documents.tag 5, which \
+             isn't exported",
+            "#8 (documents.document, pk 1): error: `fields.archive_serial_number` is not an \
+             integer: \"17\"",
+            "#8 (documents.document, pk 1): error: `fields.owner` is not a pk: \"admin\"",
+            "#9 (documents.document, pk 2): error: `fields.tags` is not a list of pks: 4",
+            "#9 (documents.document, pk 2): error: `__exported_file_name__` is missing",
+            "#10 (guardian.userobjectpermission, pk 1): error: `fields.permission` is neither a pk \
+             nor a natural key: [1]",
+            "#10 (guardian.userobjectpermission, pk 1): error: `fields.object_pk` is not a string: 1",
+        ]
+        .map(|message| format!("manifest.json: object {message}"));
+        assert_eq!(diagnostics, expected);
+    }
+
+    #[test]
+    fn pks_are_unique_across_split_manifests() {
+        let tag = json!({"model": "documents.tag", "pk": 1, "fields": {"name": "invoice"}});
+        let files = [
+            (PathBuf::from("manifest.json"), vec![tag.clone()]),
+            (PathBuf::from("json/0000001-manifest.json"), vec![tag]),
+        ];
+        let diagnostics: Vec<_> = lint(&files, None).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            diagnostics,
+            ["json/0000001-manifest.json: object #0 (documents.tag, pk 1): error: duplicate pk, \
+              also used by object #0 in manifest.json"]
+        );
+    }
+}
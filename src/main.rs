@@ -1,196 +1,872 @@
 use std::{
-    borrow::Borrow,
     collections::HashMap,
-    fs::{copy, create_dir_all, remove_dir_all, File},
-    io::BufReader,
-    os::windows::fs::symlink_file,
-    path::PathBuf,
+    fs::{self, OpenOptions},
+    io::{self, IsTerminal},
+    mem,
+    path::Path,
+    process::{self, ExitCode},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    time::{Duration, Instant},
 };
 
-use chrono::{DateTime, Datelike, Utc};
+use anyhow::{bail, Context};
+use clap::Parser;
+use cli::{Cli, Command, Export, ExportArgs, LayoutArgs, LogArgs, LogFormat, OrganizeArgs};
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use parse_paperless_manifest::{
+    api,
+    archive::ArchiveOptions,
+    backend,
+    config::{CustomViewConfig, ExcludeConfig},
+    diff::{Diff, DiffFormat},
+    duplicates::{self, find_duplicates, DuplicatesFormat},
+    encrypt::{Encryption, ENCRYPTED_DIR},
+    export::{self, ExportSource},
+    extract::extract,
+    filter::{DocumentFilter, ExcludeRules},
+    grouping::{AmountBuckets, Grouping},
+    hook::Hook,
+    lint::{lint, Severity},
+    metrics::write_metrics,
+    notify::{Notifier, RunReport},
+    organize::{clean, organize, CustomView, OrganizeOptions, Summary, View, MIN_PATH_LENGTH},
+    plan::Plan,
+    query::Query,
+    remote::Remote,
+    sanitize::Sanitizer,
+    search::search,
+    stats::{Stats, StatsFormat},
+    template::FilenameTemplate,
+    timezone::parse_timezone,
+    undo::undo,
+    verify::{check_export, verify_export, verify_outputs, verify_sums, Problem},
+    version::NEWEST_TESTED,
+    watch::FileWatcher,
+    Error, Manifest,
+};
 
-#[derive(Clone)]
-struct Tag {
-    pk: i64,
-    name: String,
-}
+use tracing::{debug, error, info, warn, Level};
 
-#[derive(Clone)]
-struct Correspondent {
-    pk: i64,
-    name: String, // fields[].name
-}
+mod cli;
 
-struct Document {
-    pk: i64,
-    file_name: String,                    // __exported_file_name__
-    archive_name: String,                 // __exported_archive_name__
-    created: DateTime<Utc>,               // fields[].created
-    correspondent: Option<Correspondent>, // fields[].correspondent
-    tags: Vec<Tag>,                       // fields[].tags[]
-}
+/// Exit code of runs that completed, but failed for some documents.
+const EXIT_PARTIAL_FAILURE: u8 = 2;
+/// Exit code of runs that stopped as the manifest is malformed or inconsistent.
+const EXIT_INVALID_MANIFEST: u8 = 3;
 
-fn main() -> anyhow::Result<()> {
-    let root_dir = r"C:\repos\paperless-ngx\docker\compose\export\";
+/// A run that completed, but not for every document.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct PartialFailure(String);
 
-    for kind in ["files", "by_tag", "by_year", "by_correspondent"] {
-        let _ = remove_dir_all(format!(r"{root_dir}\{kind}"));
+/// A manifest that is invalid or of an untested layout, without a more specific error.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct InvalidManifest(String);
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Err(error) = init_logging(&cli.log) {
+        eprintln!("Error: {error:?}");
+        return ExitCode::FAILURE;
+    }
+    match run(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            error!("{error:?}");
+            exit_code(&error)
+        }
     }
+}
 
-    let mut tags = HashMap::new();
-    let mut correspondents = HashMap::new();
-    let mut documents = HashMap::new();
+fn exit_code(error: &anyhow::Error) -> ExitCode {
+    let invalid_manifest = error.chain().any(|cause| {
+        cause
+            .downcast_ref::<Error>()
+            .is_some_and(Error::is_invalid_manifest)
+    });
+    if error.is::<PartialFailure>() {
+        ExitCode::from(EXIT_PARTIAL_FAILURE)
+    } else if invalid_manifest || error.is::<InvalidManifest>() {
+        ExitCode::from(EXIT_INVALID_MANIFEST)
+    } else {
+        ExitCode::FAILURE
+    }
+}
 
-    let manifest_path: PathBuf = PathBuf::from_iter(&[root_dir, "manifest.json"])
-        .iter()
-        .collect();
+fn init_logging(args: &LogArgs) -> anyhow::Result<()> {
+    let level = match args.verbose {
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false);
+    match &args.log_file {
+        None => match args.log_format {
+            LogFormat::Text => subscriber
+                .without_time()
+                .with_ansi(io::stderr().is_terminal())
+                .with_writer(io::stderr)
+                .init(),
+            LogFormat::Json => subscriber.json().with_writer(io::stderr).init(),
+        },
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open log file {}", path.display()))?;
+            let subscriber = subscriber.with_ansi(false).with_writer(Mutex::new(file));
+            match args.log_format {
+                LogFormat::Text => subscriber.init(),
+                LogFormat::Json => subscriber.json().init(),
+            }
+        }
+    }
+    Ok(())
+}
 
-    if let Ok(manifest_file) = File::open(manifest_path) {
-        let objects: serde_json::Value = serde_json::from_reader(BufReader::new(manifest_file))?;
-        for object in objects.as_array().unwrap() {
-            let pk = object["pk"].as_i64().unwrap();
-            let fields = object["fields"].as_object().unwrap();
-            match object["model"].as_str().unwrap() {
-                "documents.tag" => {
-                    let name = fields
-                        .iter()
-                        .find(|&(k, _)| k == "name")
-                        .expect("tag has name");
-                    tags.insert(
-                        pk,
-                        Tag {
-                            pk,
-                            name: name.1.as_str().unwrap().into(),
-                        },
-                    );
-                }
-                "documents.correspondent" => {
-                    let name = fields
-                        .iter()
-                        .find(|&(k, _)| k == "name")
-                        .expect("correspondent has name");
-                    correspondents.insert(
-                        pk,
-                        Correspondent {
-                            pk,
-                            name: name.1.as_str().unwrap().into(),
-                        },
-                    );
+fn run(command: Command) -> anyhow::Result<()> {
+    match command {
+        Command::Organize(args) => run_organize(args),
+        Command::Verify(args) => run_verify(args),
+        Command::VerifyExport(args) => {
+            let export = args.resolve()?;
+            let problems = verify_export(&load_manifest(&export)?, &export.source);
+            report_problems(&problems, "the export")?;
+            println!("all exported files match their checksums.");
+            Ok(())
+        }
+        Command::VerifyChecksums(args) => {
+            let export = args.resolve()?;
+            let (checked, problems) = verify_sums(&export.output_dir)?;
+            report_problems(&problems, "the checksummed copies")?;
+            println!("all {checked} checksummed files are intact.");
+            Ok(())
+        }
+        Command::Lint(args) => run_lint(args),
+        Command::Stats {
+            export,
+            format,
+            timezone,
+        } => run_stats(export, format, timezone.as_deref()),
+        Command::Diff { old, new, format } => run_diff(&old, &new, format),
+        Command::Extract {
+            export,
+            query,
+            timezone,
+        } => run_extract(export, &query, timezone.as_deref()),
+        Command::Duplicates {
+            export,
+            hash_files,
+            format,
+        } => {
+            let export = export.resolve()?;
+            let manifest = load_manifest(&export)?;
+            let groups = find_duplicates(&manifest, hash_files.then_some(&export.source));
+            match format {
+                DuplicatesFormat::Table => print!("{}", duplicates::table(&groups)),
+                DuplicatesFormat::Json => println!("{}", serde_json::to_string_pretty(&groups)?),
+            }
+            Ok(())
+        }
+        Command::Search {
+            export,
+            query,
+            limit,
+        } => {
+            let export = export.resolve()?;
+            for hit in search(&export.output_dir, &query, limit)? {
+                println!("{} (document {})", hit.title, hit.pk);
+                if !hit.snippet.is_empty() {
+                    println!("  {}", hit.snippet.replace('\n', " "));
                 }
-                "documents.document" => {
-                    let created = DateTime::parse_from_rfc3339(
-                        fields
-                            .iter()
-                            .find(|&(k, _)| k == "created")
-                            .expect("doc has created")
-                            .1
-                            .as_str()
-                            .expect("created has str value"),
-                    )
-                    .expect("has rfc3339 date");
-
-                    let correspondent = fields
-                        .iter()
-                        .find(|&(k, _)| k == "correspondent")
-                        .expect("doc has correspondent")
-                        .1
-                        .as_i64()
-                        .expect("created has str value");
-
-                    let tags_obj = fields
-                        .iter()
-                        .find(|&(k, _)| k == "tags")
-                        .expect("doc has tags")
-                        .1
-                        .as_array()
-                        .expect("tags has array value");
-
-                    documents.insert(
-                        pk,
-                        Document {
-                            pk,
-                            file_name: object["__exported_file_name__"].as_str().unwrap().into(), // __exported_file_name__
-                            archive_name: object["__exported_archive_name__"]
-                                .as_str()
-                                .unwrap_or(object["__exported_file_name__"].as_str().unwrap())
-                                .into(), // __exported_archive_name__
-                            created: created.into(), // fields[].created
-                            correspondent: correspondents.get(&correspondent).cloned(), // fields[].correspondent
-                            tags: tags_obj
-                                .iter()
-                                .map(|t| tags.get(&t.as_i64().unwrap()).unwrap())
-                                .cloned()
-                                .collect(), // fields[].tags[]
-                        },
-                    );
+                for (view, path) in hit.paths {
+                    println!("  {view}: {}", export.output_dir.join(path).display());
                 }
-                _ => {}
             }
+            Ok(())
+        }
+        Command::Undo(export) => {
+            let export = export.resolve()?;
+            let reverted = undo(&export.output_dir)?;
+            println!("reverted {reverted} changes.");
+            Ok(())
+        }
+        Command::Clean { export, views } => {
+            let export = export.resolve()?;
+            // explicitly listed views leave the custom ones alone
+            let (views, custom_views) = match views {
+                Some(views) => (views, Vec::new()),
+                None => (
+                    export.config.views.unwrap_or_else(|| View::ALL.to_vec()),
+                    custom_views(
+                        export.config.custom_views,
+                        &export.config.custom_field_views,
+                    )?,
+                ),
+            };
+            let removed = clean(&export.output_dir, &views, &custom_views)?;
+            println!("removed {removed} views.");
+            Ok(())
         }
     }
+}
 
-    let mut num_skipped = 0u64;
-    let mut num_copied = 0u64;
-
-    for (_, doc) in documents {
-        if doc
-            .tags
-            .iter()
-            .map(|t| t.name.as_str())
-            .any(|t| ["fine", "legal", "private"].contains(&t) || t.ends_with("2"))
-        {
-            num_skipped += 1;
-            println!(
-                "skipping {} ({})",
-                doc.archive_name,
-                doc.tags
-                    .iter()
-                    .map(|t| t.clone().name)
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            );
-        } else {
-            macro_rules! path_from_root {
-                ($($xprs:expr),*) => {
-                    PathBuf::from_iter(&[root_dir, $($xprs),*])
-                        .iter()
-                        .collect::<PathBuf>()
-                }
+/// Warns about exports of paperless releases the tool wasn't tested against, or refuses
+/// them with `--strict-version`.
+fn check_version(export: &Export) -> anyhow::Result<()> {
+    let Some(version) = export.source.exporter_version() else {
+        return Ok(());
+    };
+    if !version.is_untested() {
+        return Ok(());
+    }
+    let message = format!(
+        "the export was written by paperless {version}, newer than the latest tested release \
+         {NEWEST_TESTED}; its manifest may have changed in ways that are parsed incorrectly"
+    );
+    if export.strict_version {
+        return Err(InvalidManifest(message).into());
+    }
+    warn!("{message}");
+    Ok(())
+}
+
+fn load_manifest(export: &Export) -> anyhow::Result<Manifest> {
+    check_version(export)?;
+    let what = match (&export.manifest, &export.source) {
+        (Some(manifest), _) if manifest == Path::new(export::STDIN_MANIFEST) => {
+            "the manifest from stdin".to_owned()
+        }
+        (Some(manifest), _) => format!("manifest {}", manifest.display()),
+        (None, ExportSource::Api(client)) => format!("documents from {}", client.url().display()),
+        (None, source) => format!(
+            "manifest {}",
+            source.display_path(&source.manifest_name()).display()
+        ),
+    };
+    let mut manifest = export
+        .source
+        .load_manifest(export.manifest.as_deref(), export.strict)
+        .with_context(|| format!("failed to load {what}"))?;
+    if let Some(version) = manifest.version {
+        debug!("exported by paperless {version}");
+    }
+    for error in &manifest.malformed {
+        warn!("left out of the manifest: {error}");
+    }
+    export.config.aliases.apply(&mut manifest);
+    Ok(manifest)
+}
+
+fn custom_views(
+    config: Vec<CustomViewConfig>,
+    custom_fields: &[String],
+) -> anyhow::Result<Vec<CustomView>> {
+    let mut views: Vec<CustomView> = Vec::new();
+    let declared = config.into_iter().map(|CustomViewConfig { name, group }| {
+        let grouping = group
+            .parse()
+            .with_context(|| format!("invalid grouping for the custom view `{name}`"))?;
+        anyhow::Ok((name, grouping))
+    });
+    // `Vehicle` becomes `by_vehicle/`, `Tax ID` `by_tax_id/`
+    let generated = custom_fields.iter().map(|field| {
+        let name: String = field
+            .trim()
+            .chars()
+            .map(|c| match c.is_alphanumeric() {
+                true => c.to_ascii_lowercase(),
+                false => '_',
+            })
+            .collect();
+        Ok((format!("by_{name}"), Grouping::custom_field(field)))
+    });
+    for view in declared.chain(generated) {
+        let (name, grouping) = view?;
+        if views.iter().any(|v| v.name == name) {
+            bail!("the custom view `{name}` is declared twice");
+        }
+        views.push(CustomView::new(name, grouping)?);
+    }
+    Ok(views)
+}
+
+/// Merges the layout options from the command line with the config file, the former winning.
+fn organize_options(args: LayoutArgs, export: Export) -> anyhow::Result<OrganizeOptions> {
+    let config = export.config;
+
+    let ExcludeConfig {
+        tags,
+        tag_patterns,
+        tag_globs,
+    } = config.exclude;
+    let configured = tags.is_some() || tag_patterns.is_some() || tag_globs.is_some();
+    let mut exclude_tags = tags.unwrap_or_default();
+    exclude_tags.extend(args.exclude_tag);
+    let mut exclude_patterns = tag_patterns.unwrap_or_default();
+    exclude_patterns.extend(args.exclude_tag_pattern);
+    let mut exclude_globs = tag_globs.unwrap_or_default();
+    exclude_globs.extend(args.exclude_tag_glob);
+    let exclude = if configured
+        || !exclude_tags.is_empty()
+        || !exclude_patterns.is_empty()
+        || !exclude_globs.is_empty()
+    {
+        ExcludeRules::new(exclude_tags, &exclude_patterns, &exclude_globs)?
+    } else if args.no_default_excludes {
+        ExcludeRules::none()
+    } else {
+        ExcludeRules::default()
+    };
+    let mut recipients = config.encryption.recipients;
+    recipients.extend(args.encrypt_to);
+    let encryption = match recipients.is_empty() {
+        true => None,
+        false => Some(Encryption::new(&recipients)?),
+    };
+    let s3_endpoint = args.s3_endpoint.or(config.s3_endpoint);
+    let remote = args
+        .remote
+        .or(config.remote)
+        .map(|url| Remote::new(&url, s3_endpoint.as_deref()))
+        .transpose()?;
+    let restricted_dir = match args.restricted_dir.or(config.restricted_dir) {
+        None if encryption.is_some() => Some(export.output_dir.join(ENCRYPTED_DIR)),
+        dir => dir,
+    };
+
+    let or_config =
+        |cli: Vec<String>, config: Vec<String>| if cli.is_empty() { config } else { cli };
+    let query = match args.query {
+        Some(query) => Some(query),
+        None => config
+            .include
+            .query
+            .as_deref()
+            .map(str::parse)
+            .transpose()?,
+    };
+    let filename_template = match args.filename_template {
+        Some(template) => Some(template),
+        None if args.title_names => Some(FilenameTemplate::title()),
+        None => match config.filename_template.as_deref() {
+            Some(template) => Some(template.parse()?),
+            None if config.title_names.unwrap_or(false) => Some(FilenameTemplate::title()),
+            None => None,
+        },
+    };
+    let tax_year_start = match args.tax_year_start {
+        Some(start) => start,
+        None => config
+            .tax_year_start
+            .as_deref()
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default(),
+    };
+    let unassigned = || "unassigned".to_owned();
+    let max_path_length = args.max_path_length.or(config.max_path_length);
+    if let Some(length) = max_path_length.filter(|&l| l < MIN_PATH_LENGTH) {
+        bail!("paths can't be shortened to {length} characters, at least {MIN_PATH_LENGTH} are needed");
+    }
+    let archive = match args.archive.or(config.archive) {
+        Some(format) => {
+            let view = args
+                .archive_by
+                .or(config.archive_by)
+                .unwrap_or(View::ByYear);
+            if view == View::Files {
+                bail!("the files view has no folders to archive");
+            }
+            let level = args.archive_level.or(config.archive_level);
+            if let Some(level) = level.filter(|l| !format.levels().contains(l)) {
+                let levels = format.levels();
+                bail!(
+                    "compression level {level} is out of range for {}, expected {} to {}",
+                    format.extension(),
+                    levels.start(),
+                    levels.end()
+                );
             }
+            Some(ArchiveOptions {
+                format,
+                view,
+                level,
+            })
+        }
+        None => None,
+    };
+    let mut layouts = HashMap::new();
+    for (view, spec) in config.layouts {
+        if view == View::Files {
+            bail!("the files view has no folders to lay out");
+        }
+        let grouping = spec
+            .parse()
+            .with_context(|| format!("invalid layout for {}", view.dir_name()))?;
+        layouts.insert(view, grouping);
+    }
+    layouts.extend(args.layout);
 
-            let real_path = path_from_root!(&doc.archive_name);
-            let copy_path = path_from_root!("files", &doc.archive_name);
-            let by_year = path_from_root!(
-                "by_year",
-                &doc.created.year().to_string(),
-                &doc.archive_name
-            );
-            let by_correspondent = path_from_root!(
-                "by_correspondent",
-                &doc.correspondent
-                    .map(|c| c.name)
-                    .unwrap_or("dummy".to_owned()),
-                &doc.archive_name
-            );
+    Ok(OrganizeOptions {
+        export: export.source,
+        output_dir: export.output_dir,
+        views: args
+            .views
+            .or(config.views)
+            .unwrap_or_else(|| View::DEFAULT.to_vec()),
+        link_mode: args.link_mode.or(config.link_mode).unwrap_or_default(),
+        relative_links: args.relative_links || config.relative_links.unwrap_or(false),
+        exclude,
+        filter: DocumentFilter {
+            created_after: args.created_after,
+            created_before: args.created_before,
+            include_tags: or_config(args.include_tag, config.include.tags),
+            include_correspondents: or_config(
+                args.include_correspondent,
+                config.include.correspondents,
+            ),
+            owners: or_config(args.owner, config.include.owners),
+            include_deleted: args.include_deleted || config.include.deleted.unwrap_or(false),
+            include_mode: args
+                .include_mode
+                .or(config.include.mode)
+                .unwrap_or_default(),
+            query,
+        },
+        restricted_dir,
+        encryption,
+        remote,
+        backend: None,
+        hook: None,
+        unassigned_correspondent: args
+            .unassigned_correspondent
+            .or(config.unassigned.correspondent)
+            .unwrap_or_else(unassigned),
+        unassigned_document_type: args
+            .unassigned_document_type
+            .or(config.unassigned.document_type)
+            .unwrap_or_else(unassigned),
+        unassigned_tag: args
+            .unassigned_tag
+            .or(config.unassigned.tag)
+            .unwrap_or_else(|| "untagged".to_owned()),
+        date_depth: args.date_depth.or(config.date_depth).unwrap_or_default(),
+        timezone: parse_timezone(
+            args.timezone
+                .or(config.timezone)
+                .as_deref()
+                .unwrap_or("local"),
+        )?,
+        date_field: args.date_field.or(config.date_field).unwrap_or_default(),
+        tax_year_start,
+        amount_buckets: match args.amount_buckets {
+            Some(buckets) => buckets,
+            None => config
+                .amount_buckets
+                .map(AmountBuckets::new)
+                .transpose()
+                .context("invalid `amount_buckets` in the config")?
+                .unwrap_or_default(),
+        },
+        tag_separator: args.tag_separator.or(config.tag_separator),
+        layouts,
+        custom_views: custom_views(config.custom_views, &config.custom_field_views)?,
+        asn_layout: args.asn_layout.or(config.asn_layout).unwrap_or_default(),
+        asn_width: args.asn_width.or(config.asn_width).unwrap_or(5),
+        filename_template,
+        sanitizer: match args.replacement_char.or(config.replacement_char) {
+            Some(replacement) => Sanitizer::new(replacement)?,
+            None => Sanitizer::default(),
+        }
+        .with_max_length(args.max_name_length.or(config.max_name_length))?
+        .with_normalization(args.normalize.or(config.normalize))
+        .with_ascii(args.ascii_names || config.ascii_names.unwrap_or(false)),
+        max_path_length,
+        on_collision: args
+            .on_collision
+            .or(config.on_collision)
+            .unwrap_or_default(),
+        index: args.index.or(config.index).unwrap_or_default(),
+        link_graph: args.link_graph.or(config.link_graph).unwrap_or_default(),
+        sidecar: args.sidecar.or(config.sidecar),
+        checksums: args.checksums.or(config.checksums),
+        archive,
+        content_text: args.content_text || config.content_text.unwrap_or(false),
+        notes: args.notes || config.notes.unwrap_or(false),
+        timestamps_from_created: args.timestamps_from_created
+            || config.timestamps_from_created.unwrap_or(false),
+        report: args.report || config.report.unwrap_or(false),
+        include_originals: args.include_originals || config.include_originals.unwrap_or(false),
+        link_originals: args.link_originals || config.link_originals.unwrap_or(false),
+        incremental: false,
+        undoable: false,
+        atomic: false,
+        compare_contents: false,
+        progress: ProgressBar::hidden(),
+        jobs: 0,
+    })
+}
+
+/// How long the export has to stay untouched before a `--watch` pass starts.
+const WATCH_SETTLE: Duration = Duration::from_secs(5);
+
+fn run_organize(args: OrganizeArgs) -> anyhow::Result<()> {
+    let from_stdin =
+        args.layout.export.manifest.as_deref() == Some(Path::new(export::STDIN_MANIFEST));
+    if from_stdin && (args.watch || args.interval.is_some()) {
+        bail!("a manifest from stdin can only be read once, not with --watch or --interval");
+    }
+    if args.watch {
+        run_watching(args)
+    } else if let Some(interval) = args.interval {
+        run_periodically(args, interval)
+    } else {
+        organize_pass(args)
+    }
+}
+
+fn run_watching(args: OrganizeArgs) -> anyhow::Result<()> {
+    let export = args.layout.export.resolve()?;
+    let watched = match (&export.manifest, &export.source) {
+        (Some(manifest), _) => manifest.clone(),
+        (None, ExportSource::Zip { path, .. }) => path.clone(),
+        (None, source) => source.display_path(&source.manifest_name()),
+    };
+    let watcher = FileWatcher::new(&watched)?;
+    loop {
+        if let Err(error) = organize_pass(OrganizeArgs {
+            incremental: true,
+            ..args.clone()
+        }) {
+            error!("{error:?}");
+        }
+        info!("waiting for {} to change", watcher.file().display());
+        watcher.wait(WATCH_SETTLE)?;
+    }
+}
+
+fn run_periodically(args: OrganizeArgs, interval: Duration) -> anyhow::Result<()> {
+    let shutdown = shutdown_signal()?;
+    loop {
+        if let Err(error) = organize_pass(OrganizeArgs {
+            incremental: true,
+            ..args.clone()
+        }) {
+            error!("{error:?}");
+        }
+        info!("next pass in {}", HumanDuration(interval));
+        match shutdown.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
 
-            let _ = create_dir_all(copy_path.parent().unwrap());
-            let _ = create_dir_all(by_year.parent().unwrap());
-            let _ = create_dir_all(by_correspondent.parent().unwrap());
+/// Receives a message on the first Ctrl-C or SIGTERM, so a running pass can finish before
+/// shutting down; the second one exits immediately.
+fn shutdown_signal() -> anyhow::Result<Receiver<()>> {
+    let (sender, receiver) = mpsc::channel();
+    let mut requested = false;
+    ctrlc::set_handler(move || {
+        if requested {
+            process::exit(130);
+        }
+        requested = true;
+        warn!("shutting down after the current pass, interrupt again to abort it");
+        let _ = sender.send(());
+    })?;
+    Ok(receiver)
+}
 
-            copy(&real_path, &copy_path).expect("create copy of archive pdf");
-            symlink_file(&copy_path, &by_year).expect("create symlink (by year)");
-            symlink_file(&copy_path, &by_correspondent).expect("create symlink (by correspondent)");
+/// Organizes once, sending the summary to `--notify` and writing `--metrics-file` if set, and
+/// fails if any document could not be organized.
+fn organize_pass(args: OrganizeArgs) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let export = args.layout.export.resolve()?;
+    let output_dir = export.output_dir.clone();
+    let notifier = args
+        .notify
+        .clone()
+        .or(export.config.notify.clone())
+        .map(|url| Notifier::new(&url))
+        .transpose()?;
+    let notify_on = args
+        .notify_on
+        .or(export.config.notify_on)
+        .unwrap_or_default();
+    let metrics_file = args
+        .metrics_file
+        .clone()
+        .or(export.config.metrics_file.clone());
 
-            for tag in &doc.tags {
-                let by_tag = path_from_root!("by_tag", &tag.name, &doc.archive_name);
-                let _ = create_dir_all(by_tag.parent().unwrap());
-                symlink_file(&copy_path, &by_tag).expect("create symlink (by tag)");
+    let result = organize_once(args, export);
+    let report = match &result {
+        Ok(Some(summary)) => Some(RunReport::new(summary, &output_dir, started.elapsed())),
+        // nothing to tell about dry runs
+        Ok(None) => None,
+        Err(error) => Some(RunReport::aborted(error, &output_dir, started.elapsed())),
+    };
+    if let Some(report) = &report {
+        if let Some(path) = &metrics_file {
+            if let Err(error) = write_metrics(path, report) {
+                warn!("{error:#}");
+            }
+        }
+        if let Some(notifier) = notifier.filter(|_| report.is_sent(notify_on)) {
+            if let Err(error) = notifier.send(report) {
+                warn!("{error:#}");
             }
+        }
+    }
 
-            num_copied += 1;
+    let Some(summary) = result? else {
+        return Ok(());
+    };
+    if !summary.failures.is_empty() {
+        for failure in &summary.failures {
+            error!("{failure}");
+        }
+        let message = format!(
+            "{} documents could not be organized",
+            summary.failures.len()
+        );
+        return Err(PartialFailure(message).into());
+    }
+    if let Some(hook) = summary.hook.filter(|hook| !hook.failures.is_empty()) {
+        for failure in &hook.failures {
+            error!("{failure}");
         }
+        let message = format!("the command failed for {} documents", hook.failures.len());
+        return Err(PartialFailure(message).into());
+    }
+    if !summary.malformed.is_empty() {
+        let message = format!(
+            "{} malformed manifest objects were left out, see the warnings above",
+            summary.malformed.len()
+        );
+        return Err(PartialFailure(message).into());
+    }
+    Ok(())
+}
+
+/// Organizes once and logs the summary, which is `None` for dry runs.
+fn organize_once(args: OrganizeArgs, mut export: Export) -> anyhow::Result<Option<Summary>> {
+    if args.incremental {
+        if let ExportSource::Api(client) = export.source {
+            let state_file = export.output_dir.join(api::SYNC_STATE_FILE);
+            export.source = ExportSource::Api(client.with_state_file(state_file));
+        }
+    }
+    let mut manifest = load_manifest(&export)?;
+    let s3_endpoint = args
+        .layout
+        .s3_endpoint
+        .clone()
+        .or(export.config.s3_endpoint.clone());
+    let backend = args
+        .output_url
+        .or(export.config.output_url.clone())
+        .map(|url| backend::open(&url, s3_endpoint.as_deref()))
+        .transpose()?;
+    let exec_jobs = args.exec_jobs.or(export.config.exec_jobs).unwrap_or(1);
+    let hook = args
+        .exec
+        .or(export.config.exec.clone())
+        .map(|command| Hook::new(&command, exec_jobs))
+        .transpose()?;
+
+    if args.verify_checksums {
+        let problems = verify_export(&manifest, &export.source);
+        report_problems(&problems, "the export")?;
+    }
+
+    let progress = if args.quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(0).with_style(ProgressStyle::with_template(
+            "{bar:40} {pos}/{len} documents, {elapsed} elapsed, ETA {eta} | {wide_msg}",
+        )?)
+    };
+
+    let mut options = OrganizeOptions {
+        incremental: args.incremental,
+        undoable: args.undoable,
+        atomic: args.atomic,
+        compare_contents: args.compare_contents,
+        progress,
+        jobs: args.jobs,
+        backend,
+        hook,
+        ..organize_options(args.layout, export)?
+    };
+    // an incomplete or stray export is worth a warning, not refusing to organize the rest
+    for problem in check_export(&manifest, &options) {
+        warn!("export: {problem}");
+    }
+    if !args.dry_run && options.backend.is_none() {
+        options.link_mode = options.usable_link_mode();
+    }
+    if args.dry_run || args.plan_out.is_some() {
+        let plan = Plan::new(&manifest, &options)?;
+        if let Some(path) = &args.plan_out {
+            fs::write(path, serde_json::to_string_pretty(&plan)?)
+                .with_context(|| format!("failed to write the plan to {}", path.display()))?;
+        }
+        if args.dry_run {
+            let (copies, links, writes, deletions) = plan.counts();
+            info!(
+                "dry run: would copy {copies} files, create {links} links, write {writes} files and delete {deletions} outputs"
+            );
+            return Ok(None);
+        }
+    }
+    let mut summary = organize(&manifest, &options)?;
+    summary.malformed = mem::take(&mut manifest.malformed);
+
+    let restricted = match summary.restricted {
+        0 => String::new(),
+        n if options.encryption.is_some() => format!(", {n} were encrypted"),
+        n => format!(", {n} were restricted"),
+    };
+    info!(
+        "copied {} files ({}), {} were unchanged, {} were skipped{restricted}, {} were filtered out, {} stale outputs removed",
+        summary.copied,
+        HumanBytes(summary.bytes_copied),
+        summary.unchanged,
+        summary.skipped,
+        summary.filtered,
+        summary.removed
+    );
+    if let (Some(upload), Some(remote)) = (&summary.upload, &options.remote) {
+        info!(
+            "uploaded {} files ({}) to {remote}, {} were unchanged, {} stale objects removed",
+            upload.uploaded,
+            HumanBytes(upload.bytes),
+            upload.unchanged,
+            upload.deleted
+        );
+    }
+    if let Some(hook) = &summary.hook {
+        info!(
+            "ran the command for {} documents, {} failed",
+            hook.ran,
+            hook.failures.len()
+        );
     }
+    Ok(Some(summary))
+}
+
+fn run_verify(args: LayoutArgs) -> anyhow::Result<()> {
+    let export = args.export.resolve()?;
+    let manifest = load_manifest(&export)?;
+    let problems = verify_outputs(&manifest, &organize_options(args, export)?)?;
+    report_problems(&problems, "the generated views")?;
+    println!("all outputs are intact.");
+    Ok(())
+}
+
+fn report_problems(problems: &[Problem], what: &str) -> anyhow::Result<()> {
+    for problem in problems {
+        println!("{problem}");
+    }
+    if !problems.is_empty() {
+        bail!("found {} problems in {what}", problems.len());
+    }
+    Ok(())
+}
+
+fn run_lint(args: ExportArgs) -> anyhow::Result<()> {
+    let export = args.resolve()?;
+    if let ExportSource::Api(_) = export.source {
+        bail!("lint checks the manifest of an export, not documents fetched from the API");
+    }
+    check_version(&export)?;
+    let files = export
+        .source
+        .load_object_files(export.manifest.as_deref())
+        .context("failed to load the manifest")?;
+    let diagnostics = lint(&files, export.source.exporter_version());
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    if errors > 0 {
+        return Err(InvalidManifest(format!(
+            "found {errors} errors and {warnings} warnings in the manifest"
+        ))
+        .into());
+    }
+    let objects: usize = files.iter().map(|(_, objects)| objects.len()).sum();
+    println!("all {objects} objects of the manifest are valid ({warnings} warnings).");
+    Ok(())
+}
 
-    println!("copied {} files, {} were skipped.", num_copied, num_skipped);
+fn run_diff(old: &Path, new: &Path, format: DiffFormat) -> anyhow::Result<()> {
+    let load = |path: &Path| -> anyhow::Result<Manifest> {
+        ExportSource::open(path)
+            .and_then(|export| export.load_manifest(None, false))
+            .with_context(|| format!("failed to load the export {}", path.display()))
+    };
+    let diff = Diff::between(&load(old)?, &load(new)?);
+
+    match format {
+        DiffFormat::Table => print!("{}", diff.table()),
+        DiffFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+    }
+    Ok(())
+}
+
+fn run_extract(args: ExportArgs, query: &Query, timezone: Option<&str>) -> anyhow::Result<()> {
+    let Some(dir) = args.output_dir.clone() else {
+        bail!("--output-dir is required to know where to write the extracted export");
+    };
+    let export = args.resolve()?;
+    if matches!(export.source, ExportSource::Api(_)) {
+        bail!("extract needs an export directory or zipped export, not --url");
+    }
+    if fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        bail!("{} is not empty", dir.display());
+    }
+    let timezone = parse_timezone(timezone.unwrap_or("local"))?;
+    let manifest = load_manifest(&export)?;
+    let documents: Vec<_> = manifest
+        .documents
+        .iter()
+        .filter(|doc| doc.deleted_at.is_none() && query.matches(doc, &timezone))
+        .collect();
+
+    let summary = extract(&export.source, export.manifest.as_deref(), &documents, &dir)?;
+    info!(
+        "extracted {} documents ({} files, {}) to {}",
+        summary.documents,
+        summary.files,
+        HumanBytes(summary.bytes),
+        dir.display()
+    );
+    Ok(())
+}
+
+fn run_stats(args: ExportArgs, format: StatsFormat, timezone: Option<&str>) -> anyhow::Result<()> {
+    let export = args.resolve()?;
+    let timezone = parse_timezone(timezone.unwrap_or("local"))?;
+    let stats = Stats::collect(&load_manifest(&export)?, &export.source, &timezone);
+
+    match format {
+        StatsFormat::Table => print!("{}", stats.table()),
+        StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        StatsFormat::Csv => stats.write_csv(std::io::stdout().lock())?,
+    }
     Ok(())
 }
@@ -0,0 +1,844 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::Read,
+    path::Path,
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{
+    de::{SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{
+    version::{upgrade_object, ExporterVersion},
+    Error,
+};
+
+#[derive(Clone)]
+pub struct Tag {
+    pub pk: i64,
+    pub name: String,
+}
+
+#[derive(Clone)]
+pub struct Correspondent {
+    pub pk: i64,
+    pub name: String, // fields[].name
+}
+
+#[derive(Clone)]
+pub struct DocumentType {
+    pub pk: i64,
+    pub name: String, // fields[].name
+}
+
+#[derive(Clone)]
+pub struct StoragePath {
+    pub pk: i64,
+    pub name: String, // fields[].name
+    pub path: String, // fields[].path
+}
+
+/// A paperless user account.
+#[derive(Clone)]
+pub struct User {
+    pub pk: i64,
+    pub username: String, // fields[].username
+}
+
+/// A note attached to a document in paperless.
+#[derive(Clone)]
+pub struct Note {
+    pub created: DateTime<Utc>,
+    pub text: String, // fields[].note
+}
+
+/// The value of a paperless 2.x custom field on a document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CustomFieldValue {
+    /// `string` and `url` fields
+    Text(String),
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    /// An amount with an optional ISO currency prefix, e.g. `EUR12.50`
+    Monetary(String),
+    Date(NaiveDate),
+    /// The pks of the linked documents
+    DocumentLinks(Vec<i64>),
+    /// The label of the selected option
+    Select(String),
+}
+
+impl fmt::Display for CustomFieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CustomFieldValue::Text(text)
+            | CustomFieldValue::Monetary(text)
+            | CustomFieldValue::Select(text) => f.write_str(text),
+            CustomFieldValue::Bool(value) => f.write_str(if *value { "yes" } else { "no" }),
+            CustomFieldValue::Integer(value) => write!(f, "{value}"),
+            CustomFieldValue::Float(value) => write!(f, "{value}"),
+            CustomFieldValue::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            CustomFieldValue::DocumentLinks(pks) => {
+                let pks: Vec<_> = pks.iter().map(i64::to_string).collect();
+                f.write_str(&pks.join(","))
+            }
+        }
+    }
+}
+
+impl CustomFieldValue {
+    /// The amount of `monetary` fields without the currency, and the value of numeric ones.
+    pub fn amount(&self) -> Option<f64> {
+        match self {
+            CustomFieldValue::Monetary(text) => text
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+                .parse()
+                .ok(),
+            CustomFieldValue::Integer(value) => Some(*value as f64),
+            CustomFieldValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+pub struct Document {
+    pub pk: i64,
+    pub title: String,                  // fields[].title
+    pub file_name: String,              // __exported_file_name__
+    pub archive_name: String,           // __exported_archive_name__
+    pub thumbnail_name: Option<String>, // __exported_thumbnail_name__
+    pub created: DateTime<Utc>,         // fields[].created
+    /// The day the document was created on as paperless shows it, if the export says
+    pub created_date: Option<NaiveDate>, // fields[].created_date
+    pub added: Option<DateTime<Utc>>,   // fields[].added
+    pub modified: Option<DateTime<Utc>>, // fields[].modified
+    pub correspondent: Option<Correspondent>, // fields[].correspondent
+    pub document_type: Option<DocumentType>, // fields[].document_type
+    pub storage_path: Option<StoragePath>, // fields[].storage_path
+    pub tags: Vec<Tag>,                 // fields[].tags[]
+    pub checksum: String,               // fields[].checksum
+    pub archive_checksum: Option<String>, // fields[].archive_checksum
+    /// The MIME type of the original, e.g. `image/jpeg`, if the export says
+    pub mime_type: Option<String>, // fields[].mime_type
+    pub asn: Option<i64>,               // fields[].archive_serial_number
+    pub content: String,                // fields[].content, the OCR text
+    pub notes: Vec<Note>,               // documents.note objects, oldest first
+    /// Values of the custom fields set on the document, by field name
+    pub custom_fields: BTreeMap<String, CustomFieldValue>, // documents.customfieldinstance objects
+    pub owner: Option<User>,            // fields[].owner
+    /// Users other than the owner that were granted view or change permissions on the
+    /// document
+    pub shared_with: Vec<User>, // guardian.userobjectpermission objects
+    /// When the document was moved to the trash, if it was
+    pub deleted_at: Option<DateTime<Utc>>, // fields[].deleted_at
+    /// Whether paperless stored the document's files GPG-encrypted
+    pub encrypted: bool, // fields[].storage_type == "gpg"
+}
+
+impl Document {
+    /// Whether the export contains a separate archive version next to the original.
+    pub fn has_archive(&self) -> bool {
+        self.archive_name != self.file_name
+    }
+
+    /// The kind of file the original is, e.g. `pdf`, `jpg` or `docx`: the usual extension of
+    /// its MIME type, or the extension of the exported file if the type is unknown.
+    pub fn file_type(&self) -> String {
+        let known = self.mime_type.as_deref().and_then(|mime_type| {
+            MIME_TYPES
+                .iter()
+                .find(|(name, _)| mime_type.eq_ignore_ascii_case(name))
+                .map(|(_, extension)| *extension)
+        });
+        match known {
+            Some(extension) => extension.to_owned(),
+            None => Path::new(&self.file_name).extension().map_or_else(
+                || "unknown".to_owned(),
+                |e| e.to_string_lossy().to_lowercase(),
+            ),
+        }
+    }
+
+    /// The pks of the documents `doc` links to with document link custom fields.
+    pub fn linked_documents(&self) -> Vec<i64> {
+        let mut pks: Vec<i64> = self
+            .custom_fields
+            .values()
+            .filter_map(|value| match value {
+                CustomFieldValue::DocumentLinks(pks) => Some(pks),
+                _ => None,
+            })
+            .flatten()
+            .copied()
+            .filter(|&pk| pk != self.pk)
+            .collect();
+        pks.sort_unstable();
+        pks.dedup();
+        pks
+    }
+
+    /// Whether the user `username` can see the document in paperless: documents without an
+    /// owner are visible to everyone, others to their owner and the users they were shared with.
+    pub fn is_visible_to(&self, username: &str) -> bool {
+        match &self.owner {
+            None => true,
+            Some(owner) => {
+                owner.username == username
+                    || self.shared_with.iter().any(|u| u.username == username)
+            }
+        }
+    }
+}
+
+/// MIME types of originals paperless accepts and their usual extension.
+const MIME_TYPES: [(&str, &str); 19] = [
+    ("application/pdf", "pdf"),
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/tiff", "tiff"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/bmp", "bmp"),
+    ("image/heic", "heic"),
+    ("text/plain", "txt"),
+    ("text/csv", "csv"),
+    ("message/rfc822", "eml"),
+    ("application/msword", "doc"),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    ("application/vnd.ms-excel", "xls"),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+    ),
+    ("application/vnd.ms-powerpoint", "ppt"),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pptx",
+    ),
+    ("application/vnd.oasis.opendocument.text", "odt"),
+    ("application/vnd.oasis.opendocument.spreadsheet", "ods"),
+];
+
+/// All objects of a paperless export that are relevant for organizing documents.
+pub struct Manifest {
+    pub tags: HashMap<i64, Tag>,
+    pub correspondents: HashMap<i64, Correspondent>,
+    pub document_types: HashMap<i64, DocumentType>,
+    pub storage_paths: HashMap<i64, StoragePath>,
+    pub users: HashMap<i64, User>,
+    pub documents: Vec<Document>,
+    /// Malformed objects that were left out and references to objects that aren't exported,
+    /// which were dropped; always empty when parsed strictly
+    pub malformed: Vec<Error>,
+    /// The paperless release that wrote the export, if it says
+    pub version: Option<ExporterVersion>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "model")]
+enum ManifestObject {
+    #[serde(rename = "documents.tag")]
+    Tag(Object<TagFields>),
+    #[serde(rename = "documents.correspondent")]
+    Correspondent(Object<CorrespondentFields>),
+    #[serde(rename = "documents.documenttype")]
+    DocumentType(Object<DocumentTypeFields>),
+    #[serde(rename = "documents.storagepath")]
+    StoragePath(Object<StoragePathFields>),
+    #[serde(rename = "documents.document")]
+    Document(Box<DocumentObject>),
+    #[serde(rename = "documents.note")]
+    Note(Object<NoteFields>),
+    #[serde(rename = "documents.customfield")]
+    CustomField(Object<CustomFieldFields>),
+    #[serde(rename = "documents.customfieldinstance")]
+    CustomFieldInstance(Object<CustomFieldInstanceFields>),
+    #[serde(rename = "auth.user")]
+    User(Object<UserFields>),
+    #[serde(rename = "auth.permission")]
+    Permission(Object<PermissionFields>),
+    #[serde(rename = "guardian.userobjectpermission")]
+    UserObjectPermission(Object<UserObjectPermissionFields>),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Object<F> {
+    pk: i64,
+    fields: F,
+}
+
+#[derive(Deserialize)]
+struct TagFields {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct CorrespondentFields {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct DocumentTypeFields {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct StoragePathFields {
+    name: String,
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct NoteFields {
+    document: i64,
+    note: String,
+    created: DateTime<Utc>,
+    #[serde(default)]
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct UserFields {
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct PermissionFields {
+    codename: String,
+}
+
+#[derive(Deserialize)]
+struct UserObjectPermissionFields {
+    permission: Reference,
+    user: Reference,
+    /// The pk of the object, as a string since guardian supports any kind of primary key
+    object_pk: String,
+}
+
+/// A foreign key, serialized as the pk or, with natural keys, e.g. as `["view_document",
+/// "documents", "document"]` for a permission or `["alice"]` for a user.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Reference {
+    Pk(i64),
+    NaturalKey(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct CustomFieldFields {
+    name: String,
+    data_type: String,
+    #[serde(default)]
+    extra_data: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct CustomFieldInstanceFields {
+    document: i64,
+    field: i64,
+    /// `value_text`, `value_int`, ...; which one is used depends on the field's data type
+    #[serde(flatten)]
+    values: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The key of the `documents.customfieldinstance` value used by fields of `data_type`.
+pub(crate) fn custom_field_value_key(data_type: &str) -> Option<&'static str> {
+    Some(match data_type {
+        "string" => "value_text",
+        "url" => "value_url",
+        "date" => "value_date",
+        "boolean" => "value_bool",
+        "integer" => "value_int",
+        "float" => "value_float",
+        "monetary" => "value_monetary",
+        "documentlink" => "value_document_ids",
+        "select" => "value_select",
+        _ => return None,
+    })
+}
+
+impl CustomFieldFields {
+    /// The typed value in `values`, `None` if unset or of an unknown data type.
+    fn value(
+        &self,
+        values: &serde_json::Map<String, serde_json::Value>,
+    ) -> Option<CustomFieldValue> {
+        let value = values.get(custom_field_value_key(&self.data_type)?)?;
+        Some(match self.data_type.as_str() {
+            "string" | "url" => CustomFieldValue::Text(value.as_str()?.to_owned()),
+            "date" => CustomFieldValue::Date(value.as_str()?.parse().ok()?),
+            "boolean" => CustomFieldValue::Bool(value.as_bool()?),
+            "integer" => CustomFieldValue::Integer(value.as_i64()?),
+            "float" => CustomFieldValue::Float(value.as_f64()?),
+            "monetary" => CustomFieldValue::Monetary(value.as_str()?.to_owned()),
+            "documentlink" => CustomFieldValue::DocumentLinks(
+                value
+                    .as_array()?
+                    .iter()
+                    .filter_map(|v| v.as_i64())
+                    .collect(),
+            ),
+            "select" => CustomFieldValue::Select(self.select_label(value)?),
+            _ => return None,
+        })
+    }
+
+    /// Options are plain labels picked by index up to paperless 2.14, and `{id, label}`
+    /// objects picked by id since.
+    fn select_label(&self, selected: &serde_json::Value) -> Option<String> {
+        let options = self.extra_data["select_options"].as_array()?;
+        let option = match selected {
+            serde_json::Value::Number(index) => {
+                options.get(usize::try_from(index.as_u64()?).ok()?)?
+            }
+            serde_json::Value::String(id) => options.iter().find(|o| o["id"] == *id.as_str())?,
+            _ => return None,
+        };
+        option
+            .as_str()
+            .or_else(|| option["label"].as_str())
+            .map(str::to_owned)
+    }
+}
+
+#[derive(Deserialize)]
+struct DocumentObject {
+    pk: i64,
+    fields: DocumentFields,
+    #[serde(rename = "__exported_file_name__")]
+    file_name: String,
+    #[serde(rename = "__exported_archive_name__")]
+    archive_name: Option<String>,
+    #[serde(rename = "__exported_thumbnail_name__")]
+    thumbnail_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DocumentFields {
+    title: String,
+    created: DateTime<Utc>,
+    #[serde(default)]
+    created_date: Option<NaiveDate>,
+    #[serde(default)]
+    added: Option<DateTime<Utc>>,
+    #[serde(default)]
+    modified: Option<DateTime<Utc>>,
+    correspondent: Option<i64>,
+    #[serde(default)]
+    document_type: Option<i64>,
+    #[serde(default)]
+    storage_path: Option<i64>,
+    tags: Vec<i64>,
+    checksum: String,
+    #[serde(default)]
+    archive_checksum: Option<String>,
+    #[serde(default)]
+    archive_serial_number: Option<i64>,
+    #[serde(default)]
+    mime_type: Option<String>,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    owner: Option<i64>,
+    #[serde(default)]
+    deleted_at: Option<DateTime<Utc>>,
+    /// `unencrypted` or `gpg`
+    #[serde(default)]
+    storage_type: Option<String>,
+}
+
+/// Deserializes a single manifest entry, attributing failures to its position, model and pk.
+fn parse_object(index: usize, value: serde_json::Value) -> Result<ManifestObject, Error> {
+    let model = value["model"].as_str().unwrap_or("unknown").to_owned();
+    let pk = value["pk"].as_i64();
+    ManifestObject::deserialize(value).map_err(|error| Error::MalformedObject {
+        index,
+        model,
+        pk,
+        error,
+    })
+}
+
+/// Collects manifest objects one at a time and resolves the references between them.
+#[derive(Default)]
+pub(crate) struct ManifestBuilder {
+    tags: HashMap<i64, Tag>,
+    correspondents: HashMap<i64, Correspondent>,
+    document_types: HashMap<i64, DocumentType>,
+    storage_paths: HashMap<i64, StoragePath>,
+    raw_documents: Vec<DocumentObject>,
+    notes: HashMap<i64, Vec<Note>>,
+    custom_fields: HashMap<i64, CustomFieldFields>,
+    /// Custom field instances by document pk
+    custom_field_instances: HashMap<i64, Vec<CustomFieldInstanceFields>>,
+    users: HashMap<i64, User>,
+    /// Permission codenames by pk
+    permissions: HashMap<i64, String>,
+    object_permissions: Vec<UserObjectPermissionFields>,
+    /// Whether malformed objects fail [`ManifestBuilder::finish`] rather than being left out
+    strict: bool,
+    malformed: Vec<Error>,
+    /// The release whose layout objects are upgraded from
+    version: Option<ExporterVersion>,
+}
+
+impl ManifestBuilder {
+    /// A builder for the manifest of an export written by `version`, if known.
+    pub(crate) fn new(strict: bool, version: Option<ExporterVersion>) -> Self {
+        ManifestBuilder {
+            strict,
+            version,
+            ..ManifestBuilder::default()
+        }
+    }
+
+    fn add(&mut self, object: ManifestObject) {
+        match object {
+            ManifestObject::Tag(Object { pk, fields }) => {
+                self.tags.insert(
+                    pk,
+                    Tag {
+                        pk,
+                        name: fields.name,
+                    },
+                );
+            }
+            ManifestObject::Correspondent(Object { pk, fields }) => {
+                self.correspondents.insert(
+                    pk,
+                    Correspondent {
+                        pk,
+                        name: fields.name,
+                    },
+                );
+            }
+            ManifestObject::DocumentType(Object { pk, fields }) => {
+                self.document_types.insert(
+                    pk,
+                    DocumentType {
+                        pk,
+                        name: fields.name,
+                    },
+                );
+            }
+            ManifestObject::StoragePath(Object { pk, fields }) => {
+                self.storage_paths.insert(
+                    pk,
+                    StoragePath {
+                        pk,
+                        name: fields.name,
+                        path: fields.path,
+                    },
+                );
+            }
+            ManifestObject::Document(document) => self.raw_documents.push(*document),
+            ManifestObject::Note(Object { fields, .. }) => {
+                if fields.deleted_at.is_none() {
+                    self.notes.entry(fields.document).or_default().push(Note {
+                        created: fields.created,
+                        text: fields.note,
+                    });
+                }
+            }
+            ManifestObject::CustomField(Object { pk, fields }) => {
+                self.custom_fields.insert(pk, fields);
+            }
+            ManifestObject::CustomFieldInstance(Object { fields, .. }) => {
+                self.custom_field_instances
+                    .entry(fields.document)
+                    .or_default()
+                    .push(fields);
+            }
+            ManifestObject::User(Object { pk, fields }) => {
+                self.users.insert(
+                    pk,
+                    User {
+                        pk,
+                        username: fields.username,
+                    },
+                );
+            }
+            ManifestObject::Permission(Object { pk, fields }) => {
+                self.permissions.insert(pk, fields.codename);
+            }
+            ManifestObject::UserObjectPermission(Object { fields, .. }) => {
+                self.object_permissions.push(fields);
+            }
+            ManifestObject::Other => {}
+        }
+    }
+
+    /// Adds the manifest object `value`, found at position `index`.
+    pub(crate) fn add_value(&mut self, index: usize, mut value: serde_json::Value) {
+        upgrade_object(self.version, &mut value);
+        match parse_object(index, value) {
+            Ok(object) => self.add(object),
+            Err(error) => self.malformed.push(error),
+        }
+    }
+
+    /// Streams the objects of one manifest file from `reader`.
+    pub(crate) fn read<R: Read>(&mut self, reader: R) -> Result<(), Error> {
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        deserializer.deserialize_seq(ObjectsVisitor(self))?;
+        deserializer.end()?;
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<Manifest, Error> {
+        if self.strict && !self.malformed.is_empty() {
+            return Err(self.malformed.remove(0));
+        }
+
+        let mut shared_with: HashMap<i64, Vec<User>> = HashMap::new();
+        for permission in &self.object_permissions {
+            let codename = match &permission.permission {
+                Reference::Pk(pk) => self.permissions.get(pk).map(String::as_str),
+                Reference::NaturalKey(key) => key.first().map(String::as_str),
+            };
+            if !matches!(codename, Some("view_document" | "change_document")) {
+                continue;
+            }
+            let user = match &permission.user {
+                Reference::Pk(pk) => self.users.get(pk).cloned(),
+                Reference::NaturalKey(key) => key.first().map(|username| {
+                    self.users
+                        .values()
+                        .find(|u| &u.username == username)
+                        .cloned()
+                        .unwrap_or(User {
+                            pk: 0,
+                            username: username.clone(),
+                        })
+                }),
+            };
+            let (Some(user), Ok(pk)) = (user, permission.object_pk.parse()) else {
+                continue;
+            };
+            let users = shared_with.entry(pk).or_default();
+            if !users.iter().any(|u| u.username == user.username) {
+                users.push(user);
+            }
+        }
+
+        let documents = self
+            .raw_documents
+            .into_iter()
+            .map(|raw| {
+                let (pk, strict) = (raw.pk, self.strict);
+                // references to objects that aren't exported are dropped unless `strict`
+                let mut unknown = |field, value| {
+                    let error = Error::UnknownReference { pk, field, value };
+                    match strict {
+                        true => Err(error),
+                        false => {
+                            self.malformed.push(error);
+                            Ok(())
+                        }
+                    }
+                };
+                let mut doc_tags = Vec::new();
+                for &value in &raw.fields.tags {
+                    doc_tags
+                        .extend(resolve(&self.tags, "tags", Some(value), &mut unknown)?.cloned());
+                }
+                let correspondent = resolve(
+                    &self.correspondents,
+                    "correspondent",
+                    raw.fields.correspondent,
+                    &mut unknown,
+                )?;
+                let document_type = resolve(
+                    &self.document_types,
+                    "document_type",
+                    raw.fields.document_type,
+                    &mut unknown,
+                )?;
+                let storage_path = resolve(
+                    &self.storage_paths,
+                    "storage_path",
+                    raw.fields.storage_path,
+                    &mut unknown,
+                )?;
+                let owner = resolve(&self.users, "owner", raw.fields.owner, &mut unknown)?;
+                let mut custom_fields = BTreeMap::new();
+                for instance in self.custom_field_instances.remove(&pk).unwrap_or_default() {
+                    let field = resolve(
+                        &self.custom_fields,
+                        "custom_fields",
+                        Some(instance.field),
+                        &mut unknown,
+                    )?;
+                    // values of unknown data types are left out, `lint` warns about them
+                    if let Some((field, value)) =
+                        field.and_then(|field| Some((field, field.value(&instance.values)?)))
+                    {
+                        custom_fields.insert(field.name.clone(), value);
+                    }
+                }
+                let mut notes = self.notes.remove(&pk).unwrap_or_default();
+                notes.sort_by_key(|n| n.created);
+
+                Ok(Document {
+                    pk: raw.pk,
+                    title: raw.fields.title,
+                    archive_name: raw.archive_name.unwrap_or_else(|| raw.file_name.clone()),
+                    file_name: raw.file_name,
+                    thumbnail_name: raw.thumbnail_name,
+                    created: raw.fields.created,
+                    created_date: raw.fields.created_date,
+                    added: raw.fields.added,
+                    modified: raw.fields.modified,
+                    correspondent: correspondent.cloned(),
+                    document_type: document_type.cloned(),
+                    storage_path: storage_path.cloned(),
+                    tags: doc_tags,
+                    checksum: raw.fields.checksum,
+                    archive_checksum: raw.fields.archive_checksum,
+                    mime_type: raw.fields.mime_type,
+                    asn: raw.fields.archive_serial_number,
+                    content: raw.fields.content,
+                    notes,
+                    custom_fields,
+                    owner: owner.cloned(),
+                    shared_with: shared_with.remove(&raw.pk).unwrap_or_default(),
+                    deleted_at: raw.fields.deleted_at,
+                    encrypted: raw.fields.storage_type.as_deref() == Some("gpg"),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Manifest {
+            tags: self.tags,
+            correspondents: self.correspondents,
+            document_types: self.document_types,
+            storage_paths: self.storage_paths,
+            users: self.users,
+            documents,
+            malformed: self.malformed,
+            version: self.version,
+        })
+    }
+}
+
+/// The object of `objects` a document's `field` refers to by the pk `value`, if it refers to
+/// one; references to objects that aren't there are handed to `unknown`.
+fn resolve<'a, T>(
+    objects: &'a HashMap<i64, T>,
+    field: &'static str,
+    value: Option<i64>,
+    unknown: &mut impl FnMut(&'static str, i64) -> Result<(), Error>,
+) -> Result<Option<&'a T>, Error> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let object = objects.get(&value);
+    if object.is_none() {
+        unknown(field, value)?;
+    }
+    Ok(object)
+}
+
+/// Feeds the elements of the manifest's top-level array into a [`ManifestBuilder`] as they are
+/// read, so only a single object is held as a JSON tree at any time.
+struct ObjectsVisitor<'a>(&'a mut ManifestBuilder);
+
+impl<'de> Visitor<'de> for ObjectsVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of manifest objects")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0;
+        while let Some(value) = seq.next_element::<serde_json::Value>()? {
+            self.0.add_value(index, value);
+            index += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a paperless `manifest.json` from `reader`. Malformed objects are left out and listed
+/// in [`Manifest::malformed`].
+///
+/// The manifest is streamed, so memory use is bounded by the parsed documents rather than the
+/// size of the JSON. Pass a buffered reader.
+pub fn parse_manifest<R: Read>(reader: R) -> Result<Manifest, Error> {
+    parse_manifests([reader])
+}
+
+/// Parses and merges several manifests, e.g. the main `manifest.json` of a `--split-manifest`
+/// export together with its per-document `*-manifest.json` files.
+pub fn parse_manifests<R: Read>(readers: impl IntoIterator<Item = R>) -> Result<Manifest, Error> {
+    let mut builder = ManifestBuilder::default();
+    for reader in readers {
+        builder.read(reader)?;
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"[
+        {"model": "documents.tag", "pk": 1, "fields": {"name": "invoice"}},
+        {"model": "documents.document", "pk": 1, "fields": {
+            "title": "Invoice", "created": "2024-03-01T12:00:00Z", "checksum": "abc",
+            "tags": [1, 2], "correspondent": 3, "document_type": 4, "storage_path": 5,
+            "owner": 6},
+         "__exported_file_name__": "0000001.pdf"},
+        {"model": "documents.customfieldinstance", "pk": 1,
+         "fields": {"document": 1, "field": 7, "value_text": "x"}}
+    ]"#;
+
+    fn parse(strict: bool) -> Result<Manifest, Error> {
+        let mut builder = ManifestBuilder::new(strict, None);
+        builder.read(MANIFEST.as_bytes())?;
+        builder.finish()
+    }
+
+    #[test]
+    fn unknown_references_are_dropped_and_reported() {
+        let manifest = parse(false).unwrap();
+        let doc = &manifest.documents[0];
+        assert_eq!(doc.tags.len(), 1);
+        assert!(doc.correspondent.is_none() && doc.document_type.is_none());
+        assert!(doc.storage_path.is_none() && doc.owner.is_none());
+        assert!(doc.custom_fields.is_empty());
+        let malformed: Vec<_> = manifest.malformed.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            malformed,
+            [
+                "document 1: `tags` references unknown object 2",
+                "document 1: `correspondent` references unknown object 3",
+                "document 1: `document_type` references unknown object 4",
+                "document 1: `storage_path` references unknown object 5",
+                "document 1: `owner` references unknown object 6",
+                "document 1: `custom_fields` references unknown object 7",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_references_fail_strict_parsing() {
+        let Err(error) = parse(true) else {
+            panic!("strict parsing accepted unknown references");
+        };
+        assert_eq!(
+            error.to_string(),
+            "document 1: `tags` references unknown object 2"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use serde_json::json;
+use tracing::warn;
+
+use crate::{html::href, index::IndexEntry, organize::View, sidecar::yaml, Document, Manifest};
+
+/// Folder the notes are written to; open the output directory as a vault, so the notes can
+/// embed the documents.
+pub const VAULT_DIR: &str = "vault";
+
+/// Line ending the generated part of a note; what users write below it is kept when the note is
+/// updated.
+pub const EDITS_MARKER: &str =
+    "<!-- write below this line, it is kept when the note is updated -->";
+
+/// A Markdown note for every document of `entries`, by its path in [`VAULT_DIR`] below
+/// `output_dir`, for Obsidian or Logseq: its metadata as front matter, the document embedded,
+/// and its notes and OCR text as the body, so they are searchable.
+pub fn notes(
+    output_dir: &Path,
+    manifest: &Manifest,
+    entries: &[IndexEntry],
+) -> Vec<(PathBuf, String)> {
+    let vault_dir = output_dir.join(VAULT_DIR);
+    let documents: HashMap<i64, &Document> = manifest.documents.iter().map(|d| (d.pk, d)).collect();
+    let mut taken = HashSet::new();
+    let mut notes = Vec::new();
+    for entry in entries {
+        let Some(doc) = documents.get(&entry.pk) else {
+            continue;
+        };
+        // the copy, or a link to it without the `files` view
+        let path = entry
+            .paths
+            .get(View::Files.dir_name())
+            .into_iter()
+            .chain(entry.paths.values())
+            .find_map(|paths| paths.first());
+        // notes are named after the copy, which is already a safe file name
+        let stem = match path {
+            Some(path) => Path::new(path)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            None => {
+                warn!("{} has no output to embed in its note", doc.title);
+                format!("document {}", doc.pk)
+            }
+        };
+        let name = match taken.contains(&stem.to_lowercase()) {
+            false => stem,
+            true => format!("{stem} ({})", doc.pk),
+        };
+        taken.insert(name.to_lowercase());
+        notes.push((
+            vault_dir.join(format!("{name}.md")),
+            note(doc, output_dir, path.map(String::as_str)),
+        ));
+    }
+    notes
+}
+
+/// `note` followed by what users wrote below [`EDITS_MARKER`] in the `existing` note.
+pub fn keep_edits(note: &str, existing: Option<&str>) -> String {
+    let edits = existing
+        .and_then(|existing| existing.split_once(EDITS_MARKER))
+        .map_or("\n", |(_, edits)| edits);
+    format!("{note}{EDITS_MARKER}{edits}")
+}
+
+/// The note of `doc`, whose copy is at `path` relative to `output_dir`, up to [`EDITS_MARKER`].
+fn note(doc: &Document, output_dir: &Path, path: Option<&str>) -> String {
+    let front_matter = json!({
+        "title": doc.title,
+        "pk": doc.pk,
+        "created": doc.created,
+        "added": doc.added,
+        "modified": doc.modified,
+        "correspondent": doc.correspondent.as_ref().map(|c| &c.name),
+        "document_type": doc.document_type.as_ref().map(|t| &t.name),
+        "tags": doc.tags.iter().map(|t| &t.name).collect::<Vec<_>>(),
+        "asn": doc.asn,
+        "custom_fields": doc
+            .custom_fields
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string().into()))
+            .collect::<serde_json::Map<_, _>>(),
+    });
+    let mut note = format!("---\n{}---\n\n", yaml(&front_matter));
+    let _ = writeln!(note, "# {}\n", escape(&doc.title));
+    if let Some(path) = path {
+        let _ = writeln!(
+            note,
+            "![{}]({})\n",
+            escape(&doc.title),
+            href(output_dir, &output_dir.join(VAULT_DIR), path)
+        );
+    }
+    if !doc.notes.is_empty() {
+        let _ = writeln!(note, "## Notes\n");
+        for n in &doc.notes {
+            let _ = writeln!(note, "### {}\n", n.created.format("%Y-%m-%d %H:%M"));
+            let _ = writeln!(note, "{}\n", n.text.trim_end());
+        }
+    }
+    if !doc.content.trim().is_empty() {
+        let _ = writeln!(note, "## Content\n");
+        let _ = writeln!(note, "{}\n", doc.content.trim_end());
+    }
+    note
+}
+
+/// `text` with the characters that would start Markdown syntax backslash-escaped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\`*_[]()#!<>|".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_manifest;
+
+    const MANIFEST: &str = r#"[
+        {"model": "documents.document", "pk": 1, "fields": {
+            "title": "Invoice 42", "created": "2024-01-02T10:00:00Z", "correspondent": null,
+            "tags": [], "checksum": "abc", "content": "Total: 42 EUR"},
+         "__exported_file_name__": "0000001.pdf"},
+        {"model": "documents.customfield", "pk": 1,
+         "fields": {"name": "Due: date", "data_type": "date"}},
+        {"model": "documents.customfieldinstance", "pk": 1,
+         "fields": {"document": 1, "field": 1, "value_date": "2024-01-31"}}
+    ]"#;
+
+    #[test]
+    fn note_quotes_custom_field_names_in_the_front_matter() {
+        let manifest = parse_manifest(MANIFEST.as_bytes()).unwrap();
+        let note = note(
+            &manifest.documents[0],
+            Path::new("out"),
+            Some("files/Invoice 42.pdf"),
+        );
+        let front_matter = note
+            .strip_prefix("---\n")
+            .and_then(|rest| rest.split_once("---\n"))
+            .map(|(front_matter, _)| front_matter)
+            .unwrap();
+        assert!(front_matter.contains("custom_fields:\n  \"Due: date\": \"2024-01-31\"\n"));
+        assert!(front_matter.contains("title: \"Invoice 42\"\n"));
+        assert!(note.contains("![Invoice 42](../files/Invoice%2042.pdf)"));
+        assert!(note.ends_with("## Content\n\nTotal: 42 EUR\n\n"));
+    }
+
+    #[test]
+    fn titles_are_escaped() {
+        let mut manifest = parse_manifest(MANIFEST.as_bytes()).unwrap();
+        manifest.documents[0].title = "Invoice [draft] (2)".to_owned();
+        let note = note(
+            &manifest.documents[0],
+            Path::new("out"),
+            Some("files/a.pdf"),
+        );
+        assert!(note.contains("# Invoice \\[draft\\] \\(2\\)\n"));
+        assert!(note.contains("![Invoice \\[draft\\] \\(2\\)](../files/a.pdf)"));
+    }
+
+    #[test]
+    fn edits_below_the_marker_are_kept() {
+        let fresh = keep_edits("# Invoice\n\n", None);
+        assert_eq!(fresh, format!("# Invoice\n\n{EDITS_MARKER}\n"));
+        let edited = format!("{fresh}paid on 2024-02-01\n");
+        assert_eq!(
+            keep_edits("# Invoice 42\n\n", Some(&edited)),
+            format!("# Invoice 42\n\n{EDITS_MARKER}\npaid on 2024-02-01\n")
+        );
+        assert_eq!(keep_edits("# Invoice\n\n", Some("hand written")), fresh);
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+use crate::notify::RunReport;
+
+/// Writes the metrics of the run `report` describes to `path` in Prometheus' text format, for
+/// node_exporter's textfile collector (`--collector.textfile.directory`), so the path should
+/// end in `.prom`. The file is replaced atomically, as the collector may read it at any time.
+pub fn write_metrics(path: &Path, report: &RunReport) -> anyhow::Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let documents = report.copied
+        + report.unchanged
+        + report.skipped
+        + report.restricted
+        + report.filtered
+        + report.failed;
+    let metrics: [(&str, &str, f64); 11] = [
+        (
+            "documents_total",
+            "Documents in the manifest",
+            documents as f64,
+        ),
+        (
+            "copied_total",
+            "Documents copied in the last run",
+            report.copied as f64,
+        ),
+        (
+            "unchanged_total",
+            "Documents that were up to date",
+            report.unchanged as f64,
+        ),
+        (
+            "skipped_total",
+            "Documents skipped as excluded",
+            report.skipped as f64,
+        ),
+        (
+            "failed_total",
+            "Documents that could not be organized",
+            report.failed as f64,
+        ),
+        (
+            "malformed_objects_total",
+            "Malformed manifest objects left out",
+            report.malformed as f64,
+        ),
+        (
+            "removed_total",
+            "Stale outputs removed",
+            report.removed as f64,
+        ),
+        (
+            "bytes_copied_total",
+            "Bytes copied in the last run",
+            report.bytes_copied as f64,
+        ),
+        (
+            "run_duration_seconds",
+            "Duration of the last run",
+            report.duration_secs,
+        ),
+        (
+            "last_run_success",
+            "Whether the last run organized every document",
+            f64::from(u8::from(report.success)),
+        ),
+        (
+            "last_run_timestamp_seconds",
+            "When the last run finished",
+            timestamp as f64,
+        ),
+    ];
+
+    let mut text = String::new();
+    for (name, help, value) in metrics {
+        let _ = writeln!(text, "# HELP paperless_organize_{name} {help}.");
+        let _ = writeln!(text, "# TYPE paperless_organize_{name} gauge");
+        let _ = writeln!(text, "paperless_organize_{name} {value}");
+    }
+    // the collector ignores files not ending in `.prom`, such as this one
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, text)
+        .and_then(|()| fs::rename(&partial, path))
+        .with_context(|| format!("failed to write the metrics to {}", path.display()))
+}
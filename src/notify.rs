@@ -0,0 +1,253 @@
+use std::{env, fmt, path::Path, time::Duration};
+
+use anyhow::{anyhow, bail, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use indicatif::HumanBytes;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::organize::Summary;
+
+/// When [`Notifier::send`] is called for a run.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyOn {
+    /// After every run
+    #[default]
+    Always,
+    /// When documents were copied or removed, or something failed
+    Changes,
+    /// Only when something failed
+    Failure,
+}
+
+/// Where the summary of a run is sent, e.g. to be alerted of failures of scheduled runs.
+pub enum Notifier {
+    /// An `http(s)://` URL receiving the [`RunReport`] as JSON in a POST request
+    Webhook(String),
+    /// A topic on an [ntfy](https://ntfy.sh) server, `ntfys://ntfy.sh/topic` (or `ntfy://`
+    /// without TLS). Credentials are taken from the URL or the access token in `NTFY_TOKEN`.
+    Ntfy {
+        url: String,
+        authorization: Option<String>,
+    },
+    /// A [Gotify](https://gotify.net) server, `gotifys://host` (or `gotify://` without TLS),
+    /// with the application token from `?token=` or `GOTIFY_TOKEN`
+    Gotify { url: String, token: String },
+}
+
+/// The outcome of a run, as sent by [`Notifier`].
+#[derive(Default, Serialize)]
+pub struct RunReport {
+    /// Whether everything was organized
+    pub success: bool,
+    pub output_dir: String,
+    pub duration_secs: f64,
+    pub copied: u64,
+    pub unchanged: u64,
+    pub skipped: u64,
+    pub restricted: u64,
+    pub filtered: u64,
+    pub removed: u64,
+    pub bytes_copied: u64,
+    /// Documents that couldn't be organized, see `failures`
+    pub failed: u64,
+    /// Documents the `--exec` command failed for, included in `failures`
+    pub hook_failed: u64,
+    /// Manifest objects left out as malformed, included in `failures`
+    pub malformed: u64,
+    pub failures: Vec<String>,
+    /// Why the run was aborted, if it was
+    pub error: Option<String>,
+}
+
+impl Notifier {
+    /// The notifier for `url`: `https://...` for a webhook, `ntfys://host/topic` or
+    /// `gotifys://host`.
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        let Some((scheme, location)) = url.split_once("://") else {
+            bail!("invalid notification URL `{url}`, expected e.g. ntfys://ntfy.sh/topic");
+        };
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+        let http = |tls: bool| match tls {
+            true => "https",
+            false => "http",
+        };
+        match scheme {
+            "http" | "https" => Ok(Notifier::Webhook(url.to_owned())),
+            "ntfy" | "ntfys" => {
+                let (userinfo, location) = match location.split_once('@') {
+                    Some((userinfo, location)) => (Some(userinfo), location),
+                    None => (None, location),
+                };
+                if !location.trim_end_matches('/').contains('/') {
+                    bail!("the ntfy URL `{url}` names no topic");
+                }
+                let authorization = match userinfo {
+                    Some(userinfo) => Some(format!("Basic {}", STANDARD.encode(userinfo))),
+                    None => var("NTFY_TOKEN").map(|token| format!("Bearer {token}")),
+                };
+                Ok(Notifier::Ntfy {
+                    url: format!("{}://{location}", http(scheme == "ntfys")),
+                    authorization,
+                })
+            }
+            "gotify" | "gotifys" => {
+                let (location, token) = match location.split_once("?token=") {
+                    Some((location, token)) => (location, Some(token.to_owned())),
+                    None => (location, var("GOTIFY_TOKEN")),
+                };
+                let token = token.ok_or_else(|| {
+                    anyhow!("no token for `{url}`, add `?token=...` or set GOTIFY_TOKEN")
+                })?;
+                Ok(Notifier::Gotify {
+                    url: format!(
+                        "{}://{}/message",
+                        http(scheme == "gotifys"),
+                        location.trim_end_matches('/')
+                    ),
+                    token,
+                })
+            }
+            _ => bail!(
+                "unsupported notification URL `{url}`, expected http(s)://, ntfy(s):// or \
+                 gotify(s)://"
+            ),
+        }
+    }
+
+    /// Sends `report`.
+    pub fn send(&self, report: &RunReport) -> anyhow::Result<()> {
+        debug!("sending the summary to {self}");
+        let agent = ureq::Agent::new_with_defaults();
+        let result = match self {
+            Notifier::Webhook(url) => agent.post(url).send_json(report),
+            Notifier::Ntfy { url, authorization } => {
+                let mut request = agent
+                    .post(url)
+                    .header("Title", report.title())
+                    .header("Priority", if report.success { "default" } else { "high" })
+                    .header(
+                        "Tags",
+                        if report.success {
+                            "white_check_mark"
+                        } else {
+                            "warning"
+                        },
+                    );
+                if let Some(authorization) = authorization {
+                    request = request.header("Authorization", authorization);
+                }
+                request.send(report.message())
+            }
+            Notifier::Gotify { url, token } => agent
+                .post(url)
+                .header("X-Gotify-Key", token)
+                .send_json(serde_json::json!({
+                    "title": report.title(),
+                    "message": report.message(),
+                    "priority": if report.success { 2 } else { 8 },
+                })),
+        };
+        result
+            .map(drop)
+            .with_context(|| format!("failed to send the summary to {self}"))
+    }
+}
+
+impl fmt::Display for Notifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Notifier::Webhook(url) | Notifier::Ntfy { url, .. } | Notifier::Gotify { url, .. } => {
+                f.write_str(url)
+            }
+        }
+    }
+}
+
+impl RunReport {
+    /// The report of a run that wrote `summary` to `output_dir` in `duration`.
+    pub fn new(summary: &Summary, output_dir: &Path, duration: Duration) -> Self {
+        let hook_failures = summary.hook.iter().flat_map(|hook| &hook.failures);
+        let hook_failed = hook_failures.clone().count() as u64;
+        let failures: Vec<_> = summary
+            .failures
+            .iter()
+            .chain(hook_failures)
+            .chain(&summary.malformed)
+            .map(ToString::to_string)
+            .collect();
+        RunReport {
+            success: failures.is_empty(),
+            output_dir: output_dir.display().to_string(),
+            duration_secs: duration.as_secs_f64(),
+            copied: summary.copied,
+            unchanged: summary.unchanged,
+            skipped: summary.skipped,
+            restricted: summary.restricted,
+            filtered: summary.filtered,
+            removed: summary.removed,
+            bytes_copied: summary.bytes_copied,
+            failed: summary.failures.len() as u64,
+            hook_failed,
+            malformed: summary.malformed.len() as u64,
+            failures,
+            error: None,
+        }
+    }
+
+    /// The report of a run into `output_dir` aborted by `error` after `duration`.
+    pub fn aborted(error: &anyhow::Error, output_dir: &Path, duration: Duration) -> Self {
+        RunReport {
+            success: false,
+            output_dir: output_dir.display().to_string(),
+            duration_secs: duration.as_secs_f64(),
+            error: Some(format!("{error:#}")),
+            ..RunReport::default()
+        }
+    }
+
+    /// Whether it's to be sent with `notify_on`.
+    pub fn is_sent(&self, notify_on: NotifyOn) -> bool {
+        match notify_on {
+            NotifyOn::Always => true,
+            NotifyOn::Changes => !self.success || self.copied > 0 || self.removed > 0,
+            NotifyOn::Failure => !self.success,
+        }
+    }
+
+    fn title(&self) -> String {
+        match (&self.error, self.failures.len()) {
+            (Some(_), _) => "paperless: organizing failed".to_owned(),
+            (None, 0) => format!("paperless: copied {} documents", self.copied),
+            (None, 1) => "paperless: 1 failure".to_owned(),
+            (None, n) => format!("paperless: {n} failures"),
+        }
+    }
+
+    fn message(&self) -> String {
+        if let Some(error) = &self.error {
+            return format!("{}: {error}", self.output_dir);
+        }
+        let mut message = format!(
+            "{}: copied {} documents ({}), {} were unchanged, {} were skipped, {} stale outputs \
+             removed in {:.0}s",
+            self.output_dir,
+            self.copied,
+            HumanBytes(self.bytes_copied),
+            self.unchanged,
+            self.skipped,
+            self.removed,
+            self.duration_secs
+        );
+        // enough to see what's wrong, the log has the rest
+        for failure in self.failures.iter().take(10) {
+            message.push_str("\n- ");
+            message.push_str(failure);
+        }
+        if self.failures.len() > 10 {
+            message.push_str(&format!("\n- and {} more", self.failures.len() - 10));
+        }
+        message
+    }
+}
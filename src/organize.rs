@@ -0,0 +1,1900 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsString,
+    fmt,
+    fs::{self, create_dir_all, remove_dir_all, remove_file},
+    io,
+    path::{Component, Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use indicatif::{HumanBytes, ProgressBar};
+use rayon::{prelude::*, ThreadPoolBuilder};
+use tracing::{debug, enabled, info, trace, warn, Level};
+
+use crate::{
+    archive::{self, ArchiveOptions},
+    backend::{self, Entry, EntryKind, LocalBackend, OutputBackend},
+    checksum,
+    encrypt::{Encryption, ENCRYPTED_EXTENSION},
+    export::{ExportSource, EXPORT_DIRS},
+    filter::{DocumentFilter, ExcludeRules},
+    graph::{self, GraphFormat},
+    grouping::{first_letter, AmountBuckets, Grouping},
+    hook::{Hook, HookSummary},
+    html,
+    index::{self, IndexEntry, IndexFormat},
+    link::{self, LinkMode},
+    markdown, platform,
+    remote::{Remote, UploadSummary},
+    report::{self, Report},
+    resume::RunState,
+    sanitize::{self, Sanitizer},
+    shortcut,
+    sidecar::SidecarFormat,
+    storage_path,
+    sums::{self, SumsLayout},
+    sync,
+    template::FilenameTemplate,
+    timezone::{self, local_time, TimeZone},
+    undo::UndoLog,
+    Document, Error, Manifest,
+};
+
+/// Characters left for the file name when `max_path_length` forces shortening folders.
+const MIN_NAME_LENGTH: usize = 40;
+
+/// Shortest a folder gets when shortened for `max_path_length`.
+const MIN_FOLDER_LENGTH: usize = 12;
+
+/// Smallest `max_path_length` that leaves room for a view folder, a few folders and a name.
+pub const MIN_PATH_LENGTH: usize = 80;
+
+/// Folder in the output directory receiving the untouched originals with `include_originals`.
+/// Not `originals`, which `document_exporter --use-folder-prefix` writes to the export the
+/// output directory defaults to.
+pub const ORIGINALS_DIR: &str = "files-originals";
+
+/// `.by_tag.partial`, the sibling directory the view `by_tag` is built in with `atomic`.
+fn staging_dir(output_dir: &Path, dir: &str) -> PathBuf {
+    output_dir.join(format!(".{dir}.partial"))
+}
+
+/// A folder layout generated below the output directory.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum View {
+    /// Plain copies of all documents, the target of every other view
+    Files,
+    ByTag,
+    ByYear,
+    ByCorrespondent,
+    ByDocumentType,
+    ByStoragePath,
+    /// Documents with an archive serial number, by that number
+    ByAsn,
+    /// Documents with an owner, by the owner's username; only generated when asked for
+    ByOwner,
+    /// Documents by the month they were added to paperless, e.g. `2024-05/`, to find recent
+    /// scans whatever their date; only generated when asked for
+    ByAdded,
+    /// Documents by the first letter of their title, e.g. `A/`, which keeps folders of large
+    /// archives small; only generated when asked for
+    ByTitle,
+    /// Documents by the kind of their original, e.g. `pdf/`, `jpg/` or `docx/`, to find photos
+    /// and office documents among the scans; only generated when asked for
+    ByType,
+    /// Documents by the tax year of their date, e.g. `2023-24/` for tax years starting on April
+    /// 6 with `tax_year_start`; only generated when asked for
+    ByTaxYear,
+    /// For every document, links to the documents linking to it with document link custom
+    /// fields, e.g. `related/Contract/Invoice.pdf`; paperless keeps such links in both
+    /// directions, so these are the documents it links to as well. Only generated when asked
+    /// for
+    Related,
+}
+
+impl View {
+    pub const ALL: &'static [View] = &[
+        View::Files,
+        View::ByTag,
+        View::ByYear,
+        View::ByCorrespondent,
+        View::ByDocumentType,
+        View::ByStoragePath,
+        View::ByAsn,
+        View::ByOwner,
+        View::ByAdded,
+        View::ByTitle,
+        View::ByType,
+        View::ByTaxYear,
+        View::Related,
+    ];
+
+    /// The views generated unless a list is given.
+    pub const DEFAULT: &'static [View] = &[
+        View::Files,
+        View::ByTag,
+        View::ByYear,
+        View::ByCorrespondent,
+        View::ByDocumentType,
+        View::ByStoragePath,
+        View::ByAsn,
+    ];
+
+    /// Name of the view's top-level folder in the output directory.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            View::Files => "files",
+            View::ByTag => "by_tag",
+            View::ByYear => "by_year",
+            View::ByCorrespondent => "by_correspondent",
+            View::ByDocumentType => "by_document_type",
+            View::ByStoragePath => "by_storage_path",
+            View::ByAsn => "by_asn",
+            View::ByOwner => "by_owner",
+            View::ByAdded => "by_added",
+            View::ByTitle => "by_title",
+            View::ByType => "by_type",
+            View::ByTaxYear => "by_tax_year",
+            View::Related => "related",
+        }
+    }
+
+    /// Paths of `doc` (output file `name`, of the documents `names`) in this view, relative to
+    /// the view's folder.
+    pub(crate) fn entries(
+        self,
+        doc: &Document,
+        name: &Path,
+        names: &FileNames,
+        options: &OrganizeOptions,
+    ) -> Vec<PathBuf> {
+        if let Some(grouping) = options.layouts.get(&self) {
+            return grouping
+                .folders(doc, options)
+                .into_iter()
+                .map(|folder| folder.join(name))
+                .collect();
+        }
+        let sanitizer = &options.sanitizer;
+        match self {
+            View::Files => vec![name.to_path_buf()],
+            View::ByTag => options
+                .tag_folders(doc)
+                .into_iter()
+                .map(|folder| folder.join(name))
+                .collect(),
+            View::ByYear => vec![options.date_depth.folder(options.date(doc)).join(name)],
+            View::ByCorrespondent => {
+                let folder = doc
+                    .correspondent
+                    .as_ref()
+                    .map_or(&options.unassigned_correspondent, |c| &c.name);
+                vec![Path::new(&sanitizer.component(folder)).join(name)]
+            }
+            View::ByDocumentType => {
+                let folder = doc
+                    .document_type
+                    .as_ref()
+                    .map_or(&options.unassigned_document_type, |t| &t.name);
+                vec![Path::new(&sanitizer.component(folder)).join(name)]
+            }
+            View::ByStoragePath => vec![names.storage_path(doc).unwrap_or(name).to_path_buf()],
+            View::ByAsn => doc
+                .asn
+                .map(|asn| options.asn_layout.entry(asn, options.asn_width, name))
+                .into_iter()
+                .collect(),
+            View::ByOwner => doc
+                .owner
+                .iter()
+                .map(|owner| Path::new(&sanitizer.component(&owner.username)).join(name))
+                .collect(),
+            View::ByAdded => doc
+                .added
+                .map(|added| {
+                    let month = local_time(added, &options.timezone).format("%Y-%m");
+                    Path::new(&month.to_string()).join(name)
+                })
+                .into_iter()
+                .collect(),
+            View::ByTitle => vec![Path::new(&first_letter(&doc.title)).join(name)],
+            View::ByType => vec![Path::new(&sanitizer.component(&doc.file_type())).join(name)],
+            View::ByTaxYear => {
+                let year = options.tax_year_start.folder(options.date(doc).date());
+                vec![Path::new(&year).join(name)]
+            }
+            View::Related => doc
+                .linked_documents()
+                .into_iter()
+                .filter_map(|pk| names.by_pk(pk))
+                .map(|linked| Path::new(linked.file_stem().unwrap_or_default()).join(name))
+                .collect(),
+        }
+    }
+}
+
+/// How deep the `by_year` view nests its date folders.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateDepth {
+    /// `2020s/`, for archives spanning many decades
+    Decade,
+    /// `2023/`
+    #[default]
+    Year,
+    /// `2023/05/`
+    Month,
+    /// `2023/W18/`, the ISO week, in the year it belongs to, for many documents a day
+    Week,
+    /// `2023/05/01/`
+    Day,
+}
+
+impl DateDepth {
+    fn folder(self, date: NaiveDateTime) -> PathBuf {
+        let format = match self {
+            DateDepth::Decade => return PathBuf::from(decade(date)),
+            DateDepth::Year => "%Y",
+            DateDepth::Month => "%Y/%m",
+            DateDepth::Week => "%G/W%V",
+            DateDepth::Day => "%Y/%m/%d",
+        };
+        PathBuf::from(date.format(format).to_string())
+    }
+}
+
+/// The decade of `date`, e.g. `1990s`.
+pub(crate) fn decade(date: NaiveDateTime) -> String {
+    format!("{}s", date.year() - date.year().rem_euclid(10))
+}
+
+/// The day tax years start on, e.g. April 6 in the UK or July 1 in Australia.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TaxYearStart {
+    month: u32,
+    day: u32,
+}
+
+impl Default for TaxYearStart {
+    /// January 1, making tax years calendar years
+    fn default() -> Self {
+        TaxYearStart { month: 1, day: 1 }
+    }
+}
+
+impl FromStr for TaxYearStart {
+    type Err = anyhow::Error;
+
+    /// Parses `MM-DD`, e.g. `04-06`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid =
+            || anyhow::anyhow!("invalid tax year start `{s}`, expected MM-DD, e.g. `04-06`");
+        let (month, day) = s.trim().split_once('-').ok_or_else(invalid)?;
+        let (month, day) = (
+            month.parse().map_err(|_| invalid())?,
+            day.parse().map_err(|_| invalid())?,
+        );
+        // a leap year, so February 29 is accepted
+        NaiveDate::from_ymd_opt(2024, month, day).ok_or_else(invalid)?;
+        Ok(TaxYearStart { month, day })
+    }
+}
+
+impl TaxYearStart {
+    /// The `by_tax_year` folder of `date`: the year for calendar years, otherwise the year the
+    /// tax year starts in and the last two digits of the one it ends in, e.g. `2023-24`.
+    pub(crate) fn folder(self, date: NaiveDate) -> String {
+        let year = match (date.month(), date.day()) >= (self.month, self.day) {
+            true => date.year(),
+            false => date.year() - 1,
+        };
+        match self == TaxYearStart::default() {
+            true => year.to_string(),
+            false => format!("{year}-{:02}", (year + 1).rem_euclid(100)),
+        }
+    }
+}
+
+/// Which date of documents the date views go by.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateField {
+    /// When the document was created, e.g. the date printed on a letter
+    #[default]
+    Created,
+    /// When it was added to paperless
+    Added,
+    /// When it was last changed in paperless
+    Modified,
+}
+
+/// How the `by_asn` view files documents under their archive serial number.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AsnLayout {
+    /// `00017/Invoice.pdf`
+    #[default]
+    Folder,
+    /// `00017 Invoice.pdf`
+    Prefix,
+}
+
+impl AsnLayout {
+    fn entry(self, asn: i64, width: usize, name: &Path) -> PathBuf {
+        let asn = format!("{asn:0width$}");
+        match self {
+            AsnLayout::Folder => Path::new(&asn).join(name),
+            AsnLayout::Prefix => {
+                let mut prefixed = OsString::from(asn + " ");
+                prefixed.push(name.file_name().unwrap_or_default());
+                name.with_file_name(prefixed)
+            }
+        }
+    }
+}
+
+pub struct OrganizeOptions {
+    pub export: ExportSource,
+    pub output_dir: PathBuf,
+    pub views: Vec<View>,
+    pub link_mode: LinkMode,
+    /// Point symlinks at their targets with relative paths
+    pub relative_links: bool,
+    pub exclude: ExcludeRules,
+    pub filter: DocumentFilter,
+    pub unassigned_correspondent: String,
+    pub unassigned_document_type: String,
+    /// Folder for documents without tags in `by_tag/`; none if empty
+    pub unassigned_tag: String,
+    pub date_depth: DateDepth,
+    /// Time zone the date views take the dates of documents in
+    pub timezone: TimeZone,
+    pub date_field: DateField,
+    /// The day tax years start on in the `by_tax_year` view
+    pub tax_year_start: TaxYearStart,
+    /// The ranges of the `amount:NAME` grouping
+    pub amount_buckets: AmountBuckets,
+    /// Split tag names on this separator into nested folders in the `by_tag` view, e.g.
+    /// `finance/taxes/2023/` for `/`
+    pub tag_separator: Option<String>,
+    /// Views declared in the config, generated in addition to `views`
+    pub custom_views: Vec<CustomView>,
+    /// Folder levels replacing the default grouping of views, e.g. `correspondent/year` for
+    /// `by_correspondent`
+    pub layouts: HashMap<View, Grouping>,
+    pub asn_layout: AsnLayout,
+    /// Number of digits ASNs are zero-padded to in the `by_asn` view
+    pub asn_width: usize,
+    /// Output file name template; the exporter's archive name is used if unset
+    pub filename_template: Option<FilenameTemplate>,
+    /// Makes tag, correspondent and file names safe to use in paths
+    pub sanitizer: Sanitizer,
+    /// Longest output path below the output directory, in characters
+    pub max_path_length: Option<usize>,
+    /// What to do when several documents end up with the same file name
+    pub on_collision: CollisionStrategy,
+    /// Also copy the original files (`__exported_file_name__`) to [`ORIGINALS_DIR`]
+    pub include_originals: bool,
+    /// Write a metadata file next to every copied document
+    pub sidecar: Option<SidecarFormat>,
+    /// Write the checksums of the copies in `files/` and [`ORIGINALS_DIR`] to
+    /// [`sums::SUMS_FILE`] files
+    pub checksums: Option<SumsLayout>,
+    /// Bundle the organized documents into one archive per folder of a view
+    pub archive: Option<ArchiveOptions>,
+    /// Index files listing the organized documents to write to the output directory
+    pub index: Vec<IndexFormat>,
+    /// Graphs of the links between the organized documents to write to the output directory
+    pub link_graph: Vec<GraphFormat>,
+    /// Write the OCR text of a document to `<name>.txt` next to its copy
+    pub content_text: bool,
+    /// Write the notes of a document to `<name>.notes.txt` next to its copy
+    pub notes: bool,
+    /// Set the modification time of copied files to the document's creation date. Incremental
+    /// runs then only notice changed exports by their size, unless `compare_contents` is set.
+    pub timestamps_from_created: bool,
+    /// With `include_originals`, also link the originals into the other views, next to the
+    /// archive versions
+    pub link_originals: bool,
+    /// Keep existing outputs and only update what changed instead of wiping the views
+    pub incremental: bool,
+    /// Copy excluded documents into this separate directory instead of skipping them. It is
+    /// only accessible to the owner on Unix, and files in it that don't belong to an excluded
+    /// document are removed.
+    pub restricted_dir: Option<PathBuf>,
+    /// Encrypt the copies in `restricted_dir` to these age recipients, as `<name>.age`
+    pub encryption: Option<Encryption>,
+    /// Mirror `files/` and the index to this remote after the run
+    pub remote: Option<Remote>,
+    /// Write the views straight to this backend instead of `output_dir`, which then only
+    /// serves as the base of the output paths
+    pub backend: Option<Box<dyn OutputBackend>>,
+    /// Run this command for every document copied, once the run is complete
+    pub hook: Option<Hook>,
+    /// Write [`report::SKIPPED_REPORT`] and [`report::ERRORS_REPORT`] to the output directory
+    pub report: bool,
+    /// Keep what the run replaces or deletes, so [`undo`](crate::undo::undo) can revert it
+    pub undoable: bool,
+    /// Build the views in temporary sibling directories and rename them into place once
+    /// done, instead of wiping and rebuilding them in place
+    pub atomic: bool,
+    /// In incremental mode, also compare file contents instead of just size and mtime
+    pub compare_contents: bool,
+    /// Receives per-document progress; use [`ProgressBar::hidden`] to disable
+    pub progress: ProgressBar,
+    /// Number of worker threads, `0` picks one per CPU core
+    pub jobs: usize,
+}
+
+impl OrganizeOptions {
+    /// A hash of the options that decide which outputs documents get, so an interrupted run
+    /// isn't resumed with different ones.
+    fn fingerprint(&self) -> String {
+        let mut layouts: Vec<_> = self.layouts.iter().collect();
+        layouts.sort_by_key(|(view, _)| view.dir_name());
+        let options = format!(
+            "{:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} \
+             {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+            self.views,
+            self.link_mode,
+            self.relative_links,
+            self.exclude,
+            self.filter,
+            self.unassigned_correspondent,
+            self.unassigned_document_type,
+            self.unassigned_tag,
+            self.date_depth,
+            self.timezone,
+            self.date_field,
+            self.tax_year_start,
+            self.amount_buckets,
+            self.tag_separator,
+            self.custom_views,
+            layouts,
+            self.asn_layout,
+            self.asn_width,
+            self.filename_template,
+            self.sanitizer,
+            self.max_path_length,
+            self.on_collision,
+            self.include_originals,
+            self.sidecar,
+            self.content_text,
+            self.notes,
+            self.timestamps_from_created,
+            self.link_originals,
+            self.restricted_dir,
+            self.encryption.is_some(),
+        );
+        checksum::sha256(&mut options.as_bytes()).expect("reading from memory")
+    }
+}
+
+/// How to name a document whose output file name is already taken by another one.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum CollisionStrategy {
+    /// `Invoice_42.pdf`
+    #[default]
+    AppendPk,
+    /// `Invoice (2).pdf`
+    Counter,
+    /// Stop before writing anything
+    Abort,
+}
+
+/// A view declared by the user, filing documents into the folders of `grouping` below
+/// `name/`.
+#[derive(Clone, Debug)]
+pub struct CustomView {
+    pub name: String,
+    pub grouping: Grouping,
+}
+
+impl CustomView {
+    /// Checks that `name` is a plain folder name that doesn't clash with the built-in views or
+    /// the folders of an export, which the output directory defaults to and views are wiped
+    /// in.
+    pub fn new(name: String, grouping: Grouping) -> anyhow::Result<Self> {
+        // case-insensitive file systems would wipe `Archive/` with `archive/`
+        if let Some(dir) = EXPORT_DIRS
+            .iter()
+            .find(|dir| dir.eq_ignore_ascii_case(&name))
+        {
+            anyhow::bail!("the view name `{name}` is taken by the export's `{dir}/` folder");
+        }
+        let reserved = View::ALL
+            .iter()
+            .map(|v| v.dir_name())
+            .chain([ORIGINALS_DIR]);
+        if name.is_empty()
+            || name.starts_with('.')
+            || Path::new(&name).components().count() != 1
+            || !matches!(
+                Path::new(&name).components().next(),
+                Some(Component::Normal(_))
+            )
+        {
+            anyhow::bail!("invalid view name `{name}`, expected a plain folder name");
+        }
+        if reserved.into_iter().any(|r| r == name) {
+            anyhow::bail!("the view name `{name}` is taken by a built-in view");
+        }
+        Ok(Self { name, grouping })
+    }
+}
+
+/// Output file names of a set of documents, with collisions between them resolved.
+pub struct FileNames {
+    names: HashMap<i64, PathBuf>,
+    /// Paths in the `by_storage_path` view, if generated
+    storage_paths: HashMap<i64, PathBuf>,
+}
+
+/// Where a document ends up in the output directory.
+pub struct DocumentOutputs {
+    /// Name of the exported file the outputs are created from
+    pub source: String,
+    /// The copy in `files/`, or the exported file itself if that view is disabled
+    pub target: PathBuf,
+    /// Links (or copies, depending on the link mode) to `target` in the other views, with the
+    /// folder name of their view
+    pub links: Vec<(String, PathBuf)>,
+}
+
+impl OrganizeOptions {
+    /// When `doc` was created, in `timezone` unless the export has the day paperless shows.
+    pub fn created(&self, doc: &Document) -> NaiveDateTime {
+        timezone::created(doc, &self.timezone).naive_local()
+    }
+
+    /// The `date_field` of `doc` in `timezone`, which decides the date folders it's filed in.
+    /// Documents lacking the field, e.g. from older exports, go by their creation date.
+    pub fn date(&self, doc: &Document) -> NaiveDateTime {
+        let time = match self.date_field {
+            DateField::Created => None,
+            DateField::Added => doc.added,
+            DateField::Modified => doc.modified,
+        };
+        match time {
+            Some(time) => local_time(time, &self.timezone),
+            None => self.created(doc),
+        }
+    }
+
+    /// The directories below the output directory this run generates.
+    pub(crate) fn dirs(&self) -> Vec<String> {
+        let mut dirs: Vec<_> = self.views.iter().map(|v| v.dir_name().to_owned()).collect();
+        dirs.extend(self.custom_views.iter().map(|v| v.name.clone()));
+        if self.include_originals {
+            dirs.push(ORIGINALS_DIR.to_owned());
+        }
+        dirs
+    }
+
+    /// `link_mode`, unless symlinks were asked for but can't be created in the output directory
+    /// (on Windows without Developer Mode or the `SeCreateSymbolicLinkPrivilege`), then hard
+    /// links or, if those fail too, copies.
+    pub fn usable_link_mode(&self) -> LinkMode {
+        if self.link_mode != LinkMode::Symlink {
+            return self.link_mode;
+        }
+        let Err(error) = link::probe(LinkMode::Symlink, &self.output_dir) else {
+            return LinkMode::Symlink;
+        };
+        // hard links to the export only work if it's on the same volume
+        let (fallback, name) =
+            if self.copies_files() && link::probe(LinkMode::Hardlink, &self.output_dir).is_ok() {
+                (LinkMode::Hardlink, "hard links")
+            } else {
+                (LinkMode::Copy, "copies")
+            };
+        warn!(
+            "cannot create symlinks in {} ({error}), using {name} instead",
+            self.output_dir.display()
+        );
+        fallback
+    }
+
+    /// What a symlink at `link` to the output `target` points to.
+    pub(crate) fn symlink_target(&self, target: &Path, link: &Path) -> PathBuf {
+        if self.relative_links {
+            link::relative_path(link, target)
+        } else {
+            target.to_owned()
+        }
+    }
+
+    /// Where the output `path` is written during the run, which with `atomic` is the
+    /// staging directory of its view.
+    fn staged(&self, path: &Path) -> PathBuf {
+        if !self.atomic {
+            return path.to_owned();
+        }
+        let Ok(relative) = path.strip_prefix(&self.output_dir) else {
+            return path.to_owned();
+        };
+        let mut components = relative.components();
+        match components.next() {
+            Some(Component::Normal(first)) => match self.dirs().into_iter().find(|d| first == &**d)
+            {
+                Some(dir) => staging_dir(&self.output_dir, &dir).join(components.as_path()),
+                None => path.to_owned(),
+            },
+            _ => path.to_owned(),
+        }
+    }
+
+    /// Folders of `doc` in the `by_tag` view, `unassigned_tag` if it has no tags.
+    pub(crate) fn tag_folders(&self, doc: &Document) -> Vec<PathBuf> {
+        if doc.tags.is_empty() && !self.unassigned_tag.is_empty() {
+            return vec![PathBuf::from(
+                self.sanitizer.component(&self.unassigned_tag),
+            )];
+        }
+        doc.tags.iter().map(|t| self.tag_folder(&t.name)).collect()
+    }
+
+    /// Folder of the tag `name` in the `by_tag` view, nested if it contains `tag_separator`.
+    fn tag_folder(&self, name: &str) -> PathBuf {
+        match self.tag_separator.as_deref().filter(|s| !s.is_empty()) {
+            Some(separator) => name
+                .split(separator)
+                .map(str::trim)
+                .filter(|level| !level.is_empty())
+                .map(|level| self.sanitizer.component(level))
+                .collect(),
+            None => PathBuf::from(self.sanitizer.component(name)),
+        }
+    }
+
+    /// Name (possibly with subfolders) of `doc` in every view.
+    pub(crate) fn file_name(&self, doc: &Document) -> PathBuf {
+        match &self.filename_template {
+            Some(template) => PathBuf::from(template.render(doc, &self.sanitizer, &self.timezone)),
+            None => self.sanitizer.path(Path::new(&doc.archive_name)),
+        }
+    }
+
+    /// Assigns every document a unique file name, in the order of their pks so earlier documents
+    /// keep their names. Names differing only in case are considered equal, as they are on
+    /// Windows and macOS.
+    pub fn file_names<'a>(
+        &self,
+        documents: impl IntoIterator<Item = &'a Document>,
+    ) -> Result<FileNames, Error> {
+        let mut documents: Vec<_> = documents.into_iter().collect();
+        documents.sort_by_key(|d| d.pk);
+
+        let names = self.resolve_collisions(&documents, |doc| self.file_name(doc))?;
+        // documents are filed under their storage path, which may render the same for several
+        let storage_paths = match self.views.contains(&View::ByStoragePath)
+            && !self.layouts.contains_key(&View::ByStoragePath)
+        {
+            true => self.resolve_collisions(&documents, |doc| {
+                let rendered = doc.storage_path.as_ref().map(|storage_path| {
+                    storage_path::render(
+                        &storage_path.path,
+                        doc,
+                        self.created(doc),
+                        &self.sanitizer,
+                    )
+                });
+                match rendered.filter(|path| path.file_name().is_some()) {
+                    Some(path) => path,
+                    None => names[&doc.pk].clone(),
+                }
+            })?,
+            false => HashMap::new(),
+        };
+        Ok(FileNames {
+            names,
+            storage_paths,
+        })
+    }
+
+    /// The paths `path` gives `documents`, sorted by pk, made unique with `on_collision`.
+    fn resolve_collisions(
+        &self,
+        documents: &[&Document],
+        path: impl Fn(&Document) -> PathBuf,
+    ) -> Result<HashMap<i64, PathBuf>, Error> {
+        let key = |name: &Path| name.to_string_lossy().to_lowercase();
+        let mut taken = HashMap::new();
+        let mut names = HashMap::new();
+        for &doc in documents {
+            let name = path(doc);
+            let name = match taken.get(&key(&name)) {
+                None => name,
+                Some(&other) => match self.on_collision {
+                    CollisionStrategy::Abort => {
+                        return Err(Error::Collision {
+                            pk: doc.pk,
+                            other,
+                            name,
+                        })
+                    }
+                    CollisionStrategy::AppendPk => {
+                        unique(with_suffix(&name, &format!("_{}", doc.pk)), &taken, key)
+                    }
+                    CollisionStrategy::Counter => unique(name, &taken, key),
+                },
+            };
+            taken.insert(key(&name), doc.pk);
+            names.insert(doc.pk, name);
+        }
+        Ok(names)
+    }
+
+    /// File names of the organized `documents` and, with `restricted_dir`, of the excluded
+    /// ones, which are named independently as they end up in a directory of their own.
+    pub(crate) fn all_file_names(&self, documents: &[&Document]) -> Result<FileNames, Error> {
+        let mut names =
+            self.file_names(documents.iter().copied().filter(|d| !self.is_excluded(d)))?;
+        if self.restricted_dir.is_some() {
+            let restricted = documents.iter().copied().filter(|d| self.is_excluded(d));
+            names.names.extend(self.file_names(restricted)?.names);
+        }
+        Ok(names)
+    }
+
+    pub fn is_excluded(&self, doc: &Document) -> bool {
+        doc.tags.iter().any(|t| self.exclude.excludes_tag(&t.name))
+    }
+
+    /// The tags of `doc` that exclude it.
+    fn excluded_by(&self, doc: &Document) -> Vec<String> {
+        doc.tags
+            .iter()
+            .filter(|t| self.exclude.excludes_tag(&t.name))
+            .map(|t| t.name.clone())
+            .collect()
+    }
+
+    /// Whether documents are copied to `files/`; always the case for zipped exports and the
+    /// API, as the other views can only link to files on disk.
+    pub(crate) fn copies_files(&self) -> bool {
+        self.views.contains(&View::Files) || !self.export.is_directory()
+    }
+
+    /// Whether `doc` is copied to `files/`: as with [`copies_files`](Self::copies_files), and
+    /// always if paperless stored it encrypted, so the views don't link to the encrypted file.
+    pub(crate) fn copies(&self, doc: &Document) -> bool {
+        self.copies_files() || doc.encrypted
+    }
+
+    /// Shortens the output `path` so its part below the output directory has at most
+    /// `max_path_length` characters: first the file name, marked with `~pk` to keep it unique,
+    /// then, if the folders leave too little room for a name, the folders below the view from
+    /// the innermost out, marked with a hash of their full name so they stay apart.
+    fn fit(&self, path: PathBuf, pk: i64) -> PathBuf {
+        let Some(max_length) = self.max_path_length else {
+            return path;
+        };
+        let Ok(relative) = path.strip_prefix(&self.output_dir) else {
+            return path;
+        };
+        if relative.to_string_lossy().chars().count() <= max_length {
+            return path;
+        }
+        let mut components: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let Some(name) = components.pop() else {
+            return path;
+        };
+        let folders_length =
+            |folders: &[String]| folders.iter().map(|f| f.chars().count() + 1).sum::<usize>();
+
+        let budget = max_length.saturating_sub(MIN_NAME_LENGTH);
+        for i in (1..components.len()).rev() {
+            let excess = folders_length(&components).saturating_sub(budget);
+            if excess == 0 {
+                break;
+            }
+            let length = components[i].chars().count();
+            let shortened = length.saturating_sub(excess).max(MIN_FOLDER_LENGTH);
+            if shortened < length {
+                let marker = format!("~{}", checksum::short_hash(&components[i]));
+                components[i] = sanitize::shorten(&components[i], shortened, &marker);
+            }
+        }
+        let name_length = max_length.saturating_sub(folders_length(&components));
+        components.push(sanitize::shorten(&name, name_length, &format!("~{pk}")));
+        components
+            .into_iter()
+            .fold(self.output_dir.clone(), |path, c| path.join(c))
+    }
+
+    /// Computes every output path of `doc` without touching the file system.
+    pub fn outputs(&self, doc: &Document, names: &FileNames) -> DocumentOutputs {
+        let source = doc.archive_name.clone();
+        let name = names.get(doc).unwrap_or_else(|| self.file_name(doc));
+        // without a `files/` view, the other views refer to the export itself
+        let target = match self.export.file_path(&source) {
+            Some(path) if !self.copies(doc) => path,
+            _ => self.fit(
+                self.output_dir.join(View::Files.dir_name()).join(&name),
+                doc.pk,
+            ),
+        };
+        let view_links = self
+            .views
+            .iter()
+            .filter(|&&v| v != View::Files)
+            .flat_map(|&view| {
+                view.entries(doc, &name, names, self)
+                    .into_iter()
+                    .map(move |entry| (view.dir_name().to_owned(), entry))
+            });
+        let custom_links = self.custom_views.iter().flat_map(|view| {
+            view.grouping
+                .folders(doc, self)
+                .into_iter()
+                .map(|folder| (view.name.clone(), folder.join(&name)))
+        });
+        let links = view_links
+            .chain(custom_links)
+            .map(|(dir, entry)| {
+                let mut link = self.output_dir.join(&dir).join(entry);
+                if self.link_mode == LinkMode::Shortcut {
+                    link.as_mut_os_string()
+                        .push(format!(".{}", shortcut::EXTENSION));
+                }
+                (dir, self.fit(link, doc.pk))
+            })
+            .collect();
+        DocumentOutputs {
+            source,
+            target,
+            links,
+        }
+    }
+
+    /// The copy of the excluded `doc` in `restricted_dir`, if set.
+    pub(crate) fn restricted_outputs(
+        &self,
+        doc: &Document,
+        names: &FileNames,
+    ) -> Option<DocumentOutputs> {
+        let restricted_dir = self.restricted_dir.as_ref()?;
+        let mut target = restricted_dir.join(names.get(doc)?);
+        if self.encryption.is_some() {
+            target
+                .as_mut_os_string()
+                .push(format!(".{ENCRYPTED_EXTENSION}"));
+        }
+        Some(DocumentOutputs {
+            source: doc.archive_name.clone(),
+            target,
+            links: Vec::new(),
+        })
+    }
+
+    /// Paths and contents of the metadata, text and notes files written next to the copy
+    /// `target` of `doc`.
+    pub(crate) fn sidecars(&self, doc: &Document, target: &Path) -> Vec<(PathBuf, String)> {
+        let mut sidecars = Vec::new();
+        if !self.copies(doc) {
+            return sidecars;
+        }
+        if let Some(format) = self.sidecar {
+            sidecars.push((format.path_for(target), format.render(doc)));
+        }
+        if self.content_text && !doc.content.is_empty() {
+            let mut name = target.file_name().unwrap_or_default().to_owned();
+            name.push(".txt");
+            sidecars.push((target.with_file_name(name), doc.content.clone()));
+        }
+        if self.notes && !doc.notes.is_empty() {
+            sidecars.push((notes_path(target), notes_text(doc)));
+        }
+        sidecars
+    }
+
+    /// Outputs of the original file of `doc`, if `include_originals` is set.
+    ///
+    /// In the views, originals are named after the archive version with an `.original` infix
+    /// (`Invoice.original.png`). Documents without a separate archive version are only copied
+    /// to [`ORIGINALS_DIR`], as the views already contain their original.
+    pub fn original_outputs(&self, doc: &Document, names: &FileNames) -> Option<DocumentOutputs> {
+        if !self.include_originals {
+            return None;
+        }
+        let extension = Path::new(&doc.file_name).extension().unwrap_or_default();
+        let name = match &self.filename_template {
+            Some(_) => names
+                .get(doc)
+                .unwrap_or_else(|| self.file_name(doc))
+                .with_extension(extension),
+            None => self.sanitizer.path(Path::new(&doc.file_name)),
+        };
+        let links = if self.link_originals && doc.has_archive() {
+            self.outputs(doc, names)
+                .links
+                .into_iter()
+                .map(|(view, link)| {
+                    let mut name = link.file_stem().unwrap_or_default().to_owned();
+                    name.push(".original.");
+                    name.push(extension);
+                    (view, self.fit(link.with_file_name(name), doc.pk))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Some(DocumentOutputs {
+            source: doc.file_name.clone(),
+            target: self.fit(self.output_dir.join(ORIGINALS_DIR).join(name), doc.pk),
+            links,
+        })
+    }
+}
+
+impl FileNames {
+    pub(crate) fn get(&self, doc: &Document) -> Option<PathBuf> {
+        self.names.get(&doc.pk).cloned()
+    }
+
+    /// The name of the document `pk`, if it is one of the named documents.
+    pub(crate) fn by_pk(&self, pk: i64) -> Option<&Path> {
+        self.names.get(&pk).map(PathBuf::as_path)
+    }
+
+    /// The path of `doc` in the `by_storage_path` view.
+    fn storage_path(&self, doc: &Document) -> Option<&Path> {
+        self.storage_paths.get(&doc.pk).map(PathBuf::as_path)
+    }
+}
+
+/// `name`, or if that's taken the first free of `name (2)`, `name (3)`, ...
+fn unique<T>(name: PathBuf, taken: &HashMap<String, T>, key: impl Fn(&Path) -> String) -> PathBuf {
+    if !taken.contains_key(&key(&name)) {
+        return name;
+    }
+    (2..)
+        .map(|n| with_suffix(&name, &format!(" ({n})")))
+        .find(|name| !taken.contains_key(&key(name)))
+        .expect("some counter is free")
+}
+
+/// Appends `suffix` to the file stem of `name`, keeping the extension.
+fn with_suffix(name: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = name.file_stem().unwrap_or_default().to_owned();
+    file_name.push(suffix);
+    if let Some(extension) = name.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    name.with_file_name(file_name)
+}
+
+#[derive(Default)]
+pub struct Summary {
+    pub copied: u64,
+    pub skipped: u64,
+    /// Excluded documents copied to `OrganizeOptions::restricted_dir` instead of being skipped
+    pub restricted: u64,
+    /// Documents not matching `OrganizeOptions::filter`
+    pub filtered: u64,
+    pub unchanged: u64,
+    pub removed: u64,
+    pub bytes_copied: u64,
+    /// Documents that couldn't be organized; the run continues past them
+    pub failures: Vec<Error>,
+    /// What was mirrored to `OrganizeOptions::remote`, if set
+    pub upload: Option<UploadSummary>,
+    /// The commands `OrganizeOptions::hook` ran, if set
+    pub hook: Option<HookSummary>,
+    /// Manifest objects that were left out as malformed, see [`Manifest::malformed`]; only
+    /// known to the caller, as [`organize`] is handed the parsed manifest
+    pub malformed: Vec<Error>,
+}
+
+/// What happened to a single document.
+pub(crate) enum Outcome {
+    Skipped,
+    Copied {
+        bytes: u64,
+    },
+    Unchanged,
+    /// Copied to the restricted directory, `bytes` is 0 if it was up to date
+    Restricted {
+        bytes: u64,
+    },
+    Failed(Error),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Skipped => f.write_str("skipped"),
+            Outcome::Copied { bytes } => write!(f, "copied {}", HumanBytes(*bytes)),
+            Outcome::Unchanged => f.write_str("unchanged"),
+            Outcome::Restricted { bytes } => write!(f, "restricted, copied {}", HumanBytes(*bytes)),
+            Outcome::Failed(error) => write!(f, "failed: {error}"),
+        }
+    }
+}
+
+/// Regenerates the selected views in the output directory from `manifest`, or on
+/// `options.backend` if set.
+///
+/// Unless `options.incremental` is set, the views are wiped first. Otherwise existing outputs
+/// that are still current are kept and entries of vanished documents are removed at the end.
+/// Backends are always updated incrementally, going by what they list. Documents are
+/// processed in parallel on `options.jobs` threads.
+///
+/// Finished documents are journaled in the output directory; if a run is interrupted, the
+/// next one resumes it like an incremental run that skips the documents already finished.
+pub fn organize(manifest: &Manifest, options: &OrganizeOptions) -> anyhow::Result<Summary> {
+    let documents: Vec<_> = manifest
+        .documents
+        .iter()
+        .filter(|d| options.filter.matches(d, &options.timezone))
+        .collect();
+    let names = options.all_file_names(&documents)?;
+    if let Some(backend) = &options.backend {
+        check_backend(options, backend.as_ref())?;
+    }
+    if let Some(restricted_dir) = &options.restricted_dir {
+        create_dir_all(restricted_dir)?;
+        platform::restrict_dir(restricted_dir)?;
+    }
+
+    let dirs = options.dirs();
+
+    // an interrupted atomic run leaves the views untouched, so there is nothing to resume
+    let run = match (&options.backend, options.atomic) {
+        (Some(_), _) => None,
+        (None, true) => {
+            RunState::discard(&options.output_dir)?;
+            None
+        }
+        (None, false) => Some(RunState::open(&options.output_dir, &options.fingerprint())?),
+    };
+    let local = LocalBackend::new(&options.output_dir);
+    let pass = match &options.backend {
+        Some(backend) => Pass {
+            incremental: true,
+            undo: None,
+            output: backend.as_ref(),
+            output_dir: &options.output_dir,
+            existing: Some(
+                backend
+                    .list()?
+                    .into_iter()
+                    .map(|entry| (entry.path.clone(), entry))
+                    .collect(),
+            ),
+            link_mode: backend_link_mode(options.link_mode, backend.as_ref()),
+        },
+        None => Pass {
+            incremental: options.incremental || run.as_ref().is_some_and(RunState::is_resumed),
+            undo: options
+                .undoable
+                .then(|| UndoLog::start(&options.output_dir))
+                .transpose()?,
+            output: &local,
+            output_dir: &options.output_dir,
+            existing: None,
+            link_mode: options.link_mode,
+        },
+    };
+    // the restricted directory is on this machine even when the views are not
+    let local_pass = options.backend.is_some().then(|| Pass {
+        incremental: true,
+        undo: None,
+        output: &local,
+        output_dir: &options.output_dir,
+        existing: None,
+        link_mode: options.link_mode,
+    });
+    let restricted_pass = local_pass.as_ref().unwrap_or(&pass);
+    if options.atomic {
+        for dir in &dirs {
+            let _ = remove_dir_all(staging_dir(&options.output_dir, dir));
+        }
+    } else if !pass.incremental {
+        for dir in &dirs {
+            pass.remove(&options.output_dir.join(dir))?;
+        }
+    }
+
+    let progress = &options.progress;
+    progress.set_length(documents.len() as u64);
+    let bytes_copied = AtomicU64::new(0);
+
+    let pool = ThreadPoolBuilder::new().num_threads(options.jobs).build()?;
+    let results = pool.install(|| {
+        documents
+            .par_iter()
+            .map(|&doc| {
+                progress.set_message(format!(
+                    "{} copied, {}",
+                    HumanBytes(bytes_copied.load(Ordering::Relaxed)),
+                    doc.archive_name
+                ));
+                if let Some(outputs) = run.as_ref().and_then(|run| run.finished(doc.pk)) {
+                    progress.inc(1);
+                    let outcome = match options.is_excluded(doc) {
+                        false => Outcome::Unchanged,
+                        true if options.restricted_dir.is_some() => {
+                            Outcome::Restricted { bytes: 0 }
+                        }
+                        true => Outcome::Skipped,
+                    };
+                    return (outcome, outputs.to_vec());
+                }
+                let mut outputs = Vec::new();
+                let outcome =
+                    organize_document(doc, options, &pass, restricted_pass, &names, &mut outputs)
+                        .and_then(|outcome| {
+                            if let Some(run) = &run {
+                                run.record(doc.pk, &outputs)?;
+                            }
+                            Ok(outcome)
+                        })
+                        .unwrap_or_else(Outcome::Failed);
+                if let Outcome::Copied { bytes } | Outcome::Restricted { bytes } = outcome {
+                    bytes_copied.fetch_add(bytes, Ordering::Relaxed);
+                }
+                if enabled!(Level::DEBUG) {
+                    progress.suspend(|| debug!("{} ({}): {outcome}", doc.archive_name, doc.pk));
+                }
+                progress.inc(1);
+                (outcome, outputs)
+            })
+            .collect::<Vec<_>>()
+    });
+
+    progress.finish_and_clear();
+
+    let mut summary = Summary {
+        filtered: (manifest.documents.len() - documents.len()) as u64,
+        ..Summary::default()
+    };
+    let mut outputs = HashSet::new();
+    let mut organized_documents = Vec::new();
+    let mut copied_documents = Vec::new();
+    let (mut copies, mut unchanged, mut sources) = (Vec::new(), HashSet::new(), HashMap::new());
+    let mut index = Vec::new();
+    let mut report = Report::default();
+    for (&doc, (outcome, doc_outputs)) in documents.iter().zip(results) {
+        let organized = matches!(outcome, Outcome::Copied { .. } | Outcome::Unchanged);
+        let was_unchanged = matches!(outcome, Outcome::Unchanged);
+        if matches!(outcome, Outcome::Copied { .. }) {
+            copied_documents.push(doc);
+        }
+        if options.report {
+            match &outcome {
+                Outcome::Skipped | Outcome::Restricted { .. } => report.skip(
+                    doc,
+                    options.excluded_by(doc),
+                    matches!(outcome, Outcome::Restricted { .. }),
+                ),
+                Outcome::Failed(error) => report.fail(doc, error.to_string()),
+                _ => {}
+            }
+        }
+        match outcome {
+            Outcome::Skipped => summary.skipped += 1,
+            Outcome::Copied { bytes } => {
+                summary.copied += 1;
+                summary.bytes_copied += bytes;
+            }
+            Outcome::Unchanged => summary.unchanged += 1,
+            Outcome::Restricted { bytes } => {
+                summary.restricted += 1;
+                summary.bytes_copied += bytes;
+            }
+            Outcome::Failed(error) => summary.failures.push(error),
+        }
+        if organized {
+            organized_documents.push(doc);
+        }
+        if organized && options.checksums.is_some() && options.copies_files() {
+            let copied = std::iter::once(options.outputs(doc, &names))
+                .chain(options.original_outputs(doc, &names));
+            for DocumentOutputs { source, target, .. } in copied {
+                // the checksum files of backends aren't there to reuse hashes from
+                if was_unchanged && pass.existing.is_none() {
+                    unchanged.insert(target.clone());
+                }
+                sources.insert(target.clone(), (doc, source));
+                copies.push(target);
+            }
+        }
+        if organized && !options.index.is_empty() {
+            index.push(IndexEntry::new(
+                doc,
+                &options.outputs(doc, &names),
+                options.original_outputs(doc, &names).as_ref(),
+                &options.output_dir,
+            ));
+        }
+        outputs.extend(doc_outputs);
+    }
+
+    if options.atomic {
+        for dir in &dirs {
+            replace_dir(
+                &staging_dir(&options.output_dir, dir),
+                &options.output_dir.join(dir),
+                &pass,
+            )?;
+        }
+    }
+
+    if options.report {
+        pass.generate(|dir| {
+            pass.replace(&dir.join(report::SKIPPED_REPORT))?;
+            pass.replace(&dir.join(report::ERRORS_REPORT))?;
+            Ok(report.write(dir)?)
+        })?;
+    }
+
+    if !options.index.is_empty() {
+        pass.generate(|dir| {
+            for format in &options.index {
+                match format {
+                    IndexFormat::Markdown => continue,
+                    IndexFormat::Html => pass.replace(&dir.join(html::PAGES_DIR))?,
+                    _ => {}
+                }
+                pass.replace(&dir.join(format.file_name()))?;
+            }
+            index::write_index(dir, manifest, &index, &options.index, &options.timezone)
+        })?;
+    }
+    if options.index.contains(&IndexFormat::Markdown) {
+        write_vault(manifest, &index, options, &pass)?;
+    }
+
+    if !options.link_graph.is_empty() {
+        pass.generate(|dir| {
+            for format in &options.link_graph {
+                pass.replace(&dir.join(format.file_name()))?;
+            }
+            graph::write_graph(dir, &organized_documents, &names, &options.link_graph)
+        })?;
+    }
+
+    if let Some(layout) = options.checksums {
+        // copies on a backend can't be read back, so the exported files they are copies of
+        // are hashed instead
+        let hash = |file: &Path| match (&pass.existing, sources.get(file)) {
+            (Some(_), Some((doc, source))) => {
+                options.export.read_document(doc, source, checksum::sha256)
+            }
+            _ => checksum::sha256(&mut io::BufReader::new(fs::File::open(file)?)),
+        };
+        for (sums_file, files) in sums::sums_files(&options.output_dir, &copies, layout) {
+            let contents = sums::sums(&sums_file, &files, &unchanged, &hash)?;
+            pass.write_if_changed(&sums_file, &contents)?;
+            outputs.insert(sums_file);
+        }
+    }
+
+    if let Some(archive) = &options.archive {
+        let mut archives = Vec::new();
+        pass.generate(|dir| {
+            let written =
+                archive::write_archives(dir, &organized_documents, &names, options, archive)?;
+            archives.extend(written.into_iter().map(|path| {
+                let relative = path.strip_prefix(dir).unwrap_or(&path);
+                options.output_dir.join(relative)
+            }));
+            Ok(())
+        })?;
+        outputs.extend(archives);
+    }
+
+    match &pass.existing {
+        Some(existing) => {
+            let outputs: HashSet<_> = outputs.iter().map(|p| pass.path(p).to_owned()).collect();
+            summary.removed += backend::prune(pass.output, existing, &outputs, &dirs)?;
+        }
+        None if pass.incremental => {
+            for dir in &dirs {
+                summary.removed += sync::prune(&options.output_dir.join(dir), &outputs, &|path| {
+                    pass.remove(path)
+                })?;
+            }
+        }
+        None => {}
+    }
+    // not wiped up front, as it may be outside of the output directory
+    if let Some(restricted_dir) = &options.restricted_dir {
+        summary.removed += sync::prune(restricted_dir, &outputs, &|path| {
+            restricted_pass.remove(path)
+        })?;
+    }
+    pass.output.finish()?;
+
+    if let Some(run) = run {
+        run.finish()?;
+    }
+    if let Some(remote) = &options.remote {
+        summary.upload = Some(pool.install(|| remote.mirror(options))?);
+    }
+    if let Some(hook) = &options.hook {
+        let copies: Vec<_> = copied_documents
+            .into_iter()
+            .map(|doc| (doc, pass.display(&options.outputs(doc, &names).target)))
+            .collect();
+        summary.hook = Some(hook.run_all(&copies, &options.timezone)?);
+    }
+    Ok(summary)
+}
+
+/// Checks that the features `options` ask for work on `backend`; some need the output
+/// directory on the local file system.
+fn check_backend(options: &OrganizeOptions, backend: &dyn OutputBackend) -> anyhow::Result<()> {
+    if !options.views.contains(&View::Files) {
+        anyhow::bail!("writing to {backend} needs the `files` view");
+    }
+    let unsupported = [
+        (options.atomic, "atomic runs"),
+        (options.undoable, "undo logs"),
+        (options.remote.is_some(), "mirroring to a remote"),
+    ];
+    if let Some((_, feature)) = unsupported.iter().find(|(used, _)| *used) {
+        anyhow::bail!("{feature} are not supported when writing to {backend}");
+    }
+    Ok(())
+}
+
+/// `link_mode` as far as `backend` supports it: hard links can't be created through a
+/// backend, and neither can symlinks on some, so the views get copies instead.
+fn backend_link_mode(link_mode: LinkMode, backend: &dyn OutputBackend) -> LinkMode {
+    match link_mode {
+        LinkMode::Symlink if !backend.symlinks() => {
+            info!("{backend} has no symlinks, the views get copies instead");
+            LinkMode::Copy
+        }
+        LinkMode::Hardlink => LinkMode::Copy,
+        link_mode => link_mode,
+    }
+}
+
+/// State of a single run shared by the workers.
+struct Pass<'a> {
+    /// Whether existing outputs are updated, rather than written to wiped views
+    incremental: bool,
+    undo: Option<UndoLog>,
+    /// Receives the copies, links and generated files
+    output: &'a dyn OutputBackend,
+    /// The output directory, which output paths are below
+    output_dir: &'a Path,
+    /// What a backend has, by path; a local output directory is looked at directly instead
+    existing: Option<HashMap<PathBuf, Entry>>,
+    /// How the views refer to the copies, as far as `output` supports it
+    link_mode: LinkMode,
+}
+
+impl Pass<'_> {
+    /// Deletes the file or directory `path`, or moves it into the undo log if there is one.
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        match (&self.undo, path.symlink_metadata()) {
+            (Some(undo), _) => undo.remove(path),
+            (None, Ok(metadata)) if metadata.is_dir() => remove_dir_all(path),
+            (None, Ok(_)) => remove_file(path),
+            (None, Err(_)) => Ok(()),
+        }
+    }
+
+    /// Called before writing `path`: with an undo log, moves aside what is there.
+    fn replace(&self, path: &Path) -> io::Result<()> {
+        match &self.undo {
+            Some(undo) => undo.replace(path),
+            None => Ok(()),
+        }
+    }
+
+    /// The output `path` as `output` takes it, relative to the output directory. Paths
+    /// outside of it, like those in a restricted directory, stay absolute.
+    fn path<'p>(&self, path: &'p Path) -> &'p Path {
+        path.strip_prefix(self.output_dir).unwrap_or(path)
+    }
+
+    /// The output `path` for messages and hooks, on the backend if there is one.
+    fn display(&self, path: &Path) -> PathBuf {
+        match self.existing {
+            Some(_) => PathBuf::from(format!("{}/{}", self.output, self.path(path).display())),
+            None => path.to_owned(),
+        }
+    }
+
+    /// Whether the output `target` is an up-to-date copy of the exported file `source` of
+    /// `doc`, with the modification time `stamp` if set.
+    fn copy_up_to_date(
+        &self,
+        doc: &Document,
+        source: &str,
+        target: &Path,
+        options: &OrganizeOptions,
+        stamp: Option<SystemTime>,
+    ) -> io::Result<bool> {
+        if !self.incremental {
+            return Ok(false);
+        }
+        let export = &options.export;
+        match &self.existing {
+            Some(existing) => Ok(backend::up_to_date(
+                doc,
+                &export.file_info(source)?,
+                stamp,
+                existing.get(self.path(target)),
+            )),
+            None if doc.encrypted => sync::converted_up_to_date(export, source, target, stamp),
+            None => {
+                sync::export_up_to_date(export, source, target, options.compare_contents, stamp)
+            }
+        }
+    }
+
+    /// Whether the output `link` already refers to `original`, which is a copy of the
+    /// exported file `source` of `doc`.
+    fn link_up_to_date(
+        &self,
+        doc: &Document,
+        source: &str,
+        original: &Path,
+        link: &Path,
+        options: &OrganizeOptions,
+    ) -> io::Result<bool> {
+        if !self.incremental {
+            return Ok(false);
+        }
+        let Some(existing) = &self.existing else {
+            return sync::link_up_to_date(self.link_mode, original, link, options.compare_contents);
+        };
+        let entry = existing.get(self.path(link));
+        Ok(match self.link_mode {
+            LinkMode::Symlink => {
+                entry.is_some_and(|entry| entry.kind == EntryKind::Symlink)
+                    && self
+                        .output
+                        .read_link(self.path(link))
+                        .is_ok_and(|t| t == original)
+            }
+            LinkMode::Copy => {
+                let stamp = options
+                    .timestamps_from_created
+                    .then(|| SystemTime::from(doc.created));
+                let info = options.export.file_info(source)?;
+                backend::up_to_date(doc, &info, stamp, entry)
+            }
+            // shortcuts only depend on their paths
+            _ => entry.is_some_and(|entry| entry.kind == EntryKind::File),
+        })
+    }
+
+    /// Writes `contents` to `path` unless it already has exactly these contents, to keep the
+    /// modification time of unchanged files. Backends are always written to.
+    fn write_if_changed(&self, path: &Path, contents: &str) -> io::Result<()> {
+        if self.existing.is_none()
+            && fs::read(path).is_ok_and(|existing| existing == contents.as_bytes())
+        {
+            return Ok(());
+        }
+        self.replace(path)?;
+        self.output
+            .write(self.path(path), &mut contents.as_bytes(), None)
+            .map(|_| ())
+    }
+
+    /// Has `write` generate files in the output directory; for a backend, in a scratch
+    /// directory whose files are then uploaded to the same paths.
+    fn generate(&self, write: impl FnOnce(&Path) -> anyhow::Result<()>) -> anyhow::Result<()> {
+        if self.existing.is_none() {
+            create_dir_all(self.output_dir)?;
+            return write(self.output_dir);
+        }
+        let scratch = env::temp_dir().join(format!(".paperless-organize-{}", process::id()));
+        let _ = remove_dir_all(&scratch);
+        create_dir_all(&scratch)?;
+        let result = write(&scratch).and_then(|()| backend::upload_dir(&scratch, self.output));
+        let _ = remove_dir_all(&scratch);
+        result.map(|_| ())
+    }
+}
+
+/// Writes the Markdown notes of `entries` note by note, so only changed notes are replaced and
+/// what users wrote below the marker is kept, then removes the notes of documents that are gone.
+/// The notes on a backend can't be read back, their edits are replaced.
+fn write_vault(
+    manifest: &Manifest,
+    entries: &[IndexEntry],
+    options: &OrganizeOptions,
+    pass: &Pass,
+) -> io::Result<()> {
+    let mut written = HashSet::new();
+    for (path, note) in markdown::notes(&options.output_dir, manifest, entries) {
+        let existing = match pass.existing {
+            Some(_) => None,
+            None => fs::read_to_string(&path).ok(),
+        };
+        pass.write_if_changed(&path, &markdown::keep_edits(&note, existing.as_deref()))?;
+        written.insert(path);
+    }
+    match &pass.existing {
+        Some(existing) => {
+            let written = written.iter().map(|p| pass.path(p).to_owned()).collect();
+            backend::prune(
+                pass.output,
+                existing,
+                &written,
+                &[markdown::VAULT_DIR.to_owned()],
+            )?;
+        }
+        None => {
+            let vault_dir = options.output_dir.join(markdown::VAULT_DIR);
+            sync::prune(&vault_dir, &written, &|path| pass.remove(path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves the freshly built `staged` directory to `dir`, replacing what was there.
+fn replace_dir(staged: &Path, dir: &Path, pass: &Pass) -> io::Result<()> {
+    if pass.undo.is_some() {
+        pass.replace(dir)?;
+        if staged.exists() {
+            fs::rename(staged, dir)?;
+        }
+        return Ok(());
+    }
+    let mut old = dir.as_os_str().to_owned();
+    old.push(".old");
+    let old = PathBuf::from(old);
+    let _ = remove_dir_all(&old);
+    if dir.exists() {
+        fs::rename(dir, &old)?;
+    }
+    if staged.exists() {
+        fs::rename(staged, dir)?;
+    }
+    let _ = remove_dir_all(old);
+    Ok(())
+}
+
+fn create_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) => create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Copies and links a single document, recording every output path in `outputs`. Unless
+/// the pass is incremental, the views are expected to have been wiped. Excluded documents
+/// go to the restricted directory through `restricted_pass`.
+fn organize_document(
+    doc: &Document,
+    options: &OrganizeOptions,
+    pass: &Pass,
+    restricted_pass: &Pass,
+    names: &FileNames,
+    outputs: &mut Vec<PathBuf>,
+) -> Result<Outcome, Error> {
+    if options.is_excluded(doc) {
+        options.progress.suspend(|| {
+            info!(
+                "{} {} ({})",
+                match (&options.restricted_dir, &options.encryption) {
+                    (Some(_), Some(_)) => "encrypting",
+                    (Some(_), None) => "restricting",
+                    (None, _) => "skipping",
+                },
+                doc.archive_name,
+                doc.tags
+                    .iter()
+                    .map(|t| t.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        });
+        let Some(restricted) = options.restricted_outputs(doc, names) else {
+            return Ok(Outcome::Skipped);
+        };
+        if let Some(encryption) = &options.encryption {
+            return write_encrypted(
+                doc,
+                restricted,
+                encryption,
+                options,
+                restricted_pass,
+                outputs,
+            );
+        }
+        return Ok(
+            match write_outputs(doc, restricted, true, options, restricted_pass, outputs)? {
+                Outcome::Copied { bytes } => Outcome::Restricted { bytes },
+                _ => Outcome::Restricted { bytes: 0 },
+            },
+        );
+    }
+
+    let archive = options.outputs(doc, names);
+    let target = archive.target.clone();
+    let mut outcome = write_outputs(doc, archive, options.copies(doc), options, pass, outputs)?;
+    for (path, contents) in options.sidecars(doc, &target) {
+        pass.write_if_changed(&options.staged(&path), &contents)
+            .map_err(|error| Error::Io { pk: doc.pk, error })?;
+        outputs.push(path);
+    }
+    if let Some(original) = options.original_outputs(doc, names) {
+        if let Outcome::Copied { bytes } =
+            write_outputs(doc, original, true, options, pass, outputs)?
+        {
+            outcome = match outcome {
+                Outcome::Copied { bytes: archive } => Outcome::Copied {
+                    bytes: archive + bytes,
+                },
+                _ => Outcome::Copied { bytes },
+            };
+        }
+    }
+    Ok(outcome)
+}
+
+/// Encrypts the exported file of the excluded `doc` to the target of `restricted`.
+fn write_encrypted(
+    doc: &Document,
+    restricted: DocumentOutputs,
+    encryption: &Encryption,
+    options: &OrganizeOptions,
+    pass: &Pass,
+    outputs: &mut Vec<PathBuf>,
+) -> Result<Outcome, Error> {
+    let DocumentOutputs { source, target, .. } = restricted;
+    outputs.push(target.clone());
+    if pass.incremental
+        && sync::converted_up_to_date(&options.export, &source, &target, None)
+            .map_err(|error| Error::Io { pk: doc.pk, error })?
+    {
+        return Ok(Outcome::Restricted { bytes: 0 });
+    }
+    let bytes = create_parent_dir(&target)
+        .and_then(|()| pass.replace(&target))
+        .and_then(|()| encryption.encrypt_to(&options.export, doc, &source, &target))
+        .map_err(|error| Error::Copy {
+            pk: doc.pk,
+            from: options.export.display_path(&source),
+            to: target.clone(),
+            error,
+        })?;
+    Ok(Outcome::Restricted { bytes })
+}
+
+/// Copies one exported file to its target (if `copy` is set) and creates its links in the views.
+fn write_outputs(
+    doc: &Document,
+    DocumentOutputs {
+        source,
+        target,
+        links,
+    }: DocumentOutputs,
+    copy: bool,
+    options: &OrganizeOptions,
+    pass: &Pass,
+    outputs: &mut Vec<PathBuf>,
+) -> Result<Outcome, Error> {
+    let pk = doc.pk;
+    let io_error = |error| Error::Io { pk, error };
+    let export = &options.export;
+    let stamp = options
+        .timestamps_from_created
+        .then(|| SystemTime::from(doc.created));
+    let set_time = |path: &Path| match stamp {
+        Some(time) => platform::set_file_time(path, time),
+        None => Ok(()),
+    };
+
+    let mut outcome = if copy {
+        outputs.push(target.clone());
+        if pass
+            .copy_up_to_date(doc, &source, &target, options, stamp)
+            .map_err(io_error)?
+        {
+            Outcome::Unchanged
+        } else {
+            let staged = options.staged(&target);
+            let bytes = pass
+                .replace(&staged)
+                .and_then(|()| {
+                    pass.output
+                        .copy_document(export, doc, &source, pass.path(&staged), stamp)
+                })
+                .map_err(|error| Error::Copy {
+                    pk,
+                    from: export.display_path(&source),
+                    to: pass.display(&staged),
+                    error,
+                })?;
+            Outcome::Copied { bytes }
+        }
+    } else {
+        // without the files view, only writing a link counts as copying the document
+        Outcome::Unchanged
+    };
+
+    for (view, link) in links {
+        outputs.push(link.clone());
+        // symlinks already point to where the target ends up, the others need the file itself
+        let original = match pass.link_mode {
+            // absolute paths would point into this machine's output directory
+            LinkMode::Symlink if pass.existing.is_some() => link::relative_path(&link, &target),
+            LinkMode::Symlink => options.symlink_target(&target, &link),
+            LinkMode::Shortcut => target.clone(),
+            _ => options.staged(&target),
+        };
+        if pass
+            .link_up_to_date(doc, &source, &original, &link, options)
+            .map_err(io_error)?
+        {
+            continue;
+        }
+        let staged = options.staged(&link);
+        if enabled!(Level::TRACE) {
+            options
+                .progress
+                .suspend(|| trace!("linking {} to {}", staged.display(), original.display()));
+        }
+        let output = pass.output;
+        pass.replace(&staged)
+            .and_then(|()| match pass.link_mode {
+                LinkMode::Symlink => output.symlink(&original, pass.path(&staged)),
+                LinkMode::Shortcut => {
+                    let shortcut = shortcut::shortcut(&original, &staged)?;
+                    output
+                        .write(pass.path(&staged), &mut shortcut.as_slice(), None)
+                        .map(|_| ())
+                }
+                LinkMode::Copy if pass.existing.is_some() => output
+                    .copy_document(export, doc, &source, pass.path(&staged), stamp)
+                    .map(|_| ()),
+                // hard links and copies of the copy, in the output directory
+                link_mode => {
+                    create_parent_dir(&staged)?;
+                    if pass.incremental {
+                        let _ = remove_file(&staged);
+                    }
+                    link_mode.link(&original, &staged)?;
+                    match link_mode {
+                        LinkMode::Copy => set_time(&staged),
+                        _ => Ok(()),
+                    }
+                }
+            })
+            .map_err(|error| Error::Link {
+                pk,
+                path: pass.display(&link),
+                view,
+                error,
+            })?;
+        if !copy {
+            outcome = Outcome::Copied { bytes: 0 };
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// `Invoice.notes.txt` for the copy `Invoice.pdf`.
+fn notes_path(target: &Path) -> PathBuf {
+    let mut name = target.file_stem().unwrap_or_default().to_owned();
+    name.push(".notes.txt");
+    target.with_file_name(name)
+}
+
+/// The notes of `doc`, oldest first, each headed by its creation time.
+fn notes_text(doc: &Document) -> String {
+    doc.notes
+        .iter()
+        .map(|note| {
+            format!(
+                "{}\n{}\n",
+                note.created.format("%Y-%m-%d %H:%M UTC"),
+                note.text.trim_end()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Removes the generated `views` and `custom_views` from `output_dir`, returning how many
+/// existed. Removing `files` also removes the copied originals.
+pub fn clean(output_dir: &Path, views: &[View], custom_views: &[CustomView]) -> io::Result<usize> {
+    let mut dirs: Vec<_> = views.iter().map(|v| v.dir_name()).collect();
+    dirs.extend(custom_views.iter().map(|v| v.name.as_str()));
+    if views.contains(&View::Files) {
+        dirs.push(ORIGINALS_DIR);
+    }
+    RunState::discard(output_dir)?;
+    let mut removed = 0;
+    for dir in dirs {
+        let dir = output_dir.join(dir);
+        if dir.exists() {
+            remove_dir_all(dir)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn date_depths_parse_from_their_names() {
+        let depths = [
+            ("decade", DateDepth::Decade),
+            ("year", DateDepth::Year),
+            ("month", DateDepth::Month),
+            ("week", DateDepth::Week),
+            ("day", DateDepth::Day),
+        ];
+        for (name, depth) in depths {
+            assert_eq!(DateDepth::from_str(name, false), Ok(depth));
+            assert_eq!(
+                serde_json::from_value::<DateDepth>(name.into()).unwrap(),
+                depth
+            );
+        }
+        for name in ["", "Year", "years", "quarter", "by_month"] {
+            assert!(DateDepth::from_str(name, false).is_err(), "{name}");
+            assert!(
+                serde_json::from_value::<DateDepth>(name.into()).is_err(),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn date_depths_nest_folders() {
+        let time = date("2024-12-30").and_hms_opt(10, 0, 0).unwrap();
+        assert_eq!(DateDepth::Decade.folder(time), Path::new("2020s"));
+        assert_eq!(DateDepth::Year.folder(time), Path::new("2024"));
+        assert_eq!(DateDepth::Month.folder(time), Path::new("2024/12"));
+        assert_eq!(DateDepth::Week.folder(time), Path::new("2025/W01"));
+        assert_eq!(DateDepth::Day.folder(time), Path::new("2024/12/30"));
+    }
+
+    #[test]
+    fn tax_years_start_on_the_configured_day() {
+        let uk: TaxYearStart = "04-06".parse().unwrap();
+        assert_eq!(uk.folder(date("2024-04-05")), "2023-24");
+        assert_eq!(uk.folder(date("2024-04-06")), "2024-25");
+        assert_eq!(uk.folder(date("2024-01-01")), "2023-24");
+        assert_eq!(uk.folder(date("1999-12-31")), "1999-00");
+        assert_eq!(TaxYearStart::default().folder(date("2024-01-01")), "2024");
+        assert_eq!(TaxYearStart::default().folder(date("2023-12-31")), "2023");
+    }
+
+    #[test]
+    fn tax_year_starts_are_month_and_day() {
+        assert!("02-29".parse::<TaxYearStart>().is_ok());
+        for start in ["", "4", "04/06", "13-01", "04-31", "00-10", "april-6"] {
+            assert!(start.parse::<TaxYearStart>().is_err(), "{start}");
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{
+    archive,
+    link::LinkMode,
+    organize::{DocumentOutputs, OrganizeOptions},
+    report, sums, sync, Document, Manifest,
+};
+
+/// Everything `organize` would do with the same options, in the order it would do it.
+#[derive(Default, Serialize)]
+pub struct Plan {
+    pub operations: Vec<Operation>,
+}
+
+/// A single change to the output directory.
+#[derive(Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// Copy an exported file, encrypting it if the destination ends in `.age`
+    Copy {
+        pk: i64,
+        source: PathBuf,
+        destination: PathBuf,
+        reason: Reason,
+    },
+    /// Make a view entry refer to a copy
+    Link {
+        pk: i64,
+        target: PathBuf,
+        destination: PathBuf,
+        mode: LinkMode,
+        reason: Reason,
+    },
+    /// Write a generated file: a sidecar of the document `pk`, or an index or report
+    Write {
+        pk: Option<i64>,
+        destination: PathBuf,
+        reason: Reason,
+    },
+    /// Delete a file, or a view with everything in it
+    Delete { path: PathBuf, reason: Reason },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// Nothing is there yet
+    New,
+    /// What is there is outdated
+    Changed,
+    /// A view that is regenerated from scratch
+    Regenerated,
+    /// An output of a document that is no longer organized, or under another name
+    Stale,
+}
+
+impl Plan {
+    /// Works out what organizing `manifest` with `options` would change, without touching the
+    /// file system.
+    pub fn new(manifest: &Manifest, options: &OrganizeOptions) -> anyhow::Result<Self> {
+        let documents: Vec<_> = manifest
+            .documents
+            .iter()
+            .filter(|d| options.filter.matches(d, &options.timezone))
+            .collect();
+        let names = options.all_file_names(&documents)?;
+        let dirs: Vec<_> = options
+            .dirs()
+            .iter()
+            .map(|dir| options.output_dir.join(dir))
+            .collect();
+
+        let mut plan = Plan::default();
+        if !options.incremental {
+            for dir in &dirs {
+                if dir.exists() {
+                    plan.operations.push(Operation::Delete {
+                        path: dir.clone(),
+                        reason: Reason::Regenerated,
+                    });
+                }
+            }
+        }
+        // what is in a wiped view doesn't count as being there
+        let existing = |path: &Path| {
+            (options.incremental || !dirs.iter().any(|dir| path.starts_with(dir)))
+                && path.symlink_metadata().is_ok()
+        };
+
+        let mut keep = HashSet::new();
+        for &doc in &documents {
+            if options.is_excluded(doc) {
+                match options.restricted_outputs(doc, &names) {
+                    Some(restricted) if options.encryption.is_some() => {
+                        plan.add_encrypted(doc, restricted, options, &existing, &mut keep)?
+                    }
+                    Some(restricted) => {
+                        plan.add_outputs(doc, restricted, true, options, &existing, &mut keep)?
+                    }
+                    None => {}
+                }
+                continue;
+            }
+            let archive = options.outputs(doc, &names);
+            let target = archive.target.clone();
+            plan.add_outputs(
+                doc,
+                archive,
+                options.copies(doc),
+                options,
+                &existing,
+                &mut keep,
+            )?;
+            for (path, contents) in options.sidecars(doc, &target) {
+                let changed = fs::read(&path).map_or(true, |c| c != contents.as_bytes());
+                if !existing(&path) || changed {
+                    plan.operations.push(Operation::Write {
+                        pk: Some(doc.pk),
+                        reason: reason(existing(&path)),
+                        destination: path.clone(),
+                    });
+                }
+                keep.insert(path);
+            }
+            if let Some(original) = options.original_outputs(doc, &names) {
+                plan.add_outputs(doc, original, true, options, &existing, &mut keep)?;
+            }
+        }
+
+        let mut generated: Vec<_> = options.index.iter().map(|f| f.file_name()).collect();
+        generated.extend(options.link_graph.iter().map(|f| f.file_name()));
+        if options.report {
+            generated.extend([report::SKIPPED_REPORT, report::ERRORS_REPORT]);
+        }
+        for name in generated {
+            let path = options.output_dir.join(name);
+            plan.operations.push(Operation::Write {
+                pk: None,
+                reason: reason(path.exists()),
+                destination: path,
+            });
+        }
+
+        if let (Some(layout), true) = (options.checksums, options.copies_files()) {
+            let copies: Vec<_> = documents
+                .iter()
+                .filter(|doc| !options.is_excluded(doc))
+                .flat_map(|doc| {
+                    std::iter::once(options.outputs(doc, &names))
+                        .chain(options.original_outputs(doc, &names))
+                        .map(|outputs| outputs.target)
+                })
+                .collect();
+            for path in sums::sums_files(&options.output_dir, &copies, layout).into_keys() {
+                plan.operations.push(Operation::Write {
+                    pk: None,
+                    reason: reason(existing(&path)),
+                    destination: path.clone(),
+                });
+                keep.insert(path);
+            }
+        }
+        if let Some(archive) = &options.archive {
+            let organized: Vec<_> = documents
+                .iter()
+                .copied()
+                .filter(|doc| !options.is_excluded(doc))
+                .collect();
+            for path in archive::archive_paths(&organized, &names, options, archive) {
+                plan.operations.push(Operation::Write {
+                    pk: None,
+                    reason: reason(existing(&path)),
+                    destination: path.clone(),
+                });
+                keep.insert(path);
+            }
+        }
+
+        let mut pruned = Vec::new();
+        if options.incremental {
+            pruned.extend(&dirs);
+        }
+        pruned.extend(&options.restricted_dir);
+        for dir in pruned {
+            for entry in WalkDir::new(dir).into_iter().filter_map(Result::ok) {
+                if !entry.file_type().is_dir() && !keep.contains(entry.path()) {
+                    plan.operations.push(Operation::Delete {
+                        path: entry.into_path(),
+                        reason: Reason::Stale,
+                    });
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Adds the copy (if `copy` is set) and the links of `outputs` that aren't up to date.
+    fn add_encrypted(
+        &mut self,
+        doc: &Document,
+        outputs: DocumentOutputs,
+        options: &OrganizeOptions,
+        existing: &dyn Fn(&Path) -> bool,
+        keep: &mut HashSet<PathBuf>,
+    ) -> io::Result<()> {
+        let exists = existing(&outputs.target);
+        if !exists
+            || !sync::converted_up_to_date(&options.export, &outputs.source, &outputs.target, None)?
+        {
+            self.operations.push(Operation::Copy {
+                pk: doc.pk,
+                source: options.export.display_path(&outputs.source),
+                destination: outputs.target.clone(),
+                reason: reason(exists),
+            });
+        }
+        keep.insert(outputs.target);
+        Ok(())
+    }
+
+    fn add_outputs(
+        &mut self,
+        doc: &Document,
+        outputs: DocumentOutputs,
+        copy: bool,
+        options: &OrganizeOptions,
+        existing: &dyn Fn(&Path) -> bool,
+        keep: &mut HashSet<PathBuf>,
+    ) -> io::Result<()> {
+        let stamp = options
+            .timestamps_from_created
+            .then(|| SystemTime::from(doc.created));
+        let target = outputs.target;
+        if copy {
+            let exists = existing(&target);
+            let up_to_date = || match doc.encrypted {
+                true => {
+                    sync::converted_up_to_date(&options.export, &outputs.source, &target, stamp)
+                }
+                false => sync::export_up_to_date(
+                    &options.export,
+                    &outputs.source,
+                    &target,
+                    options.compare_contents,
+                    stamp,
+                ),
+            };
+            if !exists || !up_to_date()? {
+                self.operations.push(Operation::Copy {
+                    pk: doc.pk,
+                    source: options.export.display_path(&outputs.source),
+                    destination: target.clone(),
+                    reason: reason(exists),
+                });
+            }
+            keep.insert(target.clone());
+        }
+        for (_, link) in outputs.links {
+            let original = match options.link_mode {
+                LinkMode::Symlink => options.symlink_target(&target, &link),
+                _ => target.clone(),
+            };
+            let exists = existing(&link);
+            if !exists
+                || !sync::link_up_to_date(
+                    options.link_mode,
+                    &original,
+                    &link,
+                    options.compare_contents,
+                )?
+            {
+                self.operations.push(Operation::Link {
+                    pk: doc.pk,
+                    target: original,
+                    destination: link.clone(),
+                    mode: options.link_mode,
+                    reason: reason(exists),
+                });
+            }
+            keep.insert(link);
+        }
+        Ok(())
+    }
+
+    /// Numbers of copies, links, written files and deletions.
+    pub fn counts(&self) -> (usize, usize, usize, usize) {
+        let mut counts = (0, 0, 0, 0);
+        for operation in &self.operations {
+            match operation {
+                Operation::Copy { .. } => counts.0 += 1,
+                Operation::Link { .. } => counts.1 += 1,
+                Operation::Write { .. } => counts.2 += 1,
+                Operation::Delete { .. } => counts.3 += 1,
+            }
+        }
+        counts
+    }
+}
+
+fn reason(exists: bool) -> Reason {
+    if exists {
+        Reason::Changed
+    } else {
+        Reason::New
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    fs::{File, FileTimes},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// `path` made absolute and `\\?\`-prefixed, so paths below it may exceed `MAX_PATH`.
+#[cfg(windows)]
+pub fn extended_path(path: &Path) -> io::Result<PathBuf> {
+    use std::path::{Component, Prefix};
+    let path = std::path::absolute(path)?;
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return Ok(path);
+    };
+    // the root and everything after it; joining it keeps the new prefix
+    let rest = components.as_path();
+    Ok(match prefix.kind() {
+        Prefix::Disk(_) => {
+            let mut extended = std::ffi::OsString::from(r"\\?\");
+            extended.push(prefix.as_os_str());
+            PathBuf::from(extended).join(rest)
+        }
+        Prefix::UNC(server, share) => {
+            let mut extended = std::ffi::OsString::from(r"\\?\UNC\");
+            extended.push(server);
+            extended.push(r"\");
+            extended.push(share);
+            PathBuf::from(extended).join(rest)
+        }
+        // already verbatim or a device path
+        _ => path,
+    })
+}
+
+/// Unix has no path length limit worth working around.
+#[cfg(unix)]
+pub fn extended_path(path: &Path) -> io::Result<PathBuf> {
+    Ok(path.to_owned())
+}
+
+/// `path` without the `\\?\` prefix of [`extended_path`], which browsers and other programs
+/// linking to it don't understand.
+pub fn plain_path(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy();
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{rest}"))
+    } else if let Some(rest) = text.strip_prefix(r"\\?\") {
+        PathBuf::from(rest)
+    } else {
+        path.to_owned()
+    }
+}
+
+/// Creates a symlink at `link` pointing to the file `original`.
+#[cfg(windows)]
+pub fn symlink_file(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(original, link)
+}
+
+/// Creates a symlink at `link` pointing to the file `original`.
+#[cfg(unix)]
+pub fn symlink_file(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+/// Sets the modification and creation time of the file at `path`.
+#[cfg(windows)]
+pub fn set_file_time(path: &Path, time: SystemTime) -> io::Result<()> {
+    use std::os::windows::fs::FileTimesExt;
+    let times = FileTimes::new().set_modified(time).set_created(time);
+    File::options().write(true).open(path)?.set_times(times)
+}
+
+/// Sets the modification time of the file at `path`; Unix has no settable creation time.
+#[cfg(unix)]
+pub fn set_file_time(path: &Path, time: SystemTime) -> io::Result<()> {
+    let times = FileTimes::new().set_modified(time);
+    File::options().write(true).open(path)?.set_times(times)
+}
+
+/// Makes the directory `path` accessible to its owner only.
+#[cfg(unix)]
+pub fn restrict_dir(path: &Path) -> io::Result<()> {
+    use std::{fs, os::unix::fs::PermissionsExt};
+    fs::set_permissions(path, fs::Permissions::from_mode(0o700))
+}
+
+/// Windows directories inherit the ACL of their parent, which is left alone.
+#[cfg(windows)]
+pub fn restrict_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,314 @@
+use std::{iter::Peekable, str::FromStr, vec::IntoIter};
+
+use anyhow::{anyhow, bail};
+use chrono::NaiveDate;
+
+use crate::{
+    timezone::{self, TimeZone},
+    Document,
+};
+
+/// A boolean document selection like
+/// `tag:invoice AND correspondent:'ACME' AND created >= 2022-01-01 AND NOT tag:private`.
+///
+/// Supported terms are `tag:`, `correspondent:`, `document_type:` (case-insensitive equality),
+/// `title:` (case-insensitive substring) and comparisons of `created` with a `YYYY-MM-DD` date.
+/// Terms combine with `NOT`, `AND`, `OR` (in decreasing precedence) and parentheses.
+#[derive(Clone, Debug)]
+pub enum Query {
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Tag(String),
+    Correspondent(String),
+    DocumentType(String),
+    Title(String),
+    Created(Comparison, NaiveDate),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn holds<T: Ord>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+}
+
+impl Query {
+    /// Whether `doc` matches, comparing its creation day in `timezone`.
+    pub fn matches(&self, doc: &Document, timezone: &TimeZone) -> bool {
+        let eq = |a: &str, b: &str| a.to_lowercase() == b.to_lowercase();
+        match self {
+            Query::Not(q) => !q.matches(doc, timezone),
+            Query::And(a, b) => a.matches(doc, timezone) && b.matches(doc, timezone),
+            Query::Or(a, b) => a.matches(doc, timezone) || b.matches(doc, timezone),
+            Query::Tag(name) => doc.tags.iter().any(|t| eq(&t.name, name)),
+            Query::Correspondent(name) => doc
+                .correspondent
+                .as_ref()
+                .is_some_and(|c| eq(&c.name, name)),
+            Query::DocumentType(name) => doc
+                .document_type
+                .as_ref()
+                .is_some_and(|t| eq(&t.name, name)),
+            Query::Title(text) => doc.title.to_lowercase().contains(&text.to_lowercase()),
+            Query::Created(cmp, date) => {
+                cmp.holds(timezone::created(doc, timezone).date_naive(), *date)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    Colon,
+    And,
+    Or,
+    Not,
+    Cmp(Comparison),
+    Word(String),
+}
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ':' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    _ => Token::Colon,
+                });
+            }
+            '<' | '>' | '=' | '!' => {
+                chars.next();
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                tokens.push(Token::Cmp(match (c, or_equal) {
+                    ('<', false) => Comparison::Lt,
+                    ('<', true) => Comparison::Le,
+                    ('>', false) => Comparison::Gt,
+                    ('>', true) => Comparison::Ge,
+                    ('=', _) => Comparison::Eq,
+                    ('!', true) => Comparison::Ne,
+                    _ => bail!("expected `!=` in query `{input}`"),
+                }));
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(ch) => word.push(ch),
+                        None => bail!("unterminated quote in query `{input}`"),
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(ch) =
+                    chars.next_if(|ch| !ch.is_whitespace() && !"():<>=!'\"".contains(*ch))
+                {
+                    word.push(ch);
+                }
+                tokens.push(match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    fn or(&mut self) -> anyhow::Result<Query> {
+        let mut lhs = self.and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            lhs = Query::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Query> {
+        let mut lhs = self.unary()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            lhs = Query::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Query> {
+        if self.tokens.next_if_eq(&Token::Not).is_some() {
+            return Ok(Query::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Query> {
+        match self.tokens.next() {
+            Some(Token::LParen) => {
+                let query = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::RParen) => Ok(query),
+                    _ => bail!("expected `)`"),
+                }
+            }
+            Some(Token::Word(field)) => match self.tokens.next() {
+                Some(Token::Colon) => {
+                    let value = self.value(&field)?;
+                    match field.to_lowercase().as_str() {
+                        "tag" => Ok(Query::Tag(value)),
+                        "correspondent" => Ok(Query::Correspondent(value)),
+                        "document_type" | "type" => Ok(Query::DocumentType(value)),
+                        "title" => Ok(Query::Title(value)),
+                        _ => bail!("unknown field `{field}`"),
+                    }
+                }
+                Some(Token::Cmp(cmp)) => {
+                    let value = self.value(&field)?;
+                    match field.to_lowercase().as_str() {
+                        "created" => Ok(Query::Created(
+                            cmp,
+                            NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                                .map_err(|e| anyhow!("invalid date `{value}`: {e}"))?,
+                        )),
+                        _ => bail!("field `{field}` can't be compared, only `created` can"),
+                    }
+                }
+                _ => bail!("expected `:` or a comparison after `{field}`"),
+            },
+            Some(token) => bail!("unexpected {token:?}"),
+            None => bail!("unexpected end of query"),
+        }
+    }
+
+    fn value(&mut self, field: &str) -> anyhow::Result<String> {
+        match self.tokens.next() {
+            Some(Token::Word(value)) => Ok(value),
+            _ => bail!("expected a value for `{field}`"),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let query = parser
+            .or()
+            .map_err(|e| anyhow!("invalid query `{s}`: {e}"))?;
+        if let Some(token) = parser.tokens.next() {
+            bail!("invalid query `{s}`: unexpected {token:?}");
+        }
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_manifest;
+
+    const MANIFEST: &str = r#"[
+        {"model": "documents.tag", "pk": 1, "fields": {"name": "invoice"}},
+        {"model": "documents.tag", "pk": 2, "fields": {"name": "finance/taxes"}},
+        {"model": "documents.correspondent", "pk": 1, "fields": {"name": "ACME Corp"}},
+        {"model": "documents.document", "pk": 1, "fields": {
+            "title": "Invoice 42", "created": "2023-05-01T10:00:00Z", "correspondent": 1,
+            "tags": [1, 2], "checksum": "abc"},
+         "__exported_file_name__": "0000001.pdf"}
+    ]"#;
+
+    fn matches(query: &str) -> bool {
+        let manifest = parse_manifest(MANIFEST.as_bytes()).unwrap();
+        let query: Query = query.parse().unwrap();
+        query.matches(&manifest.documents[0], &TimeZone::UTC)
+    }
+
+    #[test]
+    fn terms_compare_case_insensitively() {
+        assert!(matches("tag:INVOICE"));
+        assert!(matches("Correspondent:'acme corp'"));
+        assert!(matches("title:\"voice 4\""));
+        assert!(matches("tag:finance/taxes"));
+        assert!(!matches("tag:private"));
+        assert!(!matches("type:Invoice"));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_than_or() {
+        assert!(matches("tag:private AND tag:none OR tag:invoice"));
+        assert!(!matches("tag:private AND (tag:none OR tag:invoice)"));
+        assert!(matches("NOT tag:private AND tag:invoice"));
+        assert!(!matches("NOT (tag:private OR tag:invoice)"));
+        assert!(matches("not not tag:invoice or tag:private"));
+    }
+
+    #[test]
+    fn created_compares_days() {
+        assert!(matches("created = 2023-05-01"));
+        assert!(matches("created>=2023-05-01 AND created<2023-05-02"));
+        assert!(matches("created != 2023-04-30"));
+        assert!(!matches("created > 2023-05-01"));
+        assert!(!matches("created <= 2023-04-30"));
+    }
+
+    #[test]
+    fn invalid_queries_are_rejected() {
+        for query in [
+            "",
+            "tag:",
+            "tag:invoice AND",
+            "(tag:invoice",
+            "tag:invoice)",
+            "tag invoice",
+            "color:red",
+            "title > 2023-01-01",
+            "created > 2023-13-01",
+            "created ! 2023-01-01",
+            "tag:'invoice",
+        ] {
+            assert!(query.parse::<Query>().is_err(), "{query}");
+        }
+        let error = "tag:a OR size:3".parse::<Query>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid query `tag:a OR size:3`: unknown field `size`"
+        );
+    }
+}
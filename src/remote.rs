@@ -0,0 +1,372 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail};
+use rayon::prelude::*;
+use tracing::{debug, info};
+use walkdir::WalkDir;
+
+use crate::{
+    backend::{Entry, EntryKind, OutputBackend},
+    html,
+    organize::{OrganizeOptions, View},
+    s3::{self, S3Client},
+    sums::SUMS_FILE,
+    webdav::WebDavClient,
+};
+
+/// Storage the organized output is mirrored to after a run, e.g. as an offsite backup.
+pub enum Remote {
+    /// `s3://bucket/prefix`, receiving `files/` and the index
+    S3 { client: S3Client, prefix: String },
+    /// `davs://host/path` (or `dav://` without TLS), receiving the views and the index, e.g.
+    /// a Nextcloud folder at `davs://cloud.example.org/remote.php/dav/files/alice/paperless`
+    WebDav(WebDavClient),
+}
+
+/// What [`Remote::mirror`] did.
+#[derive(Default)]
+pub struct UploadSummary {
+    pub uploaded: u64,
+    pub unchanged: u64,
+    /// Objects below the prefix without a local file
+    pub deleted: u64,
+    pub bytes: u64,
+}
+
+impl Remote {
+    /// The remote at `url`, e.g. `s3://backups/paperless` or `davs://host/path`. S3 buckets
+    /// outside of AWS are reached at `s3_endpoint`.
+    pub fn new(url: &str, s3_endpoint: Option<&str>) -> anyhow::Result<Self> {
+        let Some((scheme, location)) = url.split_once("://") else {
+            bail!("invalid remote `{url}`, expected e.g. s3://bucket/prefix");
+        };
+        match scheme {
+            "s3" => {
+                let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+                if bucket.is_empty() {
+                    bail!("the remote `{url}` names no bucket");
+                }
+                Ok(Remote::S3 {
+                    client: S3Client::new(bucket, s3_endpoint)?,
+                    prefix: prefix.trim_matches('/').to_owned(),
+                })
+            }
+            "dav" | "webdav" => Ok(Remote::WebDav(WebDavClient::new(&format!(
+                "http://{location}"
+            ))?)),
+            "davs" | "webdavs" => Ok(Remote::WebDav(WebDavClient::new(&format!(
+                "https://{location}"
+            ))?)),
+            _ => Err(anyhow!(
+                "unsupported remote `{url}`, expected s3://, dav:// or davs://"
+            )),
+        }
+    }
+
+    /// Uploads the output of `options`, skipping what the remote already has and deleting
+    /// what it has beyond that, so it ends up a copy of the local tree.
+    pub fn mirror(&self, options: &OrganizeOptions) -> anyhow::Result<UploadSummary> {
+        match self {
+            Remote::S3 { client, prefix } => {
+                let roots = [View::Files.dir_name().to_owned()];
+                let files = mirrored_files(&options.output_dir, &roots, options);
+                self.mirror_s3(client, prefix, files)
+            }
+            Remote::WebDav(client) => {
+                let files = mirrored_files(&options.output_dir, &options.dirs(), options);
+                self.mirror_webdav(client, files)
+            }
+        }
+    }
+
+    /// The key of the `/` separated `relative` path on S3.
+    fn key(&self, relative: &str) -> String {
+        match self {
+            Remote::S3 { prefix, .. } if !prefix.is_empty() => format!("{prefix}/{relative}"),
+            _ => relative.to_owned(),
+        }
+    }
+
+    /// Unchanged files are recognized by size and ETag, which only works for buckets that
+    /// don't encrypt with customer managed keys; otherwise everything is uploaded again.
+    /// Everything below the prefix belongs to the mirror.
+    fn mirror_s3(
+        &self,
+        client: &S3Client,
+        prefix: &str,
+        files: Vec<(String, PathBuf)>,
+    ) -> anyhow::Result<UploadSummary> {
+        let local: HashMap<String, PathBuf> = files
+            .into_iter()
+            .map(|(relative, path)| (self.key(&relative), path))
+            .collect();
+        let listed = match prefix {
+            "" => String::new(),
+            prefix => format!("{prefix}/"),
+        };
+        let remote: HashMap<_, _> = client
+            .list(&listed)?
+            .into_iter()
+            .map(|object| (object.key.clone(), object))
+            .collect();
+
+        let results = local
+            .par_iter()
+            .map(|(key, path)| {
+                if let Some(object) = remote.get(key) {
+                    if object.size == path.metadata()?.len() && object.etag == s3::etag(path)? {
+                        return Ok(None);
+                    }
+                }
+                debug!("uploading {} to {key}", path.display());
+                client.upload(key, path).map(Some)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut summary = UploadSummary::new(results);
+        let stale: Vec<_> = remote.keys().filter(|k| !local.contains_key(*k)).collect();
+        stale.par_iter().try_for_each(|key| {
+            info!("deleting {key} from {self}");
+            client.delete(key)
+        })?;
+        summary.deleted = stale.len() as u64;
+        Ok(summary)
+    }
+
+    /// Unchanged files are recognized by their size and a modification time no older than the
+    /// local one; Nextcloud keeps the local modification times of uploads. Missing folders
+    /// are created first, and everything in the remote folder that isn't in the output
+    /// directory is deleted at the end.
+    fn mirror_webdav(
+        &self,
+        client: &WebDavClient,
+        files: Vec<(String, PathBuf)>,
+    ) -> anyhow::Result<UploadSummary> {
+        let remote: HashMap<_, _> = client
+            .list()?
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        let local: HashMap<String, PathBuf> = files.into_iter().collect();
+        let dirs: BTreeSet<&str> = local
+            .keys()
+            .flat_map(|path| path.match_indices('/').map(|(i, _)| &path[..i]))
+            .collect();
+        // sorted, so parents come first
+        for dir in &dirs {
+            if !remote.get(*dir).is_some_and(|entry| entry.is_dir) {
+                client.create_dir(dir)?;
+            }
+        }
+
+        let results = local
+            .par_iter()
+            .map(|(path, file)| {
+                let metadata = file.metadata()?;
+                let modified = metadata.modified().ok();
+                // servers only keep whole seconds
+                let seconds = |time: std::time::SystemTime| {
+                    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+                };
+                if let Some(entry) = remote.get(path) {
+                    let fresh = match (entry.modified, modified) {
+                        (Some(remote), Some(local)) => seconds(remote) >= seconds(local),
+                        _ => true,
+                    };
+                    if !entry.is_dir && entry.size == metadata.len() && fresh {
+                        return Ok(None);
+                    }
+                }
+                debug!("uploading {} to {path}", file.display());
+                client.upload(path, file).map(Some)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut summary = UploadSummary::new(results);
+
+        // entries in deleted folders go with them
+        let mut stale: Vec<_> = remote
+            .values()
+            .filter(|entry| match entry.is_dir {
+                true => !dirs.contains(entry.path.as_str()),
+                false => !local.contains_key(&entry.path),
+            })
+            .map(|entry| entry.path.as_str())
+            .collect();
+        stale.sort();
+        let mut deleted: Vec<&str> = Vec::new();
+        for path in stale {
+            if deleted
+                .last()
+                .is_some_and(|dir| path.starts_with(&format!("{dir}/")))
+            {
+                continue;
+            }
+            info!("deleting {path} from {self}");
+            client.delete(path)?;
+            deleted.push(path);
+        }
+        summary.deleted = deleted.len() as u64;
+        Ok(summary)
+    }
+}
+
+/// The views written straight to the remote, with copies for links as neither has symlinks.
+impl OutputBackend for Remote {
+    fn list(&self) -> io::Result<Vec<Entry>> {
+        match self {
+            Remote::S3 { client, prefix } => {
+                let listed = match prefix.as_str() {
+                    "" => String::new(),
+                    prefix => format!("{prefix}/"),
+                };
+                let objects = client.list(&listed).map_err(io::Error::other)?;
+                Ok(objects
+                    .into_iter()
+                    .map(|object| Entry {
+                        path: PathBuf::from(&object.key[listed.len()..]),
+                        kind: EntryKind::File,
+                        size: object.size,
+                        modified: object.modified,
+                    })
+                    .collect())
+            }
+            Remote::WebDav(client) => Ok(client
+                .list()
+                .map_err(io::Error::other)?
+                .into_iter()
+                .map(|entry| Entry {
+                    path: PathBuf::from(entry.path),
+                    kind: match entry.is_dir {
+                        true => EntryKind::Dir,
+                        false => EntryKind::File,
+                    },
+                    size: entry.size,
+                    modified: entry.modified,
+                })
+                .collect()),
+        }
+    }
+
+    fn write(
+        &self,
+        path: &Path,
+        contents: &mut dyn Read,
+        modified: Option<SystemTime>,
+    ) -> io::Result<u64> {
+        let path = remote_path(path)?;
+        match self {
+            Remote::S3 { client, .. } => client.put(&self.key(&path), contents),
+            Remote::WebDav(client) => client.put(&path, contents, modified),
+        }
+        .map_err(io::Error::other)
+    }
+
+    fn symlinks(&self) -> bool {
+        false
+    }
+
+    fn symlink(&self, _target: &Path, _link: &Path) -> io::Result<()> {
+        Err(no_symlinks(self))
+    }
+
+    fn read_link(&self, _link: &Path) -> io::Result<PathBuf> {
+        Err(no_symlinks(self))
+    }
+
+    /// S3 has no folders, so removing one removes nothing; its objects are removed one by one.
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let path = remote_path(path)?;
+        match self {
+            Remote::S3 { client, .. } => client.delete(&self.key(&path)),
+            Remote::WebDav(client) => client.delete(&path),
+        }
+        .map_err(io::Error::other)
+    }
+}
+
+impl UploadSummary {
+    /// A summary of uploads that each uploaded the given number of bytes, or were skipped.
+    fn new(results: Vec<Option<u64>>) -> Self {
+        let mut summary = UploadSummary::default();
+        for bytes in results {
+            match bytes {
+                Some(bytes) => {
+                    summary.uploaded += 1;
+                    summary.bytes += bytes;
+                }
+                None => summary.unchanged += 1,
+            }
+        }
+        summary
+    }
+}
+
+impl fmt::Display for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Remote::S3 { client, prefix } => write!(f, "s3://{}/{prefix}", client.bucket()),
+            Remote::WebDav(client) => f.write_str(client.url()),
+        }
+    }
+}
+
+fn no_symlinks(remote: &Remote) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{remote} has no symlinks"),
+    )
+}
+
+/// `path` with `/` separators, as remotes expect.
+fn remote_path(path: &Path) -> io::Result<String> {
+    let components: Option<Vec<_>> = path.components().map(|c| c.as_os_str().to_str()).collect();
+    components.map(|c| c.join("/")).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not valid UTF-8", path.display()),
+        )
+    })
+}
+
+/// The files [`Remote::mirror`] uploads, by their `/` separated path relative to
+/// `output_dir`: everything in the `dirs`, the index files of `options` and their pages, the
+/// link graphs and the checksums. Links are followed, so the remote gets the files they refer
+/// to.
+fn mirrored_files(
+    output_dir: &Path,
+    dirs: &[String],
+    options: &OrganizeOptions,
+) -> Vec<(String, PathBuf)> {
+    let mut roots: HashSet<&str> = options
+        .index
+        .iter()
+        .map(|format| format.file_name())
+        .chain(options.link_graph.iter().map(|format| format.file_name()))
+        .collect();
+    roots.extend(dirs.iter().map(String::as_str));
+    roots.extend([html::PAGES_DIR, SUMS_FILE]);
+    roots
+        .into_iter()
+        .flat_map(|root| {
+            WalkDir::new(output_dir.join(root))
+                .follow_links(true)
+                .into_iter()
+                .filter_entry(|e| !e.file_name().to_string_lossy().starts_with('.'))
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_file())
+        })
+        .filter_map(|entry| {
+            let relative = entry.path().strip_prefix(output_dir).ok()?;
+            let components: Option<Vec<_>> = relative
+                .components()
+                .map(|c| c.as_os_str().to_str())
+                .collect();
+            Some((components?.join("/"), entry.into_path()))
+        })
+        .collect()
+}
@@ -0,0 +1,66 @@
+use std::{fs, io, path::Path};
+
+use serde::Serialize;
+
+use crate::Document;
+
+/// Report in the output directory listing the excluded documents.
+pub const SKIPPED_REPORT: &str = "skipped.json";
+/// Report in the output directory listing the documents that failed to be organized.
+pub const ERRORS_REPORT: &str = "errors.json";
+
+#[derive(Serialize)]
+pub struct SkippedDocument {
+    pub pk: i64,
+    pub title: String,
+    pub file_name: String,
+    /// The document's tags that matched the exclusion rules
+    pub excluded_by: Vec<String>,
+    /// Whether it was copied to the restricted directory rather than left out entirely
+    pub restricted: bool,
+}
+
+#[derive(Serialize)]
+pub struct FailedDocument {
+    pub pk: i64,
+    pub title: String,
+    pub file_name: String,
+    pub error: String,
+}
+
+/// The documents a run didn't organize, for review after unattended runs.
+#[derive(Default)]
+pub struct Report {
+    pub skipped: Vec<SkippedDocument>,
+    pub failed: Vec<FailedDocument>,
+}
+
+impl Report {
+    pub fn skip(&mut self, doc: &Document, excluded_by: Vec<String>, restricted: bool) {
+        self.skipped.push(SkippedDocument {
+            pk: doc.pk,
+            title: doc.title.clone(),
+            file_name: doc.archive_name.clone(),
+            excluded_by,
+            restricted,
+        });
+    }
+
+    pub fn fail(&mut self, doc: &Document, error: String) {
+        self.failed.push(FailedDocument {
+            pk: doc.pk,
+            title: doc.title.clone(),
+            file_name: doc.archive_name.clone(),
+            error,
+        });
+    }
+
+    /// Writes [`SKIPPED_REPORT`] and [`ERRORS_REPORT`] to `output_dir`, even if empty so they
+    /// never describe an earlier run.
+    pub fn write(&self, output_dir: &Path) -> io::Result<()> {
+        let skipped = serde_json::to_string_pretty(&self.skipped)?;
+        fs::write(output_dir.join(SKIPPED_REPORT), skipped + "\n")?;
+        let failed = serde_json::to_string_pretty(&self.failed)?;
+        fs::write(output_dir.join(ERRORS_REPORT), failed + "\n")
+    }
+}
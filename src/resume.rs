@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// Journal in the output directory of the documents finished by a run that is in progress or
+/// was interrupted.
+pub const RUN_STATE_FILE: &str = ".paperless-organize-run.jsonl";
+
+/// The first line of the journal.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    /// Fingerprint of the options of the run
+    options: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Finished {
+    pk: i64,
+    outputs: Vec<PathBuf>,
+}
+
+/// Records which documents a run has finished, so an interrupted run can be resumed without
+/// wiping the views again or revisiting finished documents.
+///
+/// Every finished document is appended as a line of JSON after a header with the fingerprint
+/// of the run's options; the file is removed once the run completes, so its presence means the
+/// previous run was interrupted.
+pub struct RunState {
+    path: PathBuf,
+    resumed: bool,
+    finished: HashMap<i64, Vec<PathBuf>>,
+    journal: Mutex<File>,
+}
+
+impl RunState {
+    /// Picks up the journal an interrupted run left in `output_dir`, or starts a new one if
+    /// there is none or that run had other `options` than this one.
+    pub fn open(output_dir: &Path, options: &str) -> Result<Self, Error> {
+        let path = output_dir.join(RUN_STATE_FILE);
+        let write_error = |error| Error::Write {
+            path: path.clone(),
+            error,
+        };
+
+        let mut finished = HashMap::new();
+        let resumed = match File::open(&path) {
+            Ok(file) => {
+                let mut lines = BufReader::new(file).lines();
+                let read_error = |error| Error::Read {
+                    path: path.clone(),
+                    error,
+                };
+                let header = lines.next().transpose().map_err(read_error)?;
+                let same_options = header
+                    .and_then(|line| serde_json::from_str::<Header>(&line).ok())
+                    .is_some_and(|header| header.options == options);
+                if same_options {
+                    // a line cut short by a crash just means that document is done again
+                    for line in lines {
+                        let line = line.map_err(read_error)?;
+                        if let Ok(entry) = serde_json::from_str::<Finished>(&line) {
+                            finished.insert(entry.pk, entry.outputs);
+                        }
+                    }
+                }
+                same_options
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => false,
+            Err(error) => return Err(Error::Read { path, error }),
+        };
+
+        fs::create_dir_all(output_dir).map_err(write_error)?;
+        let mut journal = match resumed {
+            true => OpenOptions::new().append(true).open(&path),
+            false => File::create(&path),
+        }
+        .map_err(write_error)?;
+        if !resumed {
+            let mut header = serde_json::to_vec(&Header {
+                options: options.to_owned(),
+            })?;
+            header.push(b'\n');
+            journal.write_all(&header).map_err(write_error)?;
+        }
+        Ok(Self {
+            path,
+            resumed,
+            finished,
+            journal: Mutex::new(journal),
+        })
+    }
+
+    /// Whether this continues an interrupted run.
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// The outputs of document `pk` if the interrupted run already finished it.
+    pub fn finished(&self, pk: i64) -> Option<&[PathBuf]> {
+        self.finished.get(&pk).map(Vec::as_slice)
+    }
+
+    /// Marks document `pk` as finished with `outputs`.
+    pub fn record(&self, pk: i64, outputs: &[PathBuf]) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(&Finished {
+            pk,
+            outputs: outputs.to_vec(),
+        })?;
+        line.push(b'\n');
+        let mut journal = self.journal.lock().unwrap();
+        journal
+            .write_all(&line)
+            .and_then(|()| journal.flush())
+            .map_err(|error| Error::Write {
+                path: self.path.clone(),
+                error,
+            })
+    }
+
+    /// Removes the journal after the run completed.
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.journal);
+        fs::remove_file(&self.path)
+    }
+
+    /// Discards the journal of an interrupted run, e.g. when its outputs are removed.
+    pub fn discard(output_dir: &Path) -> io::Result<()> {
+        match fs::remove_file(output_dir.join(RUN_STATE_FILE)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    env,
+    fmt::Write as _,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use md5::Md5;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use tracing::trace;
+
+use crate::checksum::hex;
+
+/// Size of the parts of multipart uploads; larger files are uploaded in parts of this size,
+/// in parallel. Also determines the ETags of such uploads.
+pub const PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// An object stored in a bucket.
+pub struct Object {
+    pub key: String,
+    pub size: u64,
+    /// Without quotes. The MD5 of the contents for plain uploads, see [`etag`].
+    pub etag: String,
+    /// When it was uploaded
+    pub modified: Option<SystemTime>,
+}
+
+/// A minimal client for an S3 bucket, on AWS or any S3-compatible service (MinIO, Backblaze
+/// B2, Cloudflare R2, Garage, ...), signing its requests with AWS Signature Version 4.
+///
+/// Credentials and region come from the usual environment variables: `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY`, optionally `AWS_SESSION_TOKEN`, and `AWS_REGION` or
+/// `AWS_DEFAULT_REGION` (`us-east-1` if neither is set).
+pub struct S3Client {
+    agent: ureq::Agent,
+    bucket: String,
+    /// Scheme and host of the bucket, plus `/bucket` for path-style access
+    base_url: String,
+    /// The path part of `base_url`
+    base_path: String,
+    host: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Client {
+    /// A client for `bucket` on AWS, or at `endpoint` (e.g. `http://localhost:9000`), which
+    /// is addressed path-style as S3-compatible services expect.
+    pub fn new(bucket: &str, endpoint: Option<&str>) -> anyhow::Result<Self> {
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+        let region = var("AWS_REGION")
+            .or_else(|| var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".to_owned());
+        let (base_url, base_path) = match endpoint {
+            Some(endpoint) => {
+                let endpoint = endpoint.trim_end_matches('/');
+                (format!("{endpoint}/{bucket}"), format!("/{bucket}"))
+            }
+            None => (
+                format!("https://{bucket}.s3.{region}.amazonaws.com"),
+                String::new(),
+            ),
+        };
+        let host = base_url
+            .split_once("://")
+            .map_or(base_url.as_str(), |(_, rest)| rest)
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        Ok(S3Client {
+            agent: ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .build()
+                .into(),
+            bucket: bucket.to_owned(),
+            base_url,
+            base_path,
+            host,
+            region,
+            access_key: var("AWS_ACCESS_KEY_ID").context("AWS_ACCESS_KEY_ID is not set")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY").context("AWS_SECRET_ACCESS_KEY is not set")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// All objects whose key starts with `prefix`.
+    pub fn list(&self, prefix: &str) -> anyhow::Result<Vec<Object>> {
+        let mut objects = Vec::new();
+        let mut token = None;
+        loop {
+            let mut query = vec![("list-type", "2".to_owned()), ("prefix", prefix.to_owned())];
+            if let Some(token) = token.take() {
+                query.push(("continuation-token", token));
+            }
+            let body = self.request("GET", "", &query, Vec::new())?.1;
+            for contents in elements(&body, "Contents") {
+                let value = |tag| element(contents, tag).map(unescape).unwrap_or_default();
+                objects.push(Object {
+                    key: value("Key"),
+                    size: value("Size").parse().unwrap_or_default(),
+                    etag: value("ETag").trim_matches('"').to_lowercase(),
+                    modified: DateTime::parse_from_rfc3339(&value("LastModified"))
+                        .ok()
+                        .map(SystemTime::from),
+                });
+            }
+            match element(&body, "NextContinuationToken") {
+                Some(next) if element(&body, "IsTruncated") == Some("true") => {
+                    token = Some(unescape(next))
+                }
+                _ => return Ok(objects),
+            }
+        }
+    }
+
+    /// Uploads the file at `path` as `key`, in parallel parts of [`PART_SIZE`] if it's larger.
+    /// Returns the number of bytes uploaded.
+    pub fn upload(&self, key: &str, path: &Path) -> anyhow::Result<u64> {
+        let len = path.metadata()?.len();
+        if len <= PART_SIZE {
+            let contents = std::fs::read(path)?;
+            self.request("PUT", key, &[], contents)?;
+            return Ok(len);
+        }
+
+        self.multipart(key, |upload_id| {
+            (1..=len.div_ceil(PART_SIZE))
+                .into_par_iter()
+                .map(|number| {
+                    let part = read_part(path, number - 1)?;
+                    self.upload_part(key, upload_id, number, part)
+                })
+                .collect()
+        })?;
+        Ok(len)
+    }
+
+    /// Uploads what `contents` yields as `key`, in parts of [`PART_SIZE`] one after the other
+    /// if there's more than that. Returns the number of bytes uploaded.
+    pub fn put(&self, key: &str, contents: &mut dyn Read) -> anyhow::Result<u64> {
+        let mut part = Vec::new();
+        (&mut *contents).take(PART_SIZE).read_to_end(&mut part)?;
+        let mut next = Vec::new();
+        (&mut *contents).take(PART_SIZE).read_to_end(&mut next)?;
+        if next.is_empty() {
+            let len = part.len() as u64;
+            self.request("PUT", key, &[], part)?;
+            return Ok(len);
+        }
+
+        let mut len = 0;
+        self.multipart(key, |upload_id| {
+            let mut etags = Vec::new();
+            for number in 1.. {
+                if part.is_empty() {
+                    break;
+                }
+                len += part.len() as u64;
+                etags.push(self.upload_part(key, upload_id, number, part)?);
+                part = std::mem::take(&mut next);
+                (&mut *contents).take(PART_SIZE).read_to_end(&mut next)?;
+            }
+            Ok(etags)
+        })?;
+        Ok(len)
+    }
+
+    /// Runs a multipart upload of `key`, whose parts `upload_parts` uploads with the given
+    /// upload id, returning their ETags in order. The upload is aborted if anything fails.
+    fn multipart(
+        &self,
+        key: &str,
+        upload_parts: impl FnOnce(&str) -> anyhow::Result<Vec<String>>,
+    ) -> anyhow::Result<()> {
+        let body = self
+            .request("POST", key, &[("uploads", String::new())], Vec::new())?
+            .1;
+        let upload_id = element(&body, "UploadId")
+            .map(unescape)
+            .ok_or_else(|| anyhow!("no upload id in the response to creating {key}"))?;
+        let completed = upload_parts(&upload_id).and_then(|etags| {
+            let mut body = String::from("<CompleteMultipartUpload>");
+            for (number, etag) in (1..).zip(etags) {
+                let _ = write!(
+                    body,
+                    "<Part><PartNumber>{number}</PartNumber><ETag>{etag}</ETag></Part>"
+                );
+            }
+            body.push_str("</CompleteMultipartUpload>");
+            let query = [("uploadId", upload_id.clone())];
+            let response = self.request("POST", key, &query, body.into_bytes())?.1;
+            // failures can surface after a 200 OK
+            match element(&response, "Code") {
+                Some(code) if response.contains("<Error>") => {
+                    bail!("failed to complete the upload of {key}: {code}")
+                }
+                _ => Ok(()),
+            }
+        });
+        if completed.is_err() {
+            let _ = self.request("DELETE", key, &[("uploadId", upload_id)], Vec::new());
+        }
+        completed
+    }
+
+    /// Uploads part `number` of a multipart upload and returns its ETag.
+    fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        number: u64,
+        part: Vec<u8>,
+    ) -> anyhow::Result<String> {
+        let query = [
+            ("partNumber", number.to_string()),
+            ("uploadId", upload_id.to_owned()),
+        ];
+        let (etag, _) = self.request("PUT", key, &query, part)?;
+        etag.ok_or_else(|| anyhow!("no ETag for part {number} of {key}"))
+    }
+
+    pub fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.request("DELETE", key, &[], Vec::new()).map(|_| ())
+    }
+
+    /// Sends a signed request for `key` and returns the ETag and body of the response.
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> anyhow::Result<(Option<String>, String)> {
+        let path = match key {
+            "" => format!("{}/", self.base_path),
+            key => format!("{}/{}", self.base_path, encode(key, false)),
+        };
+        let mut query: Vec<_> = query
+            .iter()
+            .map(|(k, v)| (encode(k, true), encode(v, true)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let now = Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex(&Sha256::digest(&body));
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut signing_key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date.as_str(), &self.region, "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key
+        );
+
+        let url = match query.as_str() {
+            "" => format!("{}{}", self.base_url, &path[self.base_path.len()..]),
+            query => format!("{}{}?{query}", self.base_url, &path[self.base_path.len()..]),
+        };
+        trace!("{method} {url}");
+        let mut request = ureq::http::Request::builder()
+            .method(method)
+            .uri(&url)
+            .header("Authorization", authorization);
+        for (name, value) in headers.into_iter().skip(1) {
+            request = request.header(name, value);
+        }
+        let mut response = self
+            .agent
+            .run(request.body(body)?)
+            .with_context(|| format!("request to {url} failed"))?;
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_owned);
+        let body = response.body_mut().read_to_string().unwrap_or_default();
+        let status = response.status();
+        if !status.is_success() {
+            let reason = element(&body, "Message")
+                .or_else(|| element(&body, "Code"))
+                .map_or_else(|| status.to_string(), unescape);
+            bail!("{method} {url} failed: {reason}");
+        }
+        Ok((etag, body))
+    }
+}
+
+/// The ETag S3 assigns to the contents of `path` when uploaded by [`S3Client::upload`]: the
+/// MD5 of the contents, or for multipart uploads the MD5 of the parts' MD5s with the number of
+/// parts appended.
+pub fn etag(path: &Path) -> anyhow::Result<String> {
+    let len = path.metadata()?.len();
+    if len <= PART_SIZE {
+        return Ok(hex(&Md5::digest(std::fs::read(path)?)));
+    }
+    let parts = len.div_ceil(PART_SIZE);
+    let mut digests = Md5::new();
+    for number in 0..parts {
+        digests.update(Md5::digest(read_part(path, number)?));
+    }
+    Ok(format!("{}-{parts}", hex(&digests.finalize())))
+}
+
+/// The zero-based part `number` of the file at `path`.
+fn read_part(path: &Path, number: u64) -> anyhow::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(number * PART_SIZE))?;
+    let mut part = Vec::new();
+    file.take(PART_SIZE).read_to_end(&mut part)?;
+    Ok(part)
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(key).expect("HMAC takes any key");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// URI-encodes `text` as Signature Version 4 expects, keeping `/` unless `slash` is set.
+fn encode(text: &str, slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !slash => encoded.push('/'),
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+/// The contents of the first `<tag>` element in `xml`.
+fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    elements(xml, tag).next()
+}
+
+/// The contents of all `<tag>` elements in `xml`; enough for S3's flat responses.
+fn elements<'a>(xml: &'a str, tag: &str) -> impl Iterator<Item = &'a str> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let end = start + rest[start..].find(&close)?;
+        let contents = &rest[start..end];
+        rest = &rest[end + close.len()..];
+        Some(contents)
+    })
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&#34;", "\"")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,161 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::bail;
+use unicode_normalization::UnicodeNormalization;
+
+/// Longest extension kept intact when shortening names, without the dot.
+const MAX_EXTENSION_LENGTH: usize = 8;
+
+/// Cuts the file or folder name `name` down to `length` characters, ending the shortened stem
+/// with `marker`. Up to two extensions of up to [`MAX_EXTENSION_LENGTH`] characters without
+/// spaces are kept, so `Invoice.pdf.lnk` stays a shortcut to a PDF.
+pub(crate) fn shorten(name: &str, length: usize, marker: &str) -> String {
+    if name.chars().count() <= length {
+        return name.to_owned();
+    }
+    let mut stem = name;
+    for _ in 0..2 {
+        match stem.rsplit_once('.') {
+            Some((rest, extension))
+                if !rest.is_empty()
+                    && extension.chars().count() <= MAX_EXTENSION_LENGTH
+                    && !extension.contains(' ') =>
+            {
+                stem = rest
+            }
+            _ => break,
+        }
+    }
+    let extension = &name[stem.len()..];
+    let keep = length
+        .saturating_sub(extension.chars().count() + marker.chars().count())
+        .max(1);
+    let stem: String = stem.chars().take(keep).collect();
+    format!("{}{marker}{extension}", stem.trim_end_matches(['.', ' ']))
+}
+
+/// Device names Windows reserves in every directory, regardless of extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Unicode normalization form applied to generated names.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    /// Composed characters (`é` as one code point), as used by Windows and Linux
+    Nfc,
+    /// Decomposed characters (`e` followed by a combining accent), as stored by HFS+
+    Nfd,
+}
+
+/// Turns tag, correspondent and other user supplied names into path components that are valid
+/// on Windows, macOS and Linux alike.
+#[derive(Clone, Copy, Debug)]
+pub struct Sanitizer {
+    replacement: char,
+    max_length: Option<usize>,
+    normalization: Option<Normalization>,
+    ascii: bool,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self {
+            replacement: '_',
+            max_length: None,
+            normalization: None,
+            ascii: false,
+        }
+    }
+}
+
+impl Sanitizer {
+    /// A sanitizer substituting `replacement` for illegal characters.
+    pub fn new(replacement: char) -> anyhow::Result<Self> {
+        if is_illegal(replacement) || replacement == '.' || replacement == ' ' {
+            bail!("`{replacement}` can't be used as replacement character");
+        }
+        Ok(Self {
+            replacement,
+            ..Self::default()
+        })
+    }
+
+    /// Also brings names into the Unicode `normalization` form.
+    pub fn with_normalization(self, normalization: Option<Normalization>) -> Self {
+        Self {
+            normalization,
+            ..self
+        }
+    }
+
+    /// Also transliterates names to ASCII if `ascii` is set, e.g. `Überweisung` to
+    /// `Uberweisung`.
+    pub fn with_ascii(self, ascii: bool) -> Self {
+        Self { ascii, ..self }
+    }
+
+    /// Also shortens components to at most `max_length` characters.
+    pub fn with_max_length(self, max_length: Option<usize>) -> anyhow::Result<Self> {
+        if let Some(max_length) = max_length.filter(|&l| l < 2 * MAX_EXTENSION_LENGTH + 3) {
+            bail!(
+                "names can't be shortened to {max_length} characters, at least {} are needed",
+                2 * MAX_EXTENSION_LENGTH + 3
+            );
+        }
+        Ok(Self { max_length, ..self })
+    }
+
+    /// Replaces characters that are illegal in file names, including path separators, after
+    /// transliterating and normalizing `name` as configured.
+    pub fn chars(&self, name: &str) -> String {
+        let name = if self.ascii {
+            deunicode::deunicode_with_tofu(name, &self.replacement.to_string())
+        } else {
+            name.to_owned()
+        };
+        let name: String = match self.normalization {
+            Some(Normalization::Nfc) => name.nfc().collect(),
+            Some(Normalization::Nfd) => name.nfd().collect(),
+            None => name,
+        };
+        name.chars()
+            .map(|c| if is_illegal(c) { self.replacement } else { c })
+            .collect()
+    }
+
+    /// Makes `name` a single valid path component: illegal characters are replaced, the name
+    /// shortened to the maximum length, trailing dots and spaces (which Windows drops) removed
+    /// and reserved device names like `CON` suffixed with the replacement character.
+    pub fn component(&self, name: &str) -> String {
+        let name = match self.max_length {
+            Some(max_length) => shorten(&self.chars(name), max_length, ""),
+            None => self.chars(name),
+        };
+        let mut name = name.trim_end_matches(['.', ' ']).to_owned();
+        if name.is_empty() {
+            name.push(self.replacement);
+        }
+        let stem = name.split('.').next().unwrap_or_default();
+        if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            name.insert(stem.len(), self.replacement);
+        }
+        name
+    }
+
+    /// Sanitizes every component of the relative path `path`; `.` and `..` are dropped.
+    pub fn path(&self, path: &Path) -> PathBuf {
+        path.components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(self.component(&name.to_string_lossy())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn is_illegal(c: char) -> bool {
+    matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') || c.is_control()
+}
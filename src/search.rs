@@ -0,0 +1,60 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use rusqlite::{Connection, OpenFlags};
+
+use crate::index::IndexFormat;
+
+/// A document matching a full-text query.
+pub struct SearchHit {
+    pub pk: i64,
+    pub title: String,
+    /// Matching excerpt of the document's text, matches enclosed in `[` `]`
+    pub snippet: String,
+    /// Output paths relative to the output directory, as `(view, path)`
+    pub paths: Vec<(String, String)>,
+}
+
+/// Runs the FTS5 `query` against the `index.db` in `output_dir`, best matches first.
+///
+/// The query supports SQLite's full-text syntax: words, `"phrases"`, `prefix*`, `AND`, `OR`,
+/// `NOT` and column filters like `title: invoice`.
+pub fn search(output_dir: &Path, query: &str, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
+    let path = output_dir.join(IndexFormat::Sqlite.file_name());
+    if !path.is_file() {
+        bail!(
+            "no search index at {}, run `organize --index sqlite` first",
+            path.display()
+        );
+    }
+    let db = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+
+    let mut matches = db.prepare(
+        "SELECT rowid, title, snippet(documents_fts, 1, '[', ']', '...', 12)
+         FROM documents_fts WHERE documents_fts MATCH ?1 ORDER BY rank LIMIT ?2",
+    )?;
+    let mut outputs =
+        db.prepare("SELECT view, path FROM outputs WHERE document = ?1 ORDER BY view, path")?;
+
+    let hits = matches
+        .query_map((query, limit as i64), |row| {
+            Ok(SearchHit {
+                pk: row.get(0)?,
+                title: row.get(1)?,
+                snippet: row.get(2)?,
+                paths: Vec::new(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid search query `{query}`"))?;
+
+    hits.into_iter()
+        .map(|mut hit| {
+            hit.paths = outputs
+                .query_map([hit.pk], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?;
+            Ok(hit)
+        })
+        .collect()
+}
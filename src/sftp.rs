@@ -0,0 +1,300 @@
+use std::{
+    collections::HashSet,
+    env, fmt,
+    io::{self, BufWriter, Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context};
+use ssh2::{CheckResult, FileStat, KnownHostFileKind, Session, Sftp};
+use tracing::{debug, trace};
+
+use crate::backend::{Entry, EntryKind, OutputBackend};
+
+/// Private keys tried in `~/.ssh` if the SSH agent has none that is accepted, as `ssh` does.
+const IDENTITY_FILES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// A folder on an SSH server, written to over SFTP, e.g. on a NAS.
+///
+/// The server has to be in `~/.ssh/known_hosts`. Authentication tries the SSH agent, then the
+/// default keys in `~/.ssh` (with the passphrase in `SFTP_KEY_PASSPHRASE`, if any), then the
+/// password from the URL or `SFTP_PASSWORD`.
+pub struct SftpClient {
+    /// Keeps the connection alive, and with it `sftp`
+    _session: Session,
+    sftp: Sftp,
+    /// `sftp://user@host:port`
+    origin: String,
+    root: PathBuf,
+    /// Folders known to exist, so writes don't have to create their parents every time
+    dirs: Mutex<HashSet<PathBuf>>,
+}
+
+impl SftpClient {
+    /// Connects to the folder at `url`, `sftp://[user[:password]@]host[:port]/path`, with an
+    /// absolute path on the server.
+    pub fn connect(url: &str) -> anyhow::Result<Self> {
+        let rest = url
+            .strip_prefix("sftp://")
+            .ok_or_else(|| anyhow!("invalid SFTP URL `{url}`"))?;
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (userinfo, address) = match authority.rsplit_once('@') {
+            Some((userinfo, address)) => (Some(userinfo), address),
+            None => (None, authority),
+        };
+        let (user, password) = match userinfo.map(|u| u.split_once(':').unwrap_or((u, ""))) {
+            Some((user, password)) => (user.to_owned(), Some(password).filter(|p| !p.is_empty())),
+            None => (env::var("USER").unwrap_or_else(|_| "root".to_owned()), None),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("invalid port in `{url}`"))?,
+            ),
+            None => (address, 22),
+        };
+        if host.is_empty() {
+            bail!("the SFTP URL `{url}` names no host");
+        }
+
+        let tcp = TcpStream::connect((host, port))
+            .with_context(|| format!("failed to connect to {host}:{port}"))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.set_timeout(60_000);
+        session
+            .handshake()
+            .with_context(|| format!("SSH handshake with {host}:{port} failed"))?;
+        check_host_key(&session, host, port)?;
+        authenticate(&session, &user, password)?;
+        if !session.authenticated() {
+            bail!("{host}:{port} refused all keys and passwords for {user}");
+        }
+
+        let origin = match port {
+            22 => format!("sftp://{user}@{host}"),
+            port => format!("sftp://{user}@{host}:{port}"),
+        };
+        Ok(SftpClient {
+            sftp: session.sftp()?,
+            _session: session,
+            origin,
+            root: PathBuf::from(format!("/{}", path.trim_end_matches('/'))),
+            dirs: Mutex::default(),
+        })
+    }
+
+    /// Creates the folder `dir` and its parents, unless they are known to exist.
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        if dir.as_os_str().is_empty()
+            || self
+                .dirs
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .contains(dir)
+        {
+            return Ok(());
+        }
+        if let Some(parent) = dir.parent() {
+            self.create_dir_all(parent)?;
+        }
+        let path = self.root.join(dir);
+        if let Err(error) = self.sftp.mkdir(&path, 0o755) {
+            if !self.sftp.stat(&path).is_ok_and(|stat| stat.is_dir()) {
+                return Err(error.into());
+            }
+        }
+        self.dirs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(dir.to_owned());
+        Ok(())
+    }
+
+    fn create_parent_dir(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) => self.create_dir_all(parent),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes what's at `path` if anything, so it can be replaced.
+    fn clear(&self, path: &Path) -> io::Result<()> {
+        match self.sftp.lstat(&self.root.join(path)) {
+            Ok(_) => self.remove(path),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+impl OutputBackend for SftpClient {
+    fn list(&self) -> io::Result<Vec<Entry>> {
+        if self.sftp.stat(&self.root).is_err() {
+            let mut missing: Vec<_> = self.root.ancestors().collect();
+            missing.reverse();
+            for dir in missing {
+                let _ = self.sftp.mkdir(dir, 0o755);
+            }
+            self.sftp.stat(&self.root)?;
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        let mut folders = vec![PathBuf::new()];
+        while let Some(folder) = folders.pop() {
+            trace!("listing {}", self.root.join(&folder).display());
+            for (path, stat) in self.sftp.readdir(self.root.join(&folder))? {
+                let Some(name) = path.file_name() else {
+                    continue;
+                };
+                let path = folder.join(name);
+                let kind = match stat.file_type() {
+                    kind if kind.is_dir() => EntryKind::Dir,
+                    kind if kind.is_symlink() => EntryKind::Symlink,
+                    _ => EntryKind::File,
+                };
+                if kind == EntryKind::Dir {
+                    folders.push(path.clone());
+                    self.dirs
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(path.clone());
+                }
+                entries.push(Entry {
+                    path,
+                    kind,
+                    size: stat.size.unwrap_or_default(),
+                    modified: stat.mtime.map(|t| UNIX_EPOCH + Duration::from_secs(t)),
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    fn write(
+        &self,
+        path: &Path,
+        contents: &mut dyn Read,
+        modified: Option<SystemTime>,
+    ) -> io::Result<u64> {
+        self.create_parent_dir(path)?;
+        self.clear(path)?;
+        let full_path = self.root.join(path);
+        let mut file = BufWriter::with_capacity(1024 * 1024, self.sftp.create(&full_path)?);
+        let bytes = io::copy(contents, &mut file)?;
+        file.flush()?;
+        drop(file);
+        if let Some(mtime) = modified.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+            let stat = FileStat {
+                size: None,
+                uid: None,
+                gid: None,
+                perm: None,
+                atime: Some(mtime.as_secs()),
+                mtime: Some(mtime.as_secs()),
+            };
+            self.sftp.setstat(&full_path, stat)?;
+        }
+        Ok(bytes)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        self.create_parent_dir(link)?;
+        self.clear(link)?;
+        self.sftp
+            .symlink(target, &self.root.join(link))
+            .map_err(io::Error::from)
+    }
+
+    fn read_link(&self, link: &Path) -> io::Result<PathBuf> {
+        self.sftp
+            .readlink(&self.root.join(link))
+            .map_err(io::Error::from)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let full_path = self.root.join(path);
+        if !self.sftp.lstat(&full_path)?.is_dir() {
+            return self.sftp.unlink(&full_path).map_err(io::Error::from);
+        }
+        for (child, _) in self.sftp.readdir(&full_path)? {
+            if let Some(name) = child.file_name() {
+                self.remove(&path.join(name))?;
+            }
+        }
+        self.dirs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|dir| !dir.starts_with(path));
+        self.sftp.rmdir(&full_path).map_err(io::Error::from)
+    }
+}
+
+impl fmt::Display for SftpClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.origin, self.root.display())
+    }
+}
+
+/// Fails unless the key of the server matches the one recorded in `~/.ssh/known_hosts`.
+fn check_host_key(session: &Session, host: &str, port: u16) -> anyhow::Result<()> {
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| anyhow!("{host} sent no host key"))?;
+    let mut known_hosts = session.known_hosts()?;
+    if let Some(path) = ssh_dir().map(|dir| dir.join("known_hosts")) {
+        if path.exists() {
+            known_hosts.read_file(&path, KnownHostFileKind::OpenSSH)?;
+        }
+    }
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => bail!(
+            "{host} is not a known host, connect with `ssh` once or add its key with \
+             `ssh-keyscan -p {port} {host} >> ~/.ssh/known_hosts`"
+        ),
+        CheckResult::Mismatch => bail!(
+            "the host key of {host} doesn't match the one in ~/.ssh/known_hosts, refusing to \
+             connect"
+        ),
+        CheckResult::Failure => bail!("failed to check the host key of {host}"),
+    }
+}
+
+fn authenticate(session: &Session, user: &str, password: Option<&str>) -> anyhow::Result<()> {
+    if env::var_os("SSH_AUTH_SOCK").is_some() && session.userauth_agent(user).is_ok() {
+        debug!("authenticated as {user} by the SSH agent");
+        return Ok(());
+    }
+    let passphrase = env::var("SFTP_KEY_PASSPHRASE").ok();
+    for name in IDENTITY_FILES {
+        let Some(key) = ssh_dir().map(|dir| dir.join(name)).filter(|p| p.exists()) else {
+            continue;
+        };
+        if session
+            .userauth_pubkey_file(user, None, &key, passphrase.as_deref())
+            .is_ok()
+        {
+            debug!("authenticated as {user} with {}", key.display());
+            return Ok(());
+        }
+    }
+    let password = password
+        .map(str::to_owned)
+        .or_else(|| env::var("SFTP_PASSWORD").ok());
+    if let Some(password) = password {
+        session
+            .userauth_password(user, &password)
+            .with_context(|| format!("the password for {user} was refused"))?;
+    }
+    Ok(())
+}
+
+fn ssh_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".ssh"))
+}
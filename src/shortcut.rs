@@ -0,0 +1,150 @@
+use std::{
+    io,
+    path::{Component, Path, Prefix},
+};
+
+use crate::link::relative_path;
+
+/// Appended to the file name of a view entry to get its shortcut's name, e.g.
+/// `Invoice.pdf.lnk`; Explorer hides it and shows `Invoice.pdf`.
+pub const EXTENSION: &str = "lnk";
+
+const HEADER_SIZE: u32 = 0x4C;
+const LINK_CLSID: [u8; 16] = [
+    0x01, 0x14, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46,
+];
+const HAS_LINK_INFO: u32 = 0x02;
+const HAS_RELATIVE_PATH: u32 = 0x08;
+const IS_UNICODE: u32 = 0x80;
+const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+const SW_SHOWNORMAL: u32 = 1;
+/// Size of a LinkInfo header that includes the offsets of the Unicode paths
+const LINK_INFO_HEADER_SIZE: u32 = 0x24;
+const VOLUME_ID_AND_LOCAL_BASE_PATH: u32 = 0x01;
+const DRIVE_FIXED: u32 = 3;
+
+/// The contents of a shortcut at `link` to the file `target`, in the Shell Link Binary File
+/// Format ([MS-SHLLINK]); writing it by hand needs neither COM nor any privileges.
+///
+/// The shortcut records both the absolute path of `target` and its path relative to `link`;
+/// Windows falls back to the latter when the former doesn't resolve, e.g. after the output
+/// directory was moved.
+///
+/// [MS-SHLLINK]: https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-shllink/
+pub fn shortcut(target: &Path, link: &Path) -> io::Result<Vec<u8>> {
+    let absolute = windows_path(&std::path::absolute(target)?);
+    let relative = match windows_path(&relative_path(link, target)) {
+        relative if relative.starts_with("..") => relative,
+        relative => format!(".\\{relative}"),
+    };
+
+    let mut lnk = Vec::new();
+    put_u32(&mut lnk, HEADER_SIZE);
+    lnk.extend(LINK_CLSID);
+    put_u32(&mut lnk, HAS_LINK_INFO | HAS_RELATIVE_PATH | IS_UNICODE);
+    put_u32(&mut lnk, FILE_ATTRIBUTE_NORMAL);
+    lnk.extend([0; 24]); // creation, access and write time
+    put_u32(&mut lnk, 0); // file size
+    put_u32(&mut lnk, 0); // icon index
+    put_u32(&mut lnk, SW_SHOWNORMAL);
+    lnk.extend([0; 12]); // hot key and reserved fields
+
+    link_info(&mut lnk, &absolute);
+
+    let relative: Vec<u16> = relative.encode_utf16().collect();
+    put_u16(&mut lnk, relative.len() as u16);
+    for unit in relative {
+        put_u16(&mut lnk, unit);
+    }
+
+    put_u32(&mut lnk, 0); // terminal block, no extra data
+    Ok(lnk)
+}
+
+/// Appends a LinkInfo structure locating `path` on a local volume.
+fn link_info(lnk: &mut Vec<u8>, path: &str) {
+    const VOLUME_ID: [u8; 17] = [
+        17,
+        0,
+        0,
+        0, // size
+        DRIVE_FIXED as u8,
+        0,
+        0,
+        0, // drive type
+        0,
+        0,
+        0,
+        0, // serial number
+        16,
+        0,
+        0,
+        0, // label offset
+        0, // empty label
+    ];
+    let ansi: Vec<u8> = path
+        .chars()
+        .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+        .chain([0])
+        .collect();
+    let unicode: Vec<u16> = path.encode_utf16().chain([0]).collect();
+
+    let volume_id = LINK_INFO_HEADER_SIZE;
+    let base_path = volume_id + VOLUME_ID.len() as u32;
+    let suffix = base_path + ansi.len() as u32;
+    let base_path_unicode = suffix + 1;
+    let suffix_unicode = base_path_unicode + 2 * unicode.len() as u32;
+    let size = suffix_unicode + 2;
+
+    put_u32(lnk, size);
+    put_u32(lnk, LINK_INFO_HEADER_SIZE);
+    put_u32(lnk, VOLUME_ID_AND_LOCAL_BASE_PATH);
+    put_u32(lnk, volume_id);
+    put_u32(lnk, base_path);
+    put_u32(lnk, 0); // no network location
+    put_u32(lnk, suffix);
+    put_u32(lnk, base_path_unicode);
+    put_u32(lnk, suffix_unicode);
+    lnk.extend(VOLUME_ID);
+    lnk.extend(ansi);
+    lnk.push(0); // empty common path suffix
+    for unit in unicode {
+        put_u16(lnk, unit);
+    }
+    put_u16(lnk, 0);
+}
+
+/// `path` with backslashes as separators, whatever platform the shortcut is created on, and
+/// without a `\\?\` prefix, which Explorer doesn't understand in shortcuts.
+fn windows_path(path: &Path) -> String {
+    let mut out = String::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::VerbatimDisk(letter) => out.push_str(&format!("{}:", letter as char)),
+                Prefix::VerbatimUNC(server, share) => out.push_str(&format!(
+                    r"\\{}\{}",
+                    server.to_string_lossy(),
+                    share.to_string_lossy()
+                )),
+                _ => out.push_str(&prefix.as_os_str().to_string_lossy()),
+            },
+            Component::RootDir => out.push('\\'),
+            component => {
+                if !out.is_empty() && !out.ends_with('\\') {
+                    out.push('\\');
+                }
+                out.push_str(&component.as_os_str().to_string_lossy());
+            }
+        }
+    }
+    out
+}
+
+fn put_u16(lnk: &mut Vec<u8>, value: u16) {
+    lnk.extend(value.to_le_bytes());
+}
+
+fn put_u32(lnk: &mut Vec<u8>, value: u32) {
+    lnk.extend(value.to_le_bytes());
+}
@@ -0,0 +1,176 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::Document;
+
+/// Format of the metadata files written next to copied documents.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SidecarFormat {
+    Json,
+    Yaml,
+}
+
+#[derive(Serialize)]
+struct Metadata<'a> {
+    pk: i64,
+    title: &'a str,
+    created: DateTime<Utc>,
+    correspondent: Option<&'a str>,
+    document_type: Option<&'a str>,
+    tags: Vec<&'a str>,
+    asn: Option<i64>,
+    notes: Vec<NoteMetadata<'a>>,
+}
+
+#[derive(Serialize)]
+struct NoteMetadata<'a> {
+    created: DateTime<Utc>,
+    text: &'a str,
+}
+
+impl SidecarFormat {
+    /// Path of the sidecar of the copied document at `path`, e.g. `Invoice.pdf.json`.
+    pub fn path_for(self, path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_owned();
+        name.push(match self {
+            SidecarFormat::Json => ".json",
+            SidecarFormat::Yaml => ".yaml",
+        });
+        path.with_file_name(name)
+    }
+
+    /// Renders the metadata of `doc`.
+    pub fn render(self, doc: &Document) -> String {
+        let metadata = Metadata {
+            pk: doc.pk,
+            title: &doc.title,
+            created: doc.created,
+            correspondent: doc.correspondent.as_ref().map(|c| c.name.as_str()),
+            document_type: doc.document_type.as_ref().map(|t| t.name.as_str()),
+            tags: doc.tags.iter().map(|t| t.name.as_str()).collect(),
+            asn: doc.asn,
+            notes: doc
+                .notes
+                .iter()
+                .map(|n| NoteMetadata {
+                    created: n.created,
+                    text: &n.text,
+                })
+                .collect(),
+        };
+        match self {
+            SidecarFormat::Json => {
+                let mut json = serde_json::to_string_pretty(&metadata).expect("serializable");
+                json.push('\n');
+                json
+            }
+            SidecarFormat::Yaml => yaml(&serde_json::to_value(&metadata).expect("serializable")),
+        }
+    }
+}
+
+/// Writes `value` as block-style YAML. Scalars are emitted as JSON, which YAML accepts as
+/// flow scalars, so strings never need YAML specific escaping.
+pub(crate) fn yaml(value: &serde_json::Value) -> String {
+    let mut out = String::new();
+    yaml_value(&mut out, value, 0);
+    out
+}
+
+fn yaml_value(out: &mut String, value: &serde_json::Value, indent: usize) {
+    use serde_json::Value;
+
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = yaml_key(key);
+                match value {
+                    Value::Object(m) if !m.is_empty() => {
+                        let _ = writeln!(out, "{pad}{key}:");
+                        yaml_value(out, value, indent + 2);
+                    }
+                    Value::Array(a) if !a.is_empty() => {
+                        let _ = writeln!(out, "{pad}{key}:");
+                        yaml_value(out, value, indent);
+                    }
+                    _ => {
+                        let _ = writeln!(out, "{pad}{key}: {value}");
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                match item {
+                    Value::Object(m) if !m.is_empty() => {
+                        // the first key goes on the dash line, the rest aligned below it
+                        let mut block = String::new();
+                        yaml_value(&mut block, item, indent + 2);
+                        let _ = write!(out, "{pad}- {}", &block[indent + 2..]);
+                    }
+                    _ => {
+                        let _ = writeln!(out, "{pad}- {item}");
+                    }
+                }
+            }
+        }
+        scalar => {
+            let _ = writeln!(out, "{pad}{scalar}");
+        }
+    }
+}
+
+/// `key` as a mapping key: identifiers as they are, anything else, e.g. custom field names
+/// like `Due: date`, quoted like a JSON string. So are words YAML reads as booleans or null.
+fn yaml_key(key: &str) -> String {
+    let plain = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !["true", "false", "yes", "no", "on", "off", "null", "y", "n"]
+            .contains(&key.to_ascii_lowercase().as_str());
+    match plain {
+        true => key.to_owned(),
+        false => serde_json::Value::from(key).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn yaml_quotes_keys_that_are_not_identifiers() {
+        let value = json!({
+            "title": "Invoice",
+            "Due: date": "2024-01-31",
+            "- item": 1,
+            "null": null,
+            "a\nb": "#c",
+        });
+        assert_eq!(
+            yaml(&value),
+            "\"- item\": 1\n\"Due: date\": \"2024-01-31\"\n\"a\\nb\": \"#c\"\n\"null\": null\n\
+             title: \"Invoice\"\n"
+        );
+    }
+
+    #[test]
+    fn yaml_nests_maps_and_lists() {
+        let value = json!({"fields": {"Project": "House"}, "tags": ["a", "b"]});
+        assert_eq!(
+            yaml(&value),
+            "fields:\n  Project: \"House\"\ntags:\n- \"a\"\n- \"b\"\n"
+        );
+    }
+}
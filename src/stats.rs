@@ -0,0 +1,193 @@
+use std::{collections::BTreeMap, fmt::Write as _, io};
+
+use chrono::Datelike;
+use indicatif::HumanBytes;
+use serde::Serialize;
+
+use crate::{
+    export::ExportSource,
+    timezone::{self, TimeZone},
+    Document, Manifest,
+};
+
+/// Output format of the `stats` subcommand.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum StatsFormat {
+    /// Aligned, human readable tables
+    #[default]
+    Table,
+    Json,
+    /// One `group,name,documents,bytes` row per count
+    Csv,
+}
+
+/// Number and total size of a group of documents.
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct Count {
+    pub documents: u64,
+    /// Size of the exported files, archive version and original
+    pub bytes: u64,
+}
+
+impl Count {
+    fn add(&mut self, bytes: u64) {
+        self.documents += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Document counts and sizes of a manifest, grouped in the same ways as the views.
+#[derive(Default, Serialize)]
+pub struct Stats {
+    pub total: Count,
+    pub untagged: Count,
+    pub without_correspondent: Count,
+    pub without_document_type: Count,
+    pub by_year: BTreeMap<i32, Count>,
+    pub by_tag: BTreeMap<String, Count>,
+    pub by_correspondent: BTreeMap<String, Count>,
+    pub by_document_type: BTreeMap<String, Count>,
+}
+
+impl Stats {
+    /// Collects the stats of `manifest`, taking file sizes from `export` and the years documents
+    /// were created in from `timezone`. Missing files, and all files when reading from the API,
+    /// count as empty.
+    pub fn collect(manifest: &Manifest, export: &ExportSource, timezone: &TimeZone) -> Self {
+        let mut stats = Stats::default();
+        for doc in &manifest.documents {
+            let bytes = size(doc, export);
+            stats.total.add(bytes);
+            for tag in &doc.tags {
+                stats.by_tag.entry(tag.name.clone()).or_default().add(bytes);
+            }
+            if doc.tags.is_empty() {
+                stats.untagged.add(bytes);
+            }
+            match &doc.correspondent {
+                Some(c) => stats
+                    .by_correspondent
+                    .entry(c.name.clone())
+                    .or_default()
+                    .add(bytes),
+                None => stats.without_correspondent.add(bytes),
+            }
+            match &doc.document_type {
+                Some(t) => stats
+                    .by_document_type
+                    .entry(t.name.clone())
+                    .or_default()
+                    .add(bytes),
+                None => stats.without_document_type.add(bytes),
+            }
+            stats
+                .by_year
+                .entry(timezone::created(doc, timezone).year())
+                .or_default()
+                .add(bytes);
+        }
+        stats
+    }
+
+    /// Every count as `(group, name, count)`, in the order they are printed.
+    fn rows(&self) -> Vec<(&'static str, String, Count)> {
+        let mut rows = vec![
+            ("total", String::new(), self.total),
+            ("untagged", String::new(), self.untagged),
+            (
+                "without_correspondent",
+                String::new(),
+                self.without_correspondent,
+            ),
+            (
+                "without_document_type",
+                String::new(),
+                self.without_document_type,
+            ),
+        ];
+        rows.extend(
+            self.by_year
+                .iter()
+                .map(|(y, c)| ("year", y.to_string(), *c)),
+        );
+        rows.extend(self.by_tag.iter().map(|(t, c)| ("tag", t.clone(), *c)));
+        rows.extend(
+            self.by_correspondent
+                .iter()
+                .map(|(n, c)| ("correspondent", n.clone(), *c)),
+        );
+        rows.extend(
+            self.by_document_type
+                .iter()
+                .map(|(n, c)| ("document_type", n.clone(), *c)),
+        );
+        rows
+    }
+
+    /// Renders the stats as one table per grouping.
+    pub fn table(&self) -> String {
+        let mut out = String::new();
+        let line = |out: &mut String, name: &str, count: &Count| {
+            let _ = writeln!(
+                out,
+                "  {name:<40} {:>9} {:>12}",
+                count.documents,
+                HumanBytes(count.bytes).to_string()
+            );
+        };
+
+        let _ = writeln!(out, "  {:<40} {:>9} {:>12}", "", "documents", "size");
+        line(&mut out, "all documents", &self.total);
+        line(&mut out, "untagged", &self.untagged);
+        line(
+            &mut out,
+            "without correspondent",
+            &self.without_correspondent,
+        );
+        line(
+            &mut out,
+            "without document type",
+            &self.without_document_type,
+        );
+
+        let mut group = "";
+        for (name, value, count) in self.rows().iter().skip(4) {
+            if *name != group {
+                group = name;
+                let _ = writeln!(out, "\nby {}:", name.replace('_', " "));
+            }
+            line(&mut out, value, count);
+        }
+        out
+    }
+
+    /// Writes the stats as CSV with a `group,name,documents,bytes` header.
+    pub fn write_csv(&self, writer: impl io::Write) -> csv::Result<()> {
+        let mut csv = csv::Writer::from_writer(writer);
+        csv.write_record(["group", "name", "documents", "bytes"])?;
+        for (group, name, count) in self.rows() {
+            csv.write_record([
+                group,
+                &name,
+                &count.documents.to_string(),
+                &count.bytes.to_string(),
+            ])?;
+        }
+        csv.flush()?;
+        Ok(())
+    }
+}
+
+/// Combined size of the exported files of `doc`.
+fn size(doc: &Document, export: &ExportSource) -> u64 {
+    let len = |name: &str| {
+        export
+            .file_info(name)
+            .map_or(0, |info| info.len.unwrap_or_default())
+    };
+    let mut bytes = len(&doc.archive_name);
+    if doc.has_archive() {
+        bytes += len(&doc.file_name);
+    }
+    bytes
+}
@@ -0,0 +1,160 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDateTime};
+
+use crate::{sanitize::Sanitizer, Document};
+
+/// Renders the paperless storage path template of `doc` into a relative path.
+///
+/// Both the legacy `{created_year}` and the newer `{{ created_year }}` placeholder syntax are
+/// understood. Missing values render as `none` (like paperless does), unknown placeholders are
+/// kept verbatim. Every component is sanitized and the extension of the exported archive file is
+/// appended to the last one. Dates are those of `created`, the local time `doc` was created at.
+pub fn render(
+    template: &str,
+    doc: &Document,
+    created: NaiveDateTime,
+    sanitizer: &Sanitizer,
+) -> PathBuf {
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let (open, close) = if after.starts_with("{{") {
+            ("{{", "}}")
+        } else {
+            ("{", "}")
+        };
+        let Some(end) = after[open.len()..].find(close) else {
+            rendered.push_str(after);
+            rest = "";
+            break;
+        };
+        let key = after[open.len()..open.len() + end].trim();
+        match placeholder(key, doc, created) {
+            Some(value) => rendered.push_str(&sanitizer.chars(&value)),
+            None => rendered.push_str(&after[..open.len() + end + close.len()]),
+        }
+        rest = &after[open.len() + end + close.len()..];
+    }
+    rendered.push_str(rest);
+
+    let mut path: PathBuf = rendered
+        .split('/')
+        .map(str::trim)
+        .filter(|c| !c.is_empty() && *c != "." && *c != "..")
+        .map(|c| sanitizer.component(c))
+        .collect();
+    // appended rather than replacing the extension, which would cut `v1.2` down to `v1`
+    let ext = Path::new(&doc.archive_name).extension();
+    if let (Some(ext), Some(name)) = (ext, path.file_name()) {
+        let suffix = format!(".{}", ext.to_string_lossy()).to_lowercase();
+        if !name.to_string_lossy().to_lowercase().ends_with(&suffix) {
+            let mut name = name.to_owned();
+            name.push(".");
+            name.push(ext);
+            path.set_file_name(name);
+        }
+    }
+    path
+}
+
+fn placeholder(key: &str, doc: &Document, created: NaiveDateTime) -> Option<String> {
+    Some(match key {
+        "title" => doc.title.clone(),
+        "correspondent" => doc
+            .correspondent
+            .as_ref()
+            .map_or_else(|| "none".to_owned(), |c| c.name.clone()),
+        "document_type" => doc
+            .document_type
+            .as_ref()
+            .map_or_else(|| "none".to_owned(), |t| t.name.clone()),
+        "tag_list" => doc
+            .tags
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>()
+            .join(","),
+        "created" => created.format("%Y-%m-%d").to_string(),
+        "created_year" => created.year().to_string(),
+        "created_year_short" => created.format("%y").to_string(),
+        "created_month" => created.format("%m").to_string(),
+        "created_month_name" => created.format("%B").to_string(),
+        "created_month_name_short" => created.format("%b").to_string(),
+        "created_day" => created.format("%d").to_string(),
+        "doc_pk" => format!("{:07}", doc.pk),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_manifest;
+
+    const MANIFEST: &str = r#"[
+        {"model": "documents.correspondent", "pk": 1, "fields": {"name": "Bank: Transfer"}},
+        {"model": "documents.document", "pk": 7, "fields": {
+            "title": "Invoice 4/2", "created": "2023-05-01T10:00:00Z", "correspondent": 1,
+            "tags": [], "checksum": "abc"},
+         "__exported_file_name__": "0000007.png",
+         "__exported_archive_name__": "archive/0000007.pdf"}
+    ]"#;
+
+    fn render_titled(template: &str, title: &str) -> PathBuf {
+        let mut doc = parse_manifest(MANIFEST.as_bytes())
+            .unwrap()
+            .documents
+            .remove(0);
+        doc.title = title.to_owned();
+        let created = doc.created.naive_utc();
+        render(template, &doc, created, &Sanitizer::default())
+    }
+
+    #[test]
+    fn both_placeholder_syntaxes_are_rendered() {
+        assert_eq!(
+            render_titled("{created_year}/{correspondent}/{title}", "Invoice 4/2"),
+            Path::new("2023/Bank_ Transfer/Invoice 4_2.pdf")
+        );
+        assert_eq!(
+            render_titled(
+                "{{ created_year }}-{{created_month}}/{{ document_type }}/{{ doc_pk }}",
+                "Invoice"
+            ),
+            Path::new("2023-05/none/0000007.pdf")
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_kept() {
+        assert_eq!(
+            render_titled("{owner}/{{ title }}", "Invoice"),
+            Path::new("{owner}/Invoice.pdf")
+        );
+        assert_eq!(
+            render_titled("{created_day}/{title", "Invoice"),
+            Path::new("01/{title.pdf")
+        );
+    }
+
+    #[test]
+    fn components_stay_inside_the_output() {
+        assert_eq!(
+            render_titled("../{title}/./ x /", "Invoice"),
+            Path::new("Invoice/x.pdf")
+        );
+    }
+
+    #[test]
+    fn the_extension_is_appended_unless_present() {
+        assert_eq!(
+            render_titled("{title}", "Release v1.2"),
+            Path::new("Release v1.2.pdf")
+        );
+        assert_eq!(render_titled("{title}", "Scan.PDF"), Path::new("Scan.PDF"));
+    }
+}
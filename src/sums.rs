@@ -0,0 +1,145 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use rayon::prelude::*;
+
+/// Name of the checksum files, in the format of coreutils' `sha256sum`, so
+/// `sha256sum -c SHA256SUMS` checks them as well.
+pub const SUMS_FILE: &str = "SHA256SUMS";
+
+/// Where the checksums of the copied documents are written.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum SumsLayout {
+    /// One [`SUMS_FILE`] in the output directory
+    #[default]
+    Single,
+    /// A [`SUMS_FILE`] in every directory with copies, listing just that directory's files
+    PerDirectory,
+}
+
+impl SumsLayout {
+    fn sums_file(self, output_dir: &Path, file: &Path) -> PathBuf {
+        match self {
+            SumsLayout::Single => output_dir.join(SUMS_FILE),
+            SumsLayout::PerDirectory => file.with_file_name(SUMS_FILE),
+        }
+    }
+}
+
+/// The checksum files listing `files` when laid out by `layout`, with the files each lists.
+pub fn sums_files<'a>(
+    output_dir: &Path,
+    files: &'a [PathBuf],
+    layout: SumsLayout,
+) -> BTreeMap<PathBuf, Vec<&'a PathBuf>> {
+    let mut sums_files: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for file in files {
+        sums_files
+            .entry(layout.sums_file(output_dir, file))
+            .or_default()
+            .push(file);
+    }
+    for files in sums_files.values_mut() {
+        files.sort();
+        files.dedup();
+    }
+    sums_files
+}
+
+/// The contents of the checksum file `sums_file` listing the SHA-256 of `files`, which `hash`
+/// computes. Hashes it already records are reused for the `unchanged` files instead.
+pub fn sums(
+    sums_file: &Path,
+    files: &[&PathBuf],
+    unchanged: &HashSet<PathBuf>,
+    hash: &(dyn Fn(&Path) -> io::Result<String> + Sync),
+) -> io::Result<String> {
+    let known: HashMap<PathBuf, String> = read_sums(sums_file)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(hash, path)| (path, hash))
+        .collect();
+    let dir = sums_file.parent().unwrap_or(Path::new(""));
+    let lines = files
+        .par_iter()
+        .map(|&file| {
+            let hash = match known.get(file) {
+                Some(hash) if unchanged.contains(file) => hash.clone(),
+                _ => hash(file)?,
+            };
+            Ok(line(&hash, file.strip_prefix(dir).unwrap_or(file)))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(lines.concat())
+}
+
+/// The entries of the checksum file `path` as hashes and the paths they are for, resolved
+/// against the file's directory.
+pub fn read_sums(path: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let invalid = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("malformed line in {}: {line}", path.display()),
+        )
+    };
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (escaped, line) = match line.strip_prefix('\\') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            // a space marks text mode, an asterisk binary mode
+            let (hash, name) = line
+                .split_once(' ')
+                .and_then(|(hash, name)| Some((hash, name.strip_prefix([' ', '*'])?)))
+                .ok_or_else(|| invalid(line))?;
+            let name = if escaped {
+                unescape(name)
+            } else {
+                name.to_owned()
+            };
+            Ok((hash.to_lowercase(), dir.join(name)))
+        })
+        .collect()
+}
+
+/// A line of `sha256sum` output: names with a backslash or newline are escaped, marked by a
+/// leading backslash.
+fn line(hash: &str, path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let name = components.join("/");
+    if name.contains(['\\', '\n']) {
+        let name = name.replace('\\', "\\\\").replace('\n', "\\n");
+        format!("\\{hash}  {name}\n")
+    } else {
+        format!("{hash}  {name}\n")
+    }
+}
+
+fn unescape(name: &str) -> String {
+    let mut out = String::new();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    export::{ExportSource, FileInfo},
+    link::LinkMode,
+    shortcut::shortcut,
+};
+
+/// Whether `dst` is an up-to-date copy of `src`: same size and not older than the source,
+/// and with `compare_contents` also byte-for-byte identical.
+pub fn up_to_date(src: &Path, dst: &Path, compare_contents: bool) -> io::Result<bool> {
+    let src_meta = fs::metadata(src)?;
+    let src_info = FileInfo {
+        len: Some(src_meta.len()),
+        modified: src_meta.modified().ok(),
+    };
+    if !fresh(&src_info, dst, None) {
+        return Ok(false);
+    }
+    if compare_contents {
+        return same_contents(src, dst);
+    }
+    Ok(true)
+}
+
+/// Like [`up_to_date`], for the exported file `name` of `export`. A `dst` whose modification
+/// time was set to `stamped` after copying also counts as fresh.
+pub fn export_up_to_date(
+    export: &ExportSource,
+    name: &str,
+    dst: &Path,
+    compare_contents: bool,
+    stamped: Option<SystemTime>,
+) -> io::Result<bool> {
+    if !fresh(&export.file_info(name)?, dst, stamped) {
+        return Ok(false);
+    }
+    if compare_contents {
+        return export.read(name, |src| same_contents_as(src, dst));
+    }
+    Ok(true)
+}
+
+/// Whether `dst` is an up-to-date copy of the exported file `name` that was encrypted or
+/// decrypted on the way. That changes the size, so only the modification times can tell; a
+/// `dst` whose modification time was set to `stamped` also counts as fresh.
+pub fn converted_up_to_date(
+    export: &ExportSource,
+    name: &str,
+    dst: &Path,
+    stamped: Option<SystemTime>,
+) -> io::Result<bool> {
+    let src = export.file_info(name)?;
+    let Ok(dst) = fs::metadata(dst).and_then(|meta| meta.modified()) else {
+        return Ok(false);
+    };
+    Ok(Some(dst) == stamped || src.modified.is_none_or(|src| dst >= src))
+}
+
+fn fresh(src: &FileInfo, dst: &Path, stamped: Option<SystemTime>) -> bool {
+    let Ok(dst_meta) = fs::metadata(dst) else {
+        return false;
+    };
+    let newer = match (src.modified, dst_meta.modified()) {
+        (_, Ok(dst)) if Some(dst) == stamped => true,
+        (Some(src), Ok(dst)) => dst >= src,
+        _ => true,
+    };
+    src.len.is_none_or(|len| len == dst_meta.len()) && newer
+}
+
+/// Whether `link` already refers to `target` the way `mode` would have created it.
+pub fn link_up_to_date(
+    mode: LinkMode,
+    target: &Path,
+    link: &Path,
+    compare_contents: bool,
+) -> io::Result<bool> {
+    match mode {
+        LinkMode::Symlink => Ok(fs::read_link(link).is_ok_and(|t| t == target)),
+        LinkMode::Shortcut => {
+            let expected = shortcut(target, link)?;
+            Ok(fs::read(link).is_ok_and(|lnk| lnk == expected))
+        }
+        _ => {
+            let is_symlink = fs::symlink_metadata(link).is_ok_and(|m| m.is_symlink());
+            Ok(!is_symlink && up_to_date(target, link, compare_contents)?)
+        }
+    }
+}
+
+/// Whether the files `a` and `b` have identical contents.
+pub fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    same_contents_as(&mut BufReader::new(File::open(a)?), b)
+}
+
+/// Whether reading `a` to the end yields exactly the contents of the file `b`.
+pub fn same_contents_as(a: &mut dyn Read, b: &Path) -> io::Result<bool> {
+    let mut b = BufReader::new(File::open(b)?);
+    let (mut buf_a, mut buf_b) = ([0u8; 8192], [0u8; 8192]);
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Removes every file below `dir` that is not in `keep` through `remove`, then any
+/// directories left empty. Returns the number of removed files.
+pub fn prune(
+    dir: &Path,
+    keep: &HashSet<PathBuf>,
+    remove: &dyn Fn(&Path) -> io::Result<()>,
+) -> io::Result<u64> {
+    let mut removed = 0;
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(0);
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            removed += prune(&path, keep, remove)?;
+            if fs::read_dir(&path)?.next().is_none() {
+                fs::remove_dir(&path)?;
+            }
+        } else if !keep.contains(&path) {
+            remove(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
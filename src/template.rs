@@ -0,0 +1,236 @@
+use std::{path::Path, str::FromStr};
+
+use anyhow::bail;
+use chrono::format::{Item, StrftimeItems};
+
+use crate::{
+    sanitize::Sanitizer,
+    timezone::{self, TimeZone},
+    Document,
+};
+
+/// A user supplied output file name like
+/// `{{created:%Y-%m-%d}} - {{correspondent}} - {{title}}.pdf`.
+///
+/// Placeholders are `{{field}}` or `{{field:format}}`; the format is a chrono format string for
+/// date fields. `{{custom_field:NAME}}` is the value of the custom field `NAME`. Rendered values
+/// are sanitized so they can't introduce illegal path characters or subfolders.
+#[derive(Clone, Debug)]
+pub struct FilenameTemplate {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Field {
+        name: String,
+        format: Option<String>,
+    },
+}
+
+const FIELDS: &[&str] = &[
+    "title",
+    "correspondent",
+    "document_type",
+    "tags",
+    "created",
+    "pk",
+    "asn",
+    "original_name",
+    "archive_name",
+    "ext",
+    "custom_field",
+];
+
+impl FromStr for FilenameTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_owned()));
+            }
+            let Some(end) = rest[start..].find("}}") else {
+                bail!("unterminated placeholder in template `{s}`");
+            };
+            let placeholder = &rest[start + 2..start + end];
+            let (name, format) = match placeholder.split_once(':') {
+                Some((name, format)) => (name.trim(), Some(format.to_owned())),
+                None => (placeholder.trim(), None),
+            };
+            if !FIELDS.contains(&name) {
+                bail!(
+                    "unknown placeholder `{name}` in template `{s}` (expected one of {})",
+                    FIELDS.join(", ")
+                );
+            }
+            // rendering an invalid date format would panic
+            let invalid_date = |format: &str| StrftimeItems::new(format).any(|i| i == Item::Error);
+            if let Some(format) = format
+                .as_deref()
+                .filter(|f| name == "created" && invalid_date(f))
+            {
+                bail!("invalid date format `{format}` in template `{s}`");
+            }
+            if name == "custom_field" && format.is_none() {
+                bail!("missing field name in `{{{{custom_field}}}}`, e.g. `{{{{custom_field:Project}}}}`");
+            }
+            segments.push(Segment::Field {
+                name: name.to_owned(),
+                format,
+            });
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_owned()));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl FilenameTemplate {
+    /// `2023-05-01 Invoice 42.pdf`: the document's creation date and title.
+    pub fn title() -> Self {
+        "{{created:%Y-%m-%d}} {{title}}.{{ext}}"
+            .parse()
+            .expect("valid template")
+    }
+
+    /// Renders the template for `doc` into a single file name, with its creation date in
+    /// `timezone`.
+    pub fn render(&self, doc: &Document, sanitizer: &Sanitizer, timezone: &TimeZone) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => out.push_str(text),
+                Segment::Field { name, format } => {
+                    out.push_str(&sanitizer.chars(&field(doc, name, format.as_deref(), timezone)))
+                }
+            }
+        }
+        sanitizer.component(&out)
+    }
+}
+
+fn field(doc: &Document, name: &str, format: Option<&str>, timezone: &TimeZone) -> String {
+    let file_name = |name: &str| {
+        Path::new(name)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    match name {
+        "title" => doc.title.clone(),
+        "correspondent" => doc
+            .correspondent
+            .as_ref()
+            .map(|c| c.name.clone())
+            .unwrap_or_default(),
+        "document_type" => doc
+            .document_type
+            .as_ref()
+            .map(|t| t.name.clone())
+            .unwrap_or_default(),
+        "tags" => doc
+            .tags
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>()
+            .join(format.unwrap_or(",")),
+        "created" => timezone::created(doc, timezone)
+            .format(format.unwrap_or("%Y-%m-%d"))
+            .to_string(),
+        "pk" => match format.and_then(|f| f.parse::<usize>().ok()) {
+            Some(width) => format!("{:0width$}", doc.pk),
+            None => doc.pk.to_string(),
+        },
+        "asn" => match (doc.asn, format.and_then(|f| f.parse::<usize>().ok())) {
+            (Some(asn), Some(width)) => format!("{asn:0width$}"),
+            (Some(asn), None) => asn.to_string(),
+            (None, _) => String::new(),
+        },
+        "custom_field" => format
+            .and_then(|name| doc.custom_fields.get(name))
+            .map(|value| value.to_string())
+            .unwrap_or_default(),
+        "original_name" => file_name(&doc.file_name),
+        "archive_name" => file_name(&doc.archive_name),
+        "ext" => Path::new(&doc.archive_name)
+            .extension()
+            .map(|e| e.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        _ => unreachable!("placeholders are validated when parsing"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_manifest;
+
+    const MANIFEST: &str = r#"[
+        {"model": "documents.tag", "pk": 1, "fields": {"name": "invoice"}},
+        {"model": "documents.tag", "pk": 2, "fields": {"name": "finance/taxes"}},
+        {"model": "documents.correspondent", "pk": 1, "fields": {"name": "Bank: Transfer"}},
+        {"model": "documents.document", "pk": 7, "fields": {
+            "title": "Invoice 4/2", "created": "2023-05-01T23:30:00Z", "correspondent": 1,
+            "tags": [1, 2], "checksum": "abc", "archive_serial_number": 17},
+         "__exported_file_name__": "0000007.png",
+         "__exported_archive_name__": "archive/0000007.pdf"},
+        {"model": "documents.customfield", "pk": 1,
+         "fields": {"name": "Due", "data_type": "date"}},
+        {"model": "documents.customfieldinstance", "pk": 1,
+         "fields": {"document": 7, "field": 1, "value_date": "2024-01-31"}}
+    ]"#;
+
+    fn render(template: &str) -> String {
+        let manifest = parse_manifest(MANIFEST.as_bytes()).unwrap();
+        let template: FilenameTemplate = template.parse().unwrap();
+        template.render(
+            &manifest.documents[0],
+            &Sanitizer::default(),
+            &TimeZone::UTC,
+        )
+    }
+
+    #[test]
+    fn values_are_sanitized_into_one_name() {
+        assert_eq!(
+            render("{{created:%Y-%m-%d}} - {{correspondent}} - {{title}}.{{ext}}"),
+            "2023-05-01 - Bank_ Transfer - Invoice 4_2.pdf"
+        );
+    }
+
+    #[test]
+    fn formats_apply_to_their_fields() {
+        assert_eq!(render("{{pk:5}}-{{asn:4}}"), "00007-0017");
+        assert_eq!(render("{{ pk }}-{{asn}}"), "7-17");
+        assert_eq!(render("{{tags}}"), "invoice,finance_taxes");
+        assert_eq!(render("{{tags: }}"), "invoice finance_taxes");
+        assert_eq!(render("{{created:%d.%m.%y}}"), "01.05.23");
+        assert_eq!(render("{{custom_field:Due}}"), "2024-01-31");
+        assert_eq!(render("{{custom_field:Missing}}x"), "x");
+        assert_eq!(
+            render("{{original_name}}+{{archive_name}}"),
+            "0000007+0000007"
+        );
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        for template in [
+            "{{title",
+            "{{size}}",
+            "{{custom_field}}",
+            "{{created:%}}",
+            "{{Title}}",
+        ] {
+            assert!(template.parse::<FilenameTemplate>().is_err(), "{template}");
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, NaiveTime, Utc};
+
+use crate::Document;
+
+/// The time zone dates are taken in by the date views, the filters and file name templates.
+///
+/// jiff only provides the time zone database; the offsets it looks up are applied to chrono's
+/// dates, which are used everywhere else.
+#[derive(Clone, Debug)]
+pub struct TimeZone(jiff::tz::TimeZone);
+
+impl TimeZone {
+    pub const UTC: TimeZone = TimeZone(jiff::tz::TimeZone::UTC);
+}
+
+/// The time zone `name` stands for: an IANA name like `Europe/Berlin`, `UTC`, or `local` for
+/// the system's.
+pub fn parse_timezone(name: &str) -> anyhow::Result<TimeZone> {
+    let timezone = match name {
+        "local" => {
+            jiff::tz::TimeZone::try_system().context("failed to determine the local time zone")?
+        }
+        name => jiff::tz::TimeZone::get(name).with_context(|| {
+            format!("unknown time zone `{name}`, expected e.g. `Europe/Berlin`, `UTC` or `local`")
+        })?,
+    };
+    Ok(TimeZone(timezone))
+}
+
+/// `time` in `timezone`, with the offset it has there.
+pub fn zoned_time(time: DateTime<Utc>, timezone: &TimeZone) -> DateTime<FixedOffset> {
+    let offset = jiff::Timestamp::from_second(time.timestamp())
+        .ok()
+        .map(|timestamp| timezone.0.to_offset(timestamp))
+        .and_then(|offset| FixedOffset::east_opt(offset.seconds()))
+        .unwrap_or(FixedOffset::east_opt(0).expect("valid offset"));
+    time.with_timezone(&offset)
+}
+
+/// The wall clock time in `timezone` at `time`.
+pub fn local_time(time: DateTime<Utc>, timezone: &TimeZone) -> NaiveDateTime {
+    zoned_time(time, timezone).naive_local()
+}
+
+/// When `doc` was created, in `timezone`. The day paperless shows wins if the export has it, so
+/// documents are filed, filtered and named by the same day as in paperless.
+pub fn created(doc: &Document, timezone: &TimeZone) -> DateTime<FixedOffset> {
+    let created = zoned_time(doc.created, timezone);
+    doc.created_date
+        .and_then(|date| {
+            date.and_time(NaiveTime::MIN)
+                .and_local_timezone(*created.offset())
+                .single()
+        })
+        .unwrap_or(created)
+}
@@ -0,0 +1,138 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+/// Directory in the output directory holding what the last run replaced, to restore with
+/// [`undo`].
+pub const UNDO_DIR: &str = ".paperless-organize-undo";
+
+const JOURNAL_FILE: &str = "journal.jsonl";
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Change {
+    /// The run wrote `path`, which didn't exist before
+    Created { path: PathBuf },
+    /// The run replaced or deleted `path`, whose previous version was moved to `backup`
+    Moved { path: PathBuf, backup: PathBuf },
+}
+
+/// Journal of the changes a run makes to the output directory, kept so [`undo`] can revert
+/// them.
+///
+/// Instead of being deleted, replaced files and directories are moved into [`UNDO_DIR`], which
+/// only costs space until the next run starts a new journal.
+pub struct UndoLog {
+    dir: PathBuf,
+    journal: Mutex<File>,
+    backups: AtomicU64,
+}
+
+impl UndoLog {
+    /// Starts a new journal in `output_dir`, discarding the one of the previous run.
+    pub fn start(output_dir: &Path) -> io::Result<Self> {
+        let dir = output_dir.join(UNDO_DIR);
+        match fs::remove_dir_all(&dir) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+        fs::create_dir_all(&dir)?;
+        let journal = File::create(dir.join(JOURNAL_FILE))?;
+        Ok(Self {
+            dir,
+            journal: Mutex::new(journal),
+            backups: AtomicU64::new(0),
+        })
+    }
+
+    fn record(&self, change: &Change) -> io::Result<()> {
+        let mut line = serde_json::to_vec(change)?;
+        line.push(b'\n');
+        let mut journal = self.journal.lock().unwrap();
+        journal.write_all(&line)?;
+        journal.flush()
+    }
+
+    /// Moves the file or directory `path`, if it exists, out of the way.
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        if path.symlink_metadata().is_err() {
+            return Ok(());
+        }
+        let backup = self
+            .dir
+            .join(self.backups.fetch_add(1, Ordering::Relaxed).to_string());
+        self.record(&Change::Moved {
+            path: path.to_owned(),
+            backup: backup.clone(),
+        })?;
+        fs::rename(path, backup)
+    }
+
+    /// Moves away what is at `path` and records that the run is about to create it.
+    pub fn replace(&self, path: &Path) -> io::Result<()> {
+        self.remove(path)?;
+        self.record(&Change::Created {
+            path: path.to_owned(),
+        })
+    }
+}
+
+/// Reverts the changes journaled by the last run in `output_dir`, returning how many there were.
+pub fn undo(output_dir: &Path) -> anyhow::Result<usize> {
+    let dir = output_dir.join(UNDO_DIR);
+    let journal_path = dir.join(JOURNAL_FILE);
+    if !journal_path.exists() {
+        bail!(
+            "nothing to undo in {}, only `organize --undoable` runs can be reverted",
+            output_dir.display()
+        );
+    }
+    let journal = File::open(&journal_path)
+        .with_context(|| format!("failed to open {}", journal_path.display()))?;
+    let changes = BufReader::new(journal)
+        .lines()
+        .map(|line| Ok(serde_json::from_str::<Change>(&line?)?))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .with_context(|| format!("failed to read {}", journal_path.display()))?;
+
+    for change in changes.iter().rev() {
+        match change {
+            Change::Created { path } => {
+                let removed = match path.symlink_metadata() {
+                    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+                    Ok(_) => fs::remove_file(path),
+                    Err(_) => Ok(()),
+                };
+                removed.with_context(|| format!("failed to remove {}", path.display()))?;
+                remove_empty_parents(path, output_dir);
+            }
+            Change::Moved { path, backup } => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(backup, path)
+                    .with_context(|| format!("failed to restore {}", path.display()))?;
+            }
+        }
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(changes.len())
+}
+
+/// Removes the directories containing `path` up to `root` as long as they are empty.
+fn remove_empty_parents(path: &Path, root: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if dir == root || fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    ffi::OsStr,
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use clap::ValueEnum;
+
+use rayon::prelude::*;
+use walkdir::{DirEntry, WalkDir};
+
+use crate::{
+    checksum,
+    config::DEFAULT_CONFIG_FILE,
+    export::ExportSource,
+    graph::GraphFormat,
+    html,
+    index::IndexFormat,
+    link::LinkMode,
+    organize::{OrganizeOptions, View, ORIGINALS_DIR},
+    report,
+    shortcut::shortcut,
+    sums::{read_sums, SUMS_FILE},
+    sync::same_contents_as,
+    Document, Manifest,
+};
+
+/// An inconsistency between the manifest and the generated views.
+pub enum Problem {
+    /// The output doesn't exist (or is a link that doesn't resolve)
+    Missing(PathBuf),
+    /// A symlink that points somewhere else than expected
+    WrongTarget(PathBuf),
+    /// The output's contents differ from the exported file
+    Mismatch(PathBuf),
+    /// The exported file's checksum differs from the one recorded in the manifest
+    Corrupted(PathBuf),
+    /// A file in the export that no document of the manifest refers to
+    Orphan(PathBuf),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Missing(path) => write!(f, "missing: {}", path.display()),
+            Problem::WrongTarget(path) => write!(f, "wrong link target: {}", path.display()),
+            Problem::Mismatch(path) => write!(f, "content mismatch: {}", path.display()),
+            Problem::Corrupted(path) => write!(f, "checksum mismatch: {}", path.display()),
+            Problem::Orphan(path) => write!(f, "not in the manifest: {}", path.display()),
+        }
+    }
+}
+
+/// Checks that every output `options` would produce for `manifest` exists, resolves and
+/// has the same contents as the exported file.
+pub fn verify_outputs(
+    manifest: &Manifest,
+    options: &OrganizeOptions,
+) -> anyhow::Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+
+    let documents: Vec<_> = manifest
+        .documents
+        .iter()
+        .filter(|d| options.filter.matches(d, &options.timezone) && !options.is_excluded(d))
+        .collect();
+    let names = options.file_names(documents.iter().copied())?;
+    for doc in documents {
+        let export = &options.export;
+        for outputs in std::iter::once(options.outputs(doc, &names))
+            .chain(options.original_outputs(doc, &names))
+        {
+            if export.file_path(&outputs.source).as_ref() != Some(&outputs.target) {
+                problems.extend(check(outputs.target.clone(), export, doc, &outputs.source)?);
+            }
+            for (_, link) in outputs.links {
+                if options.link_mode == LinkMode::Shortcut {
+                    problems.extend(match fs::read(&link) {
+                        Err(_) => Some(Problem::Missing(link)),
+                        Ok(lnk) if lnk != shortcut(&outputs.target, &link)? => {
+                            Some(Problem::WrongTarget(link))
+                        }
+                        Ok(_) => None,
+                    });
+                    continue;
+                }
+                if let Ok(target) = fs::read_link(&link) {
+                    if target != options.symlink_target(&outputs.target, &link) {
+                        problems.push(Problem::WrongTarget(link));
+                        continue;
+                    }
+                }
+                problems.extend(check(link, export, doc, &outputs.source)?);
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Recomputes the checksums of all exported files of `manifest` in `export` and compares
+/// them with the ones paperless recorded.
+pub fn verify_export(manifest: &Manifest, export: &ExportSource) -> Vec<Problem> {
+    manifest
+        .documents
+        .par_iter()
+        .flat_map_iter(|doc| {
+            let mut files = vec![(&doc.file_name, Some(&doc.checksum))];
+            if doc.has_archive() {
+                files.push((&doc.archive_name, doc.archive_checksum.as_ref()));
+            }
+            files.into_iter().filter_map(|(name, expected)| {
+                let expected = expected.filter(|e| !e.is_empty())?;
+                let path = export.display_path(name);
+                match export
+                    .read_document(doc, name, |reader| checksum::digest_like(reader, expected))
+                {
+                    Err(_) => Some(Problem::Missing(path)),
+                    Ok(actual) if !actual.eq_ignore_ascii_case(expected) => {
+                        Some(Problem::Corrupted(path))
+                    }
+                    _ => None,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Compares the files in the export of `options` with `manifest`: reports the files documents
+/// refer to that are missing, and the files no document refers to. Manifests, hidden files
+/// and whatever organizing generates in the output directory are not orphans.
+pub fn check_export(manifest: &Manifest, options: &OrganizeOptions) -> Vec<Problem> {
+    let export = &options.export;
+    if matches!(export, ExportSource::Api(_)) {
+        return Vec::new();
+    }
+    let mut referenced = BTreeSet::new();
+    for doc in &manifest.documents {
+        referenced.insert(doc.file_name.as_str());
+        referenced.insert(doc.archive_name.as_str());
+        referenced.extend(doc.thumbnail_name.as_deref());
+    }
+
+    let names = export.file_names();
+    let mut problems: Vec<_> = referenced
+        .iter()
+        .filter(|name| names.binary_search_by(|n| n.as_str().cmp(name)).is_err())
+        .map(|name| Problem::Missing(export.display_path(name)))
+        .collect();
+
+    let generated = generated_names(options);
+    let export_dir = export.path();
+    problems.extend(
+        names
+            .iter()
+            .filter(|name| !referenced.contains(name.as_str()) && !ExportSource::is_metadata(name))
+            .filter(|name| !generated.contains(OsStr::new(name.split('/').next().unwrap_or(name))))
+            .map(|name| export_dir.join(name))
+            .filter(|path| {
+                options.output_dir == export_dir || !path.starts_with(&options.output_dir)
+            })
+            .map(Problem::Orphan),
+    );
+    problems
+}
+
+/// Names of the files and directories organizing may generate directly in the output
+/// directory, with any options; they are skipped in the export even if the output now goes
+/// elsewhere.
+fn generated_names(options: &OrganizeOptions) -> HashSet<&OsStr> {
+    let mut names: HashSet<&OsStr> = View::ALL
+        .iter()
+        .map(|view| OsStr::new(view.dir_name()))
+        .chain(options.custom_views.iter().map(|v| OsStr::new(&v.name)))
+        .chain(
+            IndexFormat::value_variants()
+                .iter()
+                .map(|format| OsStr::new(format.file_name())),
+        )
+        .chain(
+            GraphFormat::value_variants()
+                .iter()
+                .map(|format| OsStr::new(format.file_name())),
+        )
+        .chain(
+            [
+                html::PAGES_DIR,
+                report::SKIPPED_REPORT,
+                report::ERRORS_REPORT,
+                SUMS_FILE,
+                DEFAULT_CONFIG_FILE,
+            ]
+            .map(OsStr::new),
+        )
+        .collect();
+    if options.include_originals {
+        names.insert(OsStr::new(ORIGINALS_DIR));
+    }
+    if let Some(name) = options
+        .restricted_dir
+        .as_deref()
+        .and_then(|dir| dir.strip_prefix(&options.output_dir).ok())
+        .and_then(|dir| dir.iter().next())
+    {
+        names.insert(name);
+    }
+    names
+}
+
+/// Rechecks the files listed in the [`SUMS_FILE`]s below `output_dir` (outside of hidden
+/// folders such as the undo log). Returns the number of listed files and the problems found.
+pub fn verify_sums(output_dir: &Path) -> anyhow::Result<(usize, Vec<Problem>)> {
+    let sums_files: Vec<_> = WalkDir::new(output_dir)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file() && e.file_name() == SUMS_FILE)
+        .map(DirEntry::into_path)
+        .collect();
+    if sums_files.is_empty() {
+        bail!("no {SUMS_FILE} files found in {}", output_dir.display());
+    }
+
+    let mut entries = Vec::new();
+    for sums_file in sums_files {
+        entries.extend(
+            read_sums(&sums_file)
+                .with_context(|| format!("failed to read {}", sums_file.display()))?,
+        );
+    }
+    let problems = entries
+        .par_iter()
+        .filter_map(|(expected, path)| {
+            let actual =
+                File::open(path).and_then(|file| checksum::sha256(&mut BufReader::new(file)));
+            match actual {
+                Err(_) => Some(Problem::Missing(path.clone())),
+                Ok(actual) if actual != *expected => Some(Problem::Corrupted(path.clone())),
+                Ok(_) => None,
+            }
+        })
+        .collect();
+    Ok((entries.len(), problems))
+}
+
+fn check(
+    path: PathBuf,
+    export: &ExportSource,
+    doc: &Document,
+    source: &str,
+) -> io::Result<Option<Problem>> {
+    if fs::metadata(&path).is_err() {
+        Ok(Some(Problem::Missing(path)))
+    } else if !export.read_document(doc, source, |reader| same_contents_as(reader, &path))? {
+        Ok(Some(Problem::Mismatch(path)))
+    } else {
+        Ok(None)
+    }
+}
@@ -0,0 +1,194 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde_json::Value;
+
+/// Files the exporter records its version in, `metadata.json` in recent releases and
+/// `version.json` in older ones.
+pub const VERSION_FILES: [&str; 2] = ["metadata.json", "version.json"];
+
+/// The newest paperless release whose exports are known to be parsed correctly; later patch
+/// releases are assumed to write the same layout.
+pub const NEWEST_TESTED: ExporterVersion = ExporterVersion::new(2, 17, 1);
+
+/// The release of paperless whose `document_exporter` wrote an export.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ExporterVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ExporterVersion {
+    /// paperless-ngx 1.11 renamed document comments to notes
+    const NOTES: Self = ExporterVersion::new(1, 11, 0);
+
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        ExporterVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Whether the release is newer than [`NEWEST_TESTED`], so its exports may have changed in
+    /// ways this tool doesn't know about.
+    pub fn is_untested(&self) -> bool {
+        (self.major, self.minor) > (NEWEST_TESTED.major, NEWEST_TESTED.minor)
+    }
+
+    /// The version recorded in the contents of `metadata.json` or `version.json`, e.g.
+    /// `{"version": "2.7.2"}`.
+    pub fn from_json(json: &Value) -> anyhow::Result<Self> {
+        json["version"]
+            .as_str()
+            .ok_or_else(|| anyhow!("no `version` in {json}"))?
+            .parse()
+    }
+}
+
+impl FromStr for ExporterVersion {
+    type Err = anyhow::Error;
+
+    /// Parses `2.7.2`, as well as `v1.17`, `2.0.0-beta.rc1` or `1.14.0-dev`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let release = s.trim().trim_start_matches('v');
+        let release = release.split(['-', '+']).next().unwrap_or_default();
+        let mut parts = release.split('.').map(|part| {
+            part.parse::<u32>()
+                .with_context(|| format!("invalid paperless version `{s}`"))
+        });
+        Ok(ExporterVersion {
+            major: parts
+                .next()
+                .unwrap_or_else(|| Err(anyhow!("empty version")))?,
+            minor: parts.next().transpose()?.unwrap_or(0),
+            patch: parts.next().transpose()?.unwrap_or(0),
+        })
+    }
+}
+
+impl fmt::Display for ExporterVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Rewrites the manifest object `object`, written by `version` of the exporter (`None` if the
+/// export doesn't say), to the layout of current releases, which is what manifests are parsed
+/// as:
+///
+/// - `documents.comment` objects of releases before paperless-ngx 1.11 become notes
+/// - timestamps are made RFC 3339: paperless-ngx 2.16 and later write `created` as a plain
+///   date, which is taken as midnight UTC and kept as `created_date`, and timestamps without
+///   an offset are taken as UTC
+pub(crate) fn upgrade_object(version: Option<ExporterVersion>, object: &mut Value) {
+    let before = |release| version.is_none_or(|version| version < release);
+    if before(ExporterVersion::NOTES) && object["model"] == "documents.comment" {
+        object["model"] = "documents.note".into();
+        if let Some(fields) = object["fields"].as_object_mut() {
+            if let Some(comment) = fields.remove("comment") {
+                fields.insert("note".to_owned(), comment);
+            }
+        }
+    }
+
+    let is_document = object["model"] == "documents.document";
+    let timestamps: &[&str] = match object["model"].as_str() {
+        Some("documents.document") => &["created", "added", "modified", "deleted_at"],
+        Some("documents.note") => &["created", "deleted_at"],
+        _ => return,
+    };
+    let Some(fields) = object["fields"].as_object_mut() else {
+        return;
+    };
+    if is_document && !fields.contains_key("created_date") {
+        let date = fields
+            .get("created")
+            .and_then(Value::as_str)
+            .filter(|created| NaiveDate::parse_from_str(created, "%Y-%m-%d").is_ok())
+            .map(str::to_owned);
+        if let Some(date) = date {
+            fields.insert("created_date".to_owned(), date.into());
+        }
+    }
+    for name in timestamps {
+        let Some(value) = fields.get_mut(*name) else {
+            continue;
+        };
+        let Some(timestamp) = value.as_str() else {
+            continue;
+        };
+        if DateTime::parse_from_rfc3339(timestamp).is_err() {
+            if let Some(timestamp) = parse_timestamp(timestamp) {
+                *value = timestamp.to_rfc3339().into();
+            }
+        }
+    }
+}
+
+/// Parses `value` as RFC 3339, or as a plain date or timestamp without an offset in UTC.
+pub(crate) fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .into_iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .map(|timestamp| timestamp.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn old_objects_are_upgraded_to_the_current_layout() {
+        let mut comment = json!({"model": "documents.comment", "pk": 1, "fields": {
+            "document": 1, "comment": "paid", "created": "2022-01-02 10:00:00"}});
+        upgrade_object(Some(ExporterVersion::new(1, 10, 2)), &mut comment);
+        assert_eq!(
+            comment,
+            json!({"model": "documents.note", "pk": 1, "fields": {
+                "document": 1, "note": "paid", "created": "2022-01-02T10:00:00+00:00"}})
+        );
+
+        let mut document = json!({"model": "documents.document", "pk": 1, "fields": {
+            "title": "Invoice", "created": "2025-06-01", "added": "2025-06-02T08:00:00.5"}});
+        upgrade_object(Some(ExporterVersion::new(2, 16, 0)), &mut document);
+        assert_eq!(
+            document["fields"],
+            json!({"title": "Invoice", "created": "2025-06-01T00:00:00+00:00",
+                   "created_date": "2025-06-01", "added": "2025-06-02T08:00:00.500+00:00"})
+        );
+    }
+
+    #[test]
+    fn current_objects_are_left_as_they_are() {
+        let objects = [
+            json!({"model": "documents.document", "pk": 1, "fields": {
+                "title": "Invoice", "created": "2024-03-01T12:00:00+01:00",
+                "created_date": "2024-03-01", "deleted_at": null}}),
+            json!({"model": "documents.note", "pk": 1, "fields": {
+                "document": 1, "note": "paid", "created": "2024-03-02T10:00:00Z"}}),
+            json!({"model": "documents.tag", "pk": 1, "fields": {"name": "2024-03-01"}}),
+        ];
+        for object in objects {
+            let mut upgraded = object.clone();
+            upgrade_object(Some(NEWEST_TESTED), &mut upgraded);
+            assert_eq!(upgraded, object);
+        }
+        // comments are only notes before 1.11
+        let mut comment = json!({"model": "documents.comment", "pk": 1, "fields": {}});
+        upgrade_object(Some(ExporterVersion::NOTES), &mut comment);
+        assert_eq!(comment["model"], "documents.comment");
+    }
+}
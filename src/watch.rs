@@ -0,0 +1,61 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Waits for changes to a file through filesystem notifications.
+///
+/// The file's directory is watched rather than the file itself, as exporters replace the
+/// manifest instead of writing to it in place.
+pub struct FileWatcher {
+    file: PathBuf,
+    events: Receiver<notify::Result<Event>>,
+    _watcher: RecommendedWatcher,
+}
+
+impl FileWatcher {
+    pub fn new(file: &Path) -> anyhow::Result<Self> {
+        let file = std::path::absolute(file)
+            .with_context(|| format!("failed to resolve {}", file.display()))?;
+        let dir = file.parent().unwrap_or(Path::new("/"));
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("failed to watch {}", dir.display()))?;
+        Ok(Self {
+            file,
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Blocks until the file was modified and nothing else in its directory changed for
+    /// `settle`, so a pass doesn't start while the exporter is still writing.
+    pub fn wait(&self, settle: Duration) -> anyhow::Result<()> {
+        loop {
+            let event = self.events.recv().context("file watcher stopped")??;
+            let modifies = !matches!(event.kind, EventKind::Access(_));
+            if modifies && event.paths.iter().any(|path| path == &self.file) {
+                break;
+            }
+        }
+        loop {
+            match self.events.recv_timeout(settle) {
+                Ok(event) => {
+                    event?;
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => bail!("file watcher stopped"),
+            }
+        }
+    }
+}
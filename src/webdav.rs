@@ -0,0 +1,414 @@
+use std::{
+    collections::HashSet,
+    env,
+    fmt::Write as _,
+    fs::File,
+    io::{self, Cursor, Read},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::DateTime;
+use regex::Regex;
+use tracing::trace;
+use ureq::SendBody;
+
+use crate::checksum;
+
+/// Files larger than this are uploaded in chunks of this size to Nextcloud, which otherwise
+/// often runs into the upload limits of PHP or a reverse proxy.
+pub const CHUNK_SIZE: u64 = 10 * 1024 * 1024;
+
+const PROPFIND: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getcontentlength/><d:getlastmodified/></d:prop></d:propfind>"#;
+
+/// A file or folder below the root of a [`WebDavClient`].
+pub struct Entry {
+    /// Relative to the root, `/` separated
+    pub path: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// A minimal WebDAV client for a folder on a server such as Nextcloud, ownCloud or Apache's
+/// `mod_dav`.
+///
+/// Credentials are taken from the URL (`https://alice@cloud.example.org/...`) or the
+/// `WEBDAV_USERNAME` environment variable, the password from `WEBDAV_PASSWORD`, e.g. an app
+/// password for Nextcloud.
+pub struct WebDavClient {
+    agent: ureq::Agent,
+    /// The folder, without credentials and trailing slash
+    url: String,
+    /// The path part of `url`, decoded
+    path: String,
+    authorization: Option<String>,
+    /// Nextcloud's endpoint for chunked uploads, for folders below `remote.php/dav/files/`
+    uploads_url: Option<String>,
+    /// Folders known to exist, so uploads don't have to create their parents every time
+    dirs: Mutex<HashSet<String>>,
+}
+
+impl WebDavClient {
+    /// A client for the folder at the `http(s)://` `url`.
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| anyhow!("invalid WebDAV URL `{url}`"))?;
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(decode(user)), host),
+            None => (None, authority),
+        };
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+        let authorization = user.or_else(|| var("WEBDAV_USERNAME")).map(|user| {
+            let password = var("WEBDAV_PASSWORD").unwrap_or_default();
+            format!("Basic {}", STANDARD.encode(format!("{user}:{password}")))
+        });
+
+        let path = format!("/{}", path.trim_end_matches('/'));
+        let origin = format!("{scheme}://{host}");
+        let uploads_url = decode(&path)
+            .strip_prefix("/remote.php/dav/files/")
+            .and_then(|rest| rest.split('/').next())
+            .map(|user| format!("{origin}/remote.php/dav/uploads/{}", encode(user)));
+        Ok(WebDavClient {
+            agent: ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .allow_non_standard_methods(true)
+                .build()
+                .into(),
+            url: format!("{origin}{}", path.trim_end_matches('/')),
+            path: decode(path.trim_end_matches('/')),
+            authorization,
+            uploads_url,
+            dirs: Mutex::default(),
+        })
+    }
+
+    /// Everything below the root folder, which is created if it doesn't exist yet.
+    pub fn list(&self) -> anyhow::Result<Vec<Entry>> {
+        let (status, _) = self.request("PROPFIND", &self.url, &[("Depth", "0")], PROPFIND)?;
+        if status == 404 {
+            self.create_dir("")?;
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        let mut folders = vec![String::new()];
+        while let Some(folder) = folders.pop() {
+            let url = self.url_of(&folder);
+            let (_, body) =
+                self.request("PROPFIND", &format!("{url}/"), &[("Depth", "1")], PROPFIND)?;
+            for entry in self.entries(&body) {
+                // the folder itself is listed as well
+                if entry.path == folder {
+                    continue;
+                }
+                if entry.is_dir {
+                    folders.push(entry.path.clone());
+                    self.known_dir(&entry.path);
+                }
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Creates the folder `path`; its parent has to exist.
+    pub fn create_dir(&self, path: &str) -> anyhow::Result<()> {
+        let (status, body) = self.request("MKCOL", &self.url_of(path), &[], "")?;
+        // 405: it exists already
+        if status >= 300 && status != 405 {
+            bail!(
+                "failed to create {}: {}",
+                self.url_of(path),
+                reason(status, &body)
+            );
+        }
+        self.known_dir(path);
+        Ok(())
+    }
+
+    /// Creates the folder `path` and its parents, unless they are known to exist.
+    pub fn create_dir_all(&self, path: &str) -> anyhow::Result<()> {
+        if path.is_empty()
+            || self
+                .dirs
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .contains(path)
+        {
+            return Ok(());
+        }
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            self.create_dir_all(parent)?;
+        }
+        self.create_dir(path)
+    }
+
+    /// Uploads the file at `file` to `path`, keeping its modification time where the server
+    /// supports it. Returns the number of bytes uploaded.
+    pub fn upload(&self, path: &str, file: &Path) -> anyhow::Result<u64> {
+        let modified = file.metadata()?.modified().ok();
+        self.put(path, &mut File::open(file)?, modified)
+    }
+
+    /// Uploads what `contents` yields to `path`, with the modification time `modified` where
+    /// the server supports it. Missing parent folders are created. Returns the number of
+    /// bytes uploaded.
+    pub fn put(
+        &self,
+        path: &str,
+        contents: &mut dyn Read,
+        modified: Option<SystemTime>,
+    ) -> anyhow::Result<u64> {
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            self.create_dir_all(parent)?;
+        }
+        let mtime = modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|time| time.as_secs().to_string());
+        let url = self.url_of(path);
+        let mut headers: Vec<_> = mtime.iter().map(|m| ("X-OC-Mtime", m.as_str())).collect();
+        let mut chunk = Vec::new();
+        (&mut *contents).take(CHUNK_SIZE).read_to_end(&mut chunk)?;
+        let mut next = Vec::new();
+        (&mut *contents).take(CHUNK_SIZE).read_to_end(&mut next)?;
+
+        let (len, (status, body)) = match &self.uploads_url {
+            _ if next.is_empty() => (
+                chunk.len() as u64,
+                self.request("PUT", &url, &headers, chunk)?,
+            ),
+            Some(uploads_url) => {
+                let id = format!(
+                    "paperless-{}",
+                    checksum::short_hash(&format!("{url}{mtime:?}"))
+                );
+                let upload = format!("{uploads_url}/{id}");
+                let destination = [("Destination", url.as_str())];
+                let (status, body) = self.request("MKCOL", &upload, &destination, "")?;
+                if status >= 300 {
+                    bail!("failed to start uploading {url}: {}", reason(status, &body));
+                }
+                let mut len = 0;
+                // chunks are numbered from 1 and sorted as strings
+                for number in 1.. {
+                    if chunk.is_empty() {
+                        break;
+                    }
+                    len += chunk.len() as u64;
+                    let chunk_url = format!("{upload}/{number:05}");
+                    let (status, body) = self.request("PUT", &chunk_url, &destination, chunk)?;
+                    if status >= 300 {
+                        let _ = self.request("DELETE", &upload, &[], "");
+                        bail!("failed to upload {chunk_url}: {}", reason(status, &body));
+                    }
+                    chunk = std::mem::take(&mut next);
+                    (&mut *contents).take(CHUNK_SIZE).read_to_end(&mut next)?;
+                }
+                let total = len.to_string();
+                headers.extend([("Destination", url.as_str()), ("OC-Total-Length", &total)]);
+                (
+                    len,
+                    self.request("MOVE", &format!("{upload}/.file"), &headers, "")?,
+                )
+            }
+            // streamed, as the size isn't known up front
+            None => {
+                let mut counted = Counted {
+                    inner: Cursor::new(chunk).chain(Cursor::new(next)).chain(contents),
+                    bytes: 0,
+                };
+                let response =
+                    self.request("PUT", &url, &headers, SendBody::from_reader(&mut counted))?;
+                (counted.bytes, response)
+            }
+        };
+        if status >= 300 {
+            bail!("failed to upload {url}: {}", reason(status, &body));
+        }
+        Ok(len)
+    }
+
+    /// Deletes the file or folder `path`, with everything in it.
+    pub fn delete(&self, path: &str) -> anyhow::Result<()> {
+        let (status, body) = self.request("DELETE", &self.url_of(path), &[], "")?;
+        if status >= 300 && status != 404 {
+            bail!(
+                "failed to delete {}: {}",
+                self.url_of(path),
+                reason(status, &body)
+            );
+        }
+        self.dirs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|dir| dir != path && !dir.starts_with(&format!("{path}/")));
+        Ok(())
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn known_dir(&self, path: &str) {
+        self.dirs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(path.to_owned());
+    }
+
+    fn url_of(&self, path: &str) -> String {
+        match path {
+            "" => self.url.clone(),
+            path => format!("{}/{}", self.url, encode(path)),
+        }
+    }
+
+    /// The entries of a PROPFIND response below the root.
+    fn entries(&self, body: &str) -> Vec<Entry> {
+        let response =
+            Regex::new(r"(?s)<(?:[\w-]+:)?response\b[^>]*>(.*?)</(?:[\w-]+:)?response>").unwrap();
+        let collection = Regex::new(r"<(?:[\w-]+:)?collection\b").unwrap();
+        response
+            .captures_iter(body)
+            .filter_map(|response| {
+                let response = response.get(1)?.as_str();
+                let href = decode(&property(response, "href")?);
+                // either a path or a full URL
+                let href = match href.split_once("://") {
+                    Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]).to_owned(),
+                    None => href,
+                };
+                let path = href
+                    .trim_end_matches('/')
+                    .strip_prefix(&self.path)?
+                    .trim_start_matches('/')
+                    .to_owned();
+                Some(Entry {
+                    path,
+                    is_dir: collection.is_match(response),
+                    size: property(response, "getcontentlength")
+                        .and_then(|len| len.trim().parse().ok())
+                        .unwrap_or_default(),
+                    modified: property(response, "getlastmodified")
+                        .and_then(|time| DateTime::parse_from_rfc2822(time.trim()).ok())
+                        .map(SystemTime::from),
+                })
+            })
+            .collect()
+    }
+
+    /// Sends a request and returns the status and body of the response.
+    fn request(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: impl ureq::AsSendBody,
+    ) -> anyhow::Result<(u16, String)> {
+        trace!("{method} {url}");
+        let mut request = ureq::http::Request::builder().method(method).uri(url);
+        if let Some(authorization) = &self.authorization {
+            request = request.header("Authorization", authorization);
+        }
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let mut response = self
+            .agent
+            .run(request.body(body)?)
+            .with_context(|| format!("request to {url} failed"))?;
+        let status = response.status().as_u16();
+        if status == 401 {
+            bail!("{url} refused the credentials, check WEBDAV_USERNAME and WEBDAV_PASSWORD");
+        }
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(u64::MAX)
+            .read_to_string()
+            .unwrap_or_default();
+        Ok((status, body))
+    }
+}
+
+/// Counts the bytes read through it.
+struct Counted<R> {
+    inner: R,
+    bytes: u64,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+/// The text of the first `name` element in `xml`, in any namespace.
+fn property(xml: &str, name: &str) -> Option<String> {
+    let pattern = format!(r"(?s)<(?:[\w-]+:)?{name}\b[^>]*>(.*?)</");
+    let text = Regex::new(&pattern).ok()?.captures(xml)?.get(1)?.as_str();
+    Some(
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// The message of an error response, or just its status.
+fn reason(status: u16, body: &str) -> String {
+    match property(body, "message") {
+        Some(message) if !message.trim().is_empty() => format!("{} ({status})", message.trim()),
+        _ => format!("status {status}"),
+    }
+}
+
+/// Percent-encodes every byte of `path` but unreserved characters and `/`.
+fn encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}